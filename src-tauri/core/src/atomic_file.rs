@@ -0,0 +1,60 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Replaces `path` with `contents` so readers, and the file after a crash,
+// see either the old or the new contents in full. The data goes to a
+// temporary file next to `path`, which is then renamed over it; an existing
+// file's permissions carry over.
+pub(crate) fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let temporary = temporary_path(path)?;
+    let result = write_and_rename(path, &temporary, contents.as_ref());
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+fn write_and_rename(path: &Path, temporary: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temporary)?;
+    file.write_all(contents)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()?;
+    drop(file);
+    fs::rename(temporary, path)
+}
+
+// `.name.<pid>.tmp` in the same directory, so the rename stays on one file
+// system
+fn temporary_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path does not name a file"))?;
+    let mut temporary = std::ffi::OsString::from(".");
+    temporary.push(name);
+    temporary.push(format!(".{}.tmp", std::process::id()));
+    Ok(path.with_file_name(temporary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_files_without_leaving_temporaries() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let file = dir.path().join("photo.jpg.xmp");
+
+        write_atomically(&file, "first").expect("create");
+        write_atomically(&file, "second").expect("replace");
+
+        assert_eq!(fs::read_to_string(&file).expect("read"), "second");
+        assert_eq!(fs::read_dir(dir.path()).expect("list").count(), 1);
+        assert!(write_atomically(&dir.path().join("missing/file"), "data").is_err());
+    }
+}
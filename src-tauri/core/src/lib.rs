@@ -0,0 +1,72 @@
+mod atomic_file;
+pub mod path_codec;
+pub mod path_matching;
+pub mod saved_searches;
+pub mod scan;
+pub mod settings;
+pub mod sql_console;
+pub mod storage;
+mod store;
+pub mod tag_manifest;
+mod tag_query;
+pub mod tag_transfer;
+pub mod tagging;
+pub mod tmsu_import;
+pub mod xattr_sync;
+mod xdg_tags;
+pub mod xmp_sidecar;
+
+use serde::Serialize;
+use std::sync::Mutex;
+use thiserror::Error;
+
+pub use storage::{Database, StorageBackend, TagStorage};
+pub use store::TagStore;
+
+// A connection shared between threads, such as the desktop app's command
+// handlers. It is empty until the database has been opened.
+#[derive(Default)]
+pub struct DbConnection {
+    pub db: Mutex<Option<Database>>,
+}
+
+// Why the shared database could not be used at all. The error types of the
+// operations run through a `DbConnection` wrap it.
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "type", content = "message")]
+pub enum AccessError {
+    #[error("Failed to acquire database connection: {0}")]
+    Connection(String),
+
+    #[error("Database connection is not available")]
+    ConnectionUnavailable,
+
+    #[error("This needs the DuckDB storage backend")]
+    RequiresDuckDb,
+}
+
+impl DbConnection {
+    // Runs `operation` on the open database, whichever backend it uses
+    pub fn with_database<T, E: From<AccessError>>(
+        &self,
+        operation: impl FnOnce(&mut Database) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut guard = self
+            .db
+            .lock()
+            .map_err(|err| AccessError::Connection(err.to_string()))?;
+        let database = guard.as_mut().ok_or(AccessError::ConnectionUnavailable)?;
+
+        operation(database)
+    }
+
+    // Runs `operation` on the open connection, for features only DuckDB supports
+    pub fn with_duckdb<T, E: From<AccessError>>(
+        &self,
+        operation: impl FnOnce(&mut duckdb::Connection) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.with_database(|database| {
+            operation(database.duckdb().ok_or(AccessError::RequiresDuckDb)?)
+        })
+    }
+}
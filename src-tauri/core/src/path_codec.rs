@@ -0,0 +1,174 @@
+use serde::Serializer;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+// Paths cross IPC and the database as strings. Valid Unicode is kept
+// verbatim, so ordinary paths look the same everywhere and prefix matching on
+// the string still follows the directory structure. Anything else is escaped
+// behind U+FFFD: a raw byte that is not UTF-8 becomes `\u{FFFD}XX` on Unix, an
+// unpaired UTF-16 surrogate becomes `\u{FFFD}XXXX` on Windows, and a literal
+// U+FFFD is doubled. The result still reads sensibly in the UI.
+const ESCAPE: char = '\u{FFFD}';
+
+pub(crate) fn encode_path(path: &Path) -> String {
+    encode_os_str(path.as_os_str())
+}
+
+#[cfg(unix)]
+pub(crate) fn encode_os_str(value: &OsStr) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::with_capacity(value.len());
+    for chunk in value.as_bytes().utf8_chunks() {
+        push_verbatim(&mut encoded, chunk.valid());
+        for byte in chunk.invalid() {
+            let _ = write!(encoded, "{ESCAPE}{byte:02X}");
+        }
+    }
+    encoded
+}
+
+#[cfg(windows)]
+pub(crate) fn encode_os_str(value: &OsStr) -> String {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut encoded = String::with_capacity(value.len());
+    for decoded in char::decode_utf16(value.encode_wide()) {
+        match decoded {
+            Ok(ch) => push_verbatim(&mut encoded, ch.encode_utf8(&mut [0; 4])),
+            Err(err) => {
+                let _ = write!(encoded, "{ESCAPE}{:04X}", err.unpaired_surrogate());
+            }
+        }
+    }
+    encoded
+}
+
+fn push_verbatim(encoded: &mut String, valid: &str) {
+    for ch in valid.chars() {
+        if ch == ESCAPE {
+            encoded.push(ESCAPE);
+        }
+        encoded.push(ch);
+    }
+}
+
+// Inverse of `encode_path`. A U+FFFD that does not start an escape, e.g. in
+// a path stored before escaping existed, is taken literally.
+pub fn decode_path(encoded: &str) -> PathBuf {
+    let mut units = Vec::with_capacity(encoded.len());
+    let mut rest = encoded;
+
+    while let Some(position) = rest.find(ESCAPE) {
+        push_units(&mut units, &rest[..position]);
+        rest = &rest[position + ESCAPE.len_utf8()..];
+
+        if let Some(after) = rest.strip_prefix(ESCAPE) {
+            push_units(&mut units, ESCAPE.encode_utf8(&mut [0; 4]));
+            rest = after;
+        } else if let Some(unit) = escaped_unit(rest) {
+            units.push(unit);
+            rest = &rest[ESCAPED_DIGITS..];
+        } else {
+            push_units(&mut units, ESCAPE.encode_utf8(&mut [0; 4]));
+        }
+    }
+    push_units(&mut units, rest);
+
+    path_from_units(units)
+}
+
+#[cfg(unix)]
+const ESCAPED_DIGITS: usize = 2;
+#[cfg(windows)]
+const ESCAPED_DIGITS: usize = 4;
+
+#[cfg(unix)]
+type Unit = u8;
+#[cfg(windows)]
+type Unit = u16;
+
+fn escaped_unit(rest: &str) -> Option<Unit> {
+    let digits = rest.get(..ESCAPED_DIGITS)?;
+    if !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    Unit::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(unix)]
+fn push_units(units: &mut Vec<u8>, text: &str) {
+    units.extend_from_slice(text.as_bytes());
+}
+
+#[cfg(windows)]
+fn push_units(units: &mut Vec<u16>, text: &str) {
+    units.extend(text.encode_utf16());
+}
+
+#[cfg(unix)]
+fn path_from_units(units: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    PathBuf::from(std::ffi::OsString::from_vec(units))
+}
+
+#[cfg(windows)]
+fn path_from_units(units: Vec<u16>) -> PathBuf {
+    use std::os::windows::ffi::OsStringExt;
+
+    PathBuf::from(std::ffi::OsString::from_wide(&units))
+}
+
+// For `#[serde(serialize_with)]`; serde's own impl fails on non-Unicode paths
+pub(crate) fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&encode_path(path))
+}
+
+pub(crate) fn serialize_optional_path<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serializer.serialize_some(&encode_path(path)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn keeps_unicode_paths_verbatim() {
+        let path = Path::new("/home/zoë/写真/a b.txt");
+        assert_eq!(encode_path(path), "/home/zoë/写真/a b.txt");
+        assert_eq!(decode_path("/home/zoë/写真/a b.txt"), path);
+    }
+
+    #[test]
+    fn round_trips_non_utf8_paths() {
+        let path = Path::new(OsStr::from_bytes(b"/data/caf\xe9/\xff\xfe.txt"));
+        let encoded = encode_path(path);
+        assert_eq!(encoded, "/data/caf\u{FFFD}E9/\u{FFFD}FF\u{FFFD}FE.txt");
+        assert_eq!(decode_path(&encoded), path);
+    }
+
+    #[test]
+    fn distinguishes_literal_replacement_characters_from_escapes() {
+        let literal = Path::new("/data/\u{FFFD}E9");
+        let raw = Path::new(OsStr::from_bytes(b"/data/\xe9"));
+        assert_ne!(encode_path(literal), encode_path(raw));
+        assert_eq!(decode_path(&encode_path(literal)), literal);
+        assert_eq!(decode_path(&encode_path(raw)), raw);
+
+        // Strings that were never escaped decode as they read
+        assert_eq!(
+            decode_path("/data/\u{FFFD}.txt"),
+            Path::new("/data/\u{FFFD}.txt")
+        );
+        assert_eq!(decode_path("/data/\u{FFFD}"), Path::new("/data/\u{FFFD}"));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::path_codec::{decode_path, encode_path};
+use crate::storage::TagStorage;
+use crate::tagging::{normalize_path, TaggingError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+// How paths below a root are compared when looking up tags. Folding only
+// affects lookups; tags keep the spelling they were first stored under.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathMatching {
+    // For case-insensitive volumes, where `Photo.JPG` and `photo.jpg` are one file
+    pub case_insensitive: bool,
+    // Treats NFC and NFD spellings as equal, e.g. names copied from macOS
+    pub unicode_normalized: bool,
+}
+
+impl PathMatching {
+    pub(crate) const EXACT: Self = Self {
+        case_insensitive: false,
+        unicode_normalized: false,
+    };
+
+    // Every mode is at most this loose, so it can narrow down candidates for all of them
+    const LOOSEST: Self = Self {
+        case_insensitive: true,
+        unicode_normalized: true,
+    };
+
+    fn fold(&self, encoded: &str) -> String {
+        let mut folded = encoded.to_string();
+        if self.case_insensitive {
+            folded = folded.to_lowercase();
+        }
+        if self.unicode_normalized {
+            folded = folded.nfc().collect();
+        }
+        folded
+    }
+
+    // DuckDB expression folding `operand` the way `fold` does
+    pub(crate) fn fold_sql(&self, operand: &str) -> String {
+        let mut folded = operand.to_string();
+        if self.case_insensitive {
+            folded = format!("lower({folded})");
+        }
+        if self.unicode_normalized {
+            folded = format!("nfc_normalize({folded})");
+        }
+        folded
+    }
+
+    fn fold_path(&self, path: &Path) -> PathBuf {
+        PathBuf::from(self.fold(&encode_path(path)))
+    }
+
+    pub(crate) fn same_path(&self, a: &Path, b: &Path) -> bool {
+        a == b || (*self != Self::EXACT && self.fold_path(a) == self.fold_path(b))
+    }
+
+    // Whether `path` is `root` or lies below it
+    pub(crate) fn is_within(&self, path: &Path, root: &Path) -> bool {
+        path.starts_with(root)
+            || (*self != Self::EXACT && self.fold_path(path).starts_with(self.fold_path(root)))
+    }
+}
+
+// Groups paths that some mode might consider equal
+pub(crate) fn loose_key(path: &Path) -> PathBuf {
+    PathMatching::LOOSEST.fold_path(path)
+}
+
+// `loose_key` for a stored path, as storage backends compare them
+pub(crate) fn loose_fold(encoded: &str) -> String {
+    PathMatching::LOOSEST.fold(encoded)
+}
+
+// Configured roots, deepest first so nested roots override their parents
+#[derive(Debug, Clone, Default)]
+pub struct PathMatchingRules {
+    roots: Vec<(PathBuf, PathMatching)>,
+}
+
+impl PathMatchingRules {
+    pub(crate) fn load(connection: &duckdb::Connection) -> Result<Self, TaggingError> {
+        let mut statement = connection
+            .prepare("SELECT root, case_insensitive, unicode_normalized FROM path_matching")
+            .map_err(|err| TaggingError::Database(err.to_string()))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    decode_path(&row.get::<_, String>(0)?),
+                    PathMatching {
+                        case_insensitive: row.get(1)?,
+                        unicode_normalized: row.get(2)?,
+                    },
+                ))
+            })
+            .map_err(|err| TaggingError::Database(err.to_string()))?;
+
+        let roots = rows
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| TaggingError::Database(err.to_string()))?;
+        Ok(Self::from_roots(roots))
+    }
+
+    pub(crate) fn from_roots(mut roots: Vec<(PathBuf, PathMatching)>) -> Self {
+        roots.sort_by_key(|(root, _)| std::cmp::Reverse(root.components().count()));
+        Self { roots }
+    }
+
+    pub(crate) fn roots(&self) -> &[(PathBuf, PathMatching)] {
+        &self.roots
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    // Column holding the stored path. Once any root folds, queries compare the
+    // indexed, loosely folded `folded_path` and callers filter the candidates
+    // with `for_path`.
+    pub(crate) fn column(&self) -> &'static str {
+        if self.is_empty() {
+            "path"
+        } else {
+            "folded_path"
+        }
+    }
+
+    // Matching SQL operand for a bound parameter such as `?1`
+    pub(crate) fn parameter(&self, placeholder: &str) -> String {
+        if self.is_empty() {
+            placeholder.to_string()
+        } else {
+            PathMatching::LOOSEST.fold_sql(placeholder)
+        }
+    }
+
+    pub(crate) fn for_path(&self, path: &Path) -> PathMatching {
+        self.roots
+            .iter()
+            .find(|(root, matching)| matching.is_within(path, root))
+            .map(|(_, matching)| *matching)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathMatchingRoot {
+    #[serde(serialize_with = "crate::path_codec::serialize_path")]
+    root: PathBuf,
+    matching: PathMatching,
+}
+
+// Sets how paths below `root` are matched; exact matching removes the entry
+pub fn set_path_matching(
+    storage: &(impl TagStorage + ?Sized),
+    root: &Path,
+    matching: PathMatching,
+) -> Result<(), TaggingError> {
+    storage.set_path_matching(&encode_path(&normalize_path(root)?), matching)
+}
+
+pub fn list_path_matching(
+    storage: &(impl TagStorage + ?Sized),
+) -> Result<Vec<PathMatchingRoot>, TaggingError> {
+    Ok(storage
+        .path_matching_rules()?
+        .roots
+        .into_iter()
+        .map(|(root, matching)| PathMatchingRoot { root, matching })
+        .collect())
+}
+
+pub(crate) fn store_path_matching(
+    connection: &duckdb::Connection,
+    root: &str,
+    matching: PathMatching,
+) -> Result<(), TaggingError> {
+    let result = if matching == PathMatching::EXACT {
+        connection.execute(
+            "DELETE FROM path_matching WHERE root = ?1",
+            duckdb::params![root],
+        )
+    } else {
+        connection.execute(
+            "INSERT OR REPLACE INTO path_matching (root, case_insensitive, unicode_normalized)
+             VALUES (?1, ?2, ?3)",
+            duckdb::params![root, matching.case_insensitive, matching.unicode_normalized],
+        )
+    };
+
+    result
+        .map(|_| ())
+        .map_err(|err| TaggingError::Database(err.to_string()))
+}
+
+pub(crate) fn ensure_schema(connection: &duckdb::Connection) -> Result<(), TaggingError> {
+    connection
+        .execute(
+            "
+            CREATE TABLE IF NOT EXISTS path_matching (
+                root TEXT PRIMARY KEY,
+                case_insensitive BOOLEAN NOT NULL,
+                unicode_normalized BOOLEAN NOT NULL
+            )
+            ",
+            [],
+        )
+        .map_err(|err| TaggingError::Database(err.to_string()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_case_and_unicode_form_only_when_enabled() {
+        let nfc = Path::new("/photos/Caf\u{e9}/IMG.JPG");
+        let nfd = Path::new("/photos/Cafe\u{301}/img.jpg");
+
+        assert!(!PathMatching::EXACT.same_path(nfc, nfd));
+        let case_only = PathMatching {
+            case_insensitive: true,
+            ..PathMatching::default()
+        };
+        assert!(!case_only.same_path(nfc, nfd));
+        assert!(case_only.same_path(nfc, Path::new("/PHOTOS/CAF\u{c9}/img.jpg")));
+        assert!(PathMatching::LOOSEST.same_path(nfc, nfd));
+        assert!(PathMatching::LOOSEST.is_within(nfd, Path::new("/Photos/caf\u{e9}")));
+        assert!(!PathMatching::LOOSEST.is_within(nfd, Path::new("/Photos/caf")));
+    }
+
+    #[test]
+    fn nested_roots_override_their_parents() {
+        for database in crate::storage::tests::every_backend() {
+            let storage = database.storage();
+            storage
+                .set_path_matching("/volume", PathMatching::LOOSEST)
+                .expect("store");
+            storage
+                .set_path_matching(
+                    "/volume/strict",
+                    PathMatching {
+                        unicode_normalized: true,
+                        ..PathMatching::default()
+                    },
+                )
+                .expect("store");
+            storage
+                .set_path_matching("/removed", PathMatching::LOOSEST)
+                .expect("store");
+            storage
+                .set_path_matching("/removed", PathMatching::EXACT)
+                .expect("remove");
+
+            let rules = storage.path_matching_rules().expect("load");
+            assert_eq!(
+                rules.for_path(Path::new("/VOLUME/a.txt")),
+                PathMatching::LOOSEST
+            );
+            assert!(
+                !rules
+                    .for_path(Path::new("/volume/strict/a.txt"))
+                    .case_insensitive
+            );
+            assert_eq!(
+                rules.for_path(Path::new("/removed/a.txt")),
+                PathMatching::EXACT
+            );
+        }
+    }
+}
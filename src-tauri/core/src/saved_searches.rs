@@ -0,0 +1,360 @@
+use crate::path_codec::decode_path;
+use crate::scan::{build_virtual_tree, DirectoryNode, SortOptions};
+use crate::tag_query::TagQuery;
+use crate::tagging::get_tags_for_paths;
+use crate::AccessError;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "type", content = "message")]
+pub enum SavedSearchError {
+    #[error("Saved search name must not be empty")]
+    EmptyName,
+
+    #[error("Invalid tag query: {0}")]
+    InvalidQuery(String),
+
+    #[error("No saved search named {0:?}")]
+    NotFound(String),
+
+    #[error("A saved search named {0:?} already exists")]
+    AlreadyExists(String),
+
+    #[error(transparent)]
+    Access(#[from] AccessError),
+
+    #[error("Database error: {0}")]
+    Database(String),
+
+    #[error("Failed to read matching entries: {0}")]
+    Scan(String),
+}
+
+// A named tag query, shown in the sidebar as a smart folder
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SavedSearch {
+    name: String,
+    query: String,
+}
+
+impl SavedSearch {
+    fn new(name: &str, query: &str) -> Result<Self, SavedSearchError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(SavedSearchError::EmptyName);
+        }
+        let query = query.trim();
+        TagQuery::parse(query).map_err(SavedSearchError::InvalidQuery)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            query: query.to_string(),
+        })
+    }
+}
+
+pub fn create_saved_search(
+    connection: &duckdb::Connection,
+    name: &str,
+    query: &str,
+) -> Result<SavedSearch, SavedSearchError> {
+    let search = SavedSearch::new(name, query)?;
+    if load_saved_search(connection, &search.name)?.is_some() {
+        return Err(SavedSearchError::AlreadyExists(search.name.clone()));
+    }
+    insert_saved_search(connection, &search)?;
+    Ok(search)
+}
+
+pub fn list_saved_searches(
+    connection: &duckdb::Connection,
+) -> Result<Vec<SavedSearch>, SavedSearchError> {
+    list(connection)
+}
+
+// Renames a saved search and/or replaces its query; omitted fields are kept
+pub fn update_saved_search(
+    connection: &mut duckdb::Connection,
+    name: &str,
+    new_name: Option<&str>,
+    query: Option<&str>,
+) -> Result<SavedSearch, SavedSearchError> {
+    update(connection, name.trim(), new_name, query)
+}
+
+pub fn delete_saved_search(
+    connection: &duckdb::Connection,
+    name: &str,
+) -> Result<(), SavedSearchError> {
+    let deleted = connection
+        .execute(
+            "DELETE FROM saved_searches WHERE name = ?1",
+            duckdb::params![name.trim()],
+        )
+        .map_err(database_error)?;
+    if deleted == 0 {
+        return Err(SavedSearchError::NotFound(name.trim().to_string()));
+    }
+    Ok(())
+}
+
+// Runs a saved search against the current tags. Matches are grouped under
+// their real parent directories so the result renders like a scanned tree.
+pub fn evaluate_saved_search(
+    connection: &duckdb::Connection,
+    name: &str,
+    sort: &SortOptions,
+) -> Result<DirectoryNode, SavedSearchError> {
+    evaluate(connection, name.trim(), sort)
+}
+
+fn list(connection: &duckdb::Connection) -> Result<Vec<SavedSearch>, SavedSearchError> {
+    let mut statement = connection
+        .prepare("SELECT name, query FROM saved_searches ORDER BY name")
+        .map_err(database_error)?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(SavedSearch {
+                name: row.get(0)?,
+                query: row.get(1)?,
+            })
+        })
+        .map_err(database_error)?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(database_error)
+}
+
+fn load_saved_search(
+    connection: &duckdb::Connection,
+    name: &str,
+) -> Result<Option<SavedSearch>, SavedSearchError> {
+    let mut statement = connection
+        .prepare("SELECT name, query FROM saved_searches WHERE name = ?1")
+        .map_err(database_error)?;
+    let mut rows = statement
+        .query(duckdb::params![name])
+        .map_err(database_error)?;
+
+    match rows.next().map_err(database_error)? {
+        Some(row) => Ok(Some(SavedSearch {
+            name: row.get(0).map_err(database_error)?,
+            query: row.get(1).map_err(database_error)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+fn insert_saved_search(
+    connection: &duckdb::Connection,
+    search: &SavedSearch,
+) -> Result<(), SavedSearchError> {
+    connection
+        .execute(
+            "INSERT INTO saved_searches (name, query) VALUES (?1, ?2)",
+            duckdb::params![search.name, search.query],
+        )
+        .map(|_| ())
+        .map_err(database_error)
+}
+
+fn update(
+    connection: &mut duckdb::Connection,
+    name: &str,
+    new_name: Option<&str>,
+    query: Option<&str>,
+) -> Result<SavedSearch, SavedSearchError> {
+    let transaction = connection.transaction().map_err(database_error)?;
+    let existing = load_saved_search(&transaction, name)?
+        .ok_or_else(|| SavedSearchError::NotFound(name.to_string()))?;
+    let updated = SavedSearch::new(
+        new_name.unwrap_or(&existing.name),
+        query.unwrap_or(&existing.query),
+    )?;
+    if updated.name != existing.name && load_saved_search(&transaction, &updated.name)?.is_some() {
+        return Err(SavedSearchError::AlreadyExists(updated.name));
+    }
+
+    // The name is the primary key, which DuckDB will not update in place
+    transaction
+        .execute(
+            "DELETE FROM saved_searches WHERE name = ?1",
+            duckdb::params![existing.name],
+        )
+        .map_err(database_error)?;
+    insert_saved_search(&transaction, &updated)?;
+    transaction.commit().map_err(database_error)?;
+    Ok(updated)
+}
+
+fn evaluate(
+    connection: &duckdb::Connection,
+    name: &str,
+    sort: &SortOptions,
+) -> Result<DirectoryNode, SavedSearchError> {
+    let search = load_saved_search(connection, name)?
+        .ok_or_else(|| SavedSearchError::NotFound(name.to_string()))?;
+    let query = TagQuery::parse(&search.query).map_err(SavedSearchError::InvalidQuery)?;
+    let matches = matching_paths(connection, &query)?;
+
+    build_virtual_tree(connection, &search.name, &matches, sort)
+        .map_err(|err| SavedSearchError::Scan(err.to_string()))
+}
+
+// Tagged paths whose own and inherited tags satisfy `query`. Only paths
+// carrying at least one tag of their own are candidates, so a tagged
+// directory matches as a whole rather than listing its contents.
+fn matching_paths(
+    connection: &duckdb::Connection,
+    query: &TagQuery,
+) -> Result<Vec<PathBuf>, SavedSearchError> {
+    let mut statement = connection
+        .prepare("SELECT DISTINCT path FROM path_tags ORDER BY path")
+        .map_err(database_error)?;
+    let tagged = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(database_error)?
+        .map(|path| path.map(|path| decode_path(&path)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(database_error)?;
+
+    let lookup: BTreeSet<PathBuf> = tagged
+        .iter()
+        .flat_map(|path| path.ancestors().map(Path::to_path_buf))
+        .collect();
+    let lookup: Vec<PathBuf> = lookup.into_iter().collect();
+    let tags = get_tags_for_paths(connection, &lookup)
+        .map_err(|err| SavedSearchError::Database(err.to_string()))?;
+
+    Ok(tagged
+        .into_iter()
+        .filter(|path| {
+            let effective: BTreeSet<String> = path
+                .ancestors()
+                .flat_map(|ancestor| tags.get(ancestor).into_iter().flatten().cloned())
+                .collect();
+            query.matches(&effective)
+        })
+        .collect())
+}
+
+pub(crate) fn ensure_schema(connection: &duckdb::Connection) -> Result<(), SavedSearchError> {
+    connection
+        .execute(
+            "
+            CREATE TABLE IF NOT EXISTS saved_searches (
+                name TEXT PRIMARY KEY,
+                query TEXT NOT NULL
+            )
+            ",
+            [],
+        )
+        .map_err(database_error)?;
+
+    Ok(())
+}
+
+fn database_error(err: duckdb::Error) -> SavedSearchError {
+    SavedSearchError::Database(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path_codec::encode_path;
+    use std::fs;
+
+    fn test_connection() -> duckdb::Connection {
+        let connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        crate::tagging::ensure_schema(&connection).expect("tag schema");
+        ensure_schema(&connection).expect("saved search schema");
+        connection
+    }
+
+    #[test]
+    fn creates_renames_and_rejects_invalid_searches() {
+        let mut connection = test_connection();
+        let search = SavedSearch::new(" Open invoices ", "finance AND NOT archived").unwrap();
+        insert_saved_search(&connection, &search).unwrap();
+        assert_eq!(search.name, "Open invoices");
+        assert!(matches!(
+            SavedSearch::new("Broken", "finance AND"),
+            Err(SavedSearchError::InvalidQuery(_))
+        ));
+
+        insert_saved_search(&connection, &SavedSearch::new("Drafts", "draft").unwrap()).unwrap();
+        assert!(matches!(
+            update(&mut connection, "Drafts", Some("Open invoices"), None),
+            Err(SavedSearchError::AlreadyExists(_))
+        ));
+
+        let renamed = update(&mut connection, "Open invoices", Some("Invoices"), None).unwrap();
+        assert_eq!(renamed.query, "finance AND NOT archived");
+        let names: Vec<String> = list(&connection)
+            .unwrap()
+            .into_iter()
+            .map(|search| search.name)
+            .collect();
+        assert_eq!(names, vec!["Drafts", "Invoices"]);
+        assert!(matches!(
+            update(&mut connection, "Missing", None, Some("x")),
+            Err(SavedSearchError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn groups_matches_under_their_parent_directories() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = fs::canonicalize(temp.path()).expect("canonical temp dir");
+        let finance = root.join("finance");
+        let archive = finance.join("2019");
+        fs::create_dir_all(&archive).expect("create dirs");
+        for file in [
+            finance.join("a.pdf"),
+            finance.join("b.pdf"),
+            archive.join("old.pdf"),
+        ] {
+            fs::write(file, "").expect("write");
+        }
+
+        let connection = test_connection();
+        for (path, tag) in [
+            (finance.clone(), "finance"),
+            (archive.clone(), "archived"),
+            (finance.join("a.pdf"), "status=open"),
+            (finance.join("b.pdf"), "status=paid"),
+            (archive.join("old.pdf"), "status=open"),
+            (finance.join("gone.pdf"), "status=open"),
+        ] {
+            connection
+                .execute(
+                    "INSERT INTO path_tags (path, tag, path_depth) VALUES (?1, ?2, 0)",
+                    duckdb::params![encode_path(&path), tag],
+                )
+                .expect("tag");
+        }
+        let search =
+            SavedSearch::new("Open invoices", "finance AND status=open AND NOT archived").unwrap();
+        insert_saved_search(&connection, &search).unwrap();
+
+        let query = TagQuery::parse(&search.query).unwrap();
+        assert_eq!(
+            matching_paths(&connection, &query).unwrap(),
+            vec![finance.join("a.pdf"), finance.join("gone.pdf")]
+        );
+
+        let tree = evaluate(&connection, "Open invoices", &SortOptions::default()).unwrap();
+        let json = serde_json::to_value(&tree).expect("serializable tree");
+        assert_eq!(json["name"], "Open invoices");
+        let groups = json["children"].as_array().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0]["name"], encode_path(&finance));
+        assert_eq!(groups[0]["info"]["own_tags"][0], "finance");
+        let children = groups[0]["children"].as_array().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0]["name"], "a.pdf");
+        assert_eq!(children[0]["info"]["inherited_tags"][0], "finance");
+    }
+}
@@ -0,0 +1,1206 @@
+mod budget;
+mod cache;
+mod facets;
+mod helpers;
+mod ignore_rules;
+mod metadata;
+mod options;
+mod platform;
+mod search;
+mod sort;
+mod stats;
+mod virtual_tree;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::path_codec::{encode_os_str, encode_path, serialize_optional_path, serialize_path};
+use crate::storage::Database;
+use crate::tagging::{
+    get_tags_for_directory, get_tags_for_paths, normalize_lexically, relative_base,
+    DirectoryTagSnapshot, TaggingError,
+};
+use crate::{AccessError, DbConnection};
+use budget::ScanBudget;
+use cache::DirectoryCache;
+use helpers::collect_path_hierarchy;
+use metadata::ExtendedMetadata;
+use options::ScanFilter;
+use stats::DirectoryStats;
+
+pub(crate) use cache::ensure_cache_schema;
+pub use facets::{FacetFilter, FilterResult};
+pub(crate) use ignore_rules::IgnoreRules;
+pub use options::ScanOptions;
+pub use search::SearchOptions;
+pub use sort::SortOptions;
+pub(crate) use virtual_tree::build_virtual_tree;
+
+// Custom error type for directory scanning operations
+#[derive(Error, Debug, Serialize)]
+#[serde(tag = "type", content = "message")]
+pub enum ScanError {
+    #[error("Failed to get current directory: {0}")]
+    CurrentDir(String),
+
+    #[error("Root path not found in scan results: {0:?}")]
+    #[serde(serialize_with = "serialize_path")]
+    MissingRoot(PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(String),
+
+    #[error("Database error: {0}")]
+    Database(String),
+
+    #[error(transparent)]
+    Access(#[from] AccessError),
+
+    #[error("Invalid scan options: {0}")]
+    InvalidOptions(String),
+
+    #[error("Invalid scan path: {0}")]
+    InvalidPath(String),
+}
+
+// Where an entry's metadata came from during the last scan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Freshness {
+    // Read from disk by this scan
+    #[default]
+    Live,
+    // Served from the scan cache because the parent directory's mtime was unchanged;
+    // in-place edits to the file itself may not be reflected yet
+    Cached,
+}
+
+// File/Directory information structure
+#[derive(Serialize, Clone)]
+pub struct FileInfo {
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) path: PathBuf,
+    pub(crate) is_directory: bool,
+    is_symlink: bool,
+    #[serde(serialize_with = "serialize_optional_path")]
+    symlink_target: Option<PathBuf>,
+    is_broken_symlink: bool,
+    size: u64,
+    hierarchy: Vec<String>,
+    modified: Option<String>,
+    own_tags: Vec<String>,
+    inherited_tags: Vec<String>,
+    #[serde(default)]
+    pub(crate) windows_tags: Vec<String>,
+    // Tags stored by the OS or desktop: System.Keywords on Windows, the
+    // `user.xdg.tags` xattr elsewhere
+    #[serde(default)]
+    pub(crate) platform_tags: Vec<String>,
+    // Keywords (`dc:subject`) from an XMP sidecar next to a file
+    #[serde(default)]
+    pub(crate) sidecar_tags: Vec<String>,
+    // Tags listed for the entry in its directory's `.tags` manifest
+    #[serde(default)]
+    pub(crate) manifest_tags: Vec<String>,
+    // Number of direct children hidden by .gitignore/.tagignore rules
+    #[serde(default)]
+    pub(crate) ignored_children: u64,
+    #[serde(default)]
+    pub(crate) freshness: Freshness,
+    // Recursive totals, only set on directories
+    #[serde(default)]
+    pub(crate) stats: Option<DirectoryStats>,
+    #[serde(default)]
+    pub(crate) extended: ExtendedMetadata,
+}
+
+// Tree node structure
+#[derive(Serialize)]
+pub struct DirectoryNode {
+    name: String,
+    info: FileInfo,
+    #[serde(default)]
+    children: Vec<DirectoryNode>,
+    // Set when a scan budget ran out before all of this directory's children were listed
+    #[serde(default)]
+    truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipKind {
+    PermissionDenied,
+    // Removed between being listed and being read
+    NotFound,
+    // The filesystem lacks a feature the operation needs, e.g. extended attributes
+    Unsupported,
+    Other,
+}
+
+impl From<io::ErrorKind> for SkipKind {
+    fn from(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::NotFound => Self::NotFound,
+            io::ErrorKind::Unsupported => Self::Unsupported,
+            _ => Self::Other,
+        }
+    }
+}
+
+// An entry or directory listing the scan could not read; the walk carries on without it
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedEntry {
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) path: PathBuf,
+    pub(crate) kind: SkipKind,
+    pub(crate) message: String,
+}
+
+impl SkippedEntry {
+    pub(crate) fn from_io(path: &Path, err: &io::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            kind: err.kind().into(),
+            message: err.to_string(),
+        }
+    }
+}
+
+// What the scan commands return
+#[derive(Serialize)]
+pub struct ScanResult {
+    tree: DirectoryNode,
+    skipped: Vec<SkippedEntry>,
+}
+
+impl ScanResult {
+    pub fn tree(&self) -> &DirectoryNode {
+        &self.tree
+    }
+
+    pub fn skipped(&self) -> &[SkippedEntry] {
+        &self.skipped
+    }
+}
+
+impl DirectoryNode {
+    pub fn info(&self) -> &FileInfo {
+        &self.info
+    }
+
+    pub fn children(&self) -> &[DirectoryNode] {
+        &self.children
+    }
+}
+
+impl FileInfo {
+    // An entry with only its path and kind known, for callers to fill in
+    pub(crate) fn new(path: &Path, is_directory: bool) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            is_directory,
+            is_symlink: false,
+            symlink_target: None,
+            is_broken_symlink: false,
+            size: 0,
+            hierarchy: collect_path_hierarchy(path),
+            modified: None,
+            own_tags: Vec::new(),
+            inherited_tags: Vec::new(),
+            windows_tags: Vec::new(),
+            platform_tags: Vec::new(),
+            sidecar_tags: Vec::new(),
+            manifest_tags: Vec::new(),
+            ignored_children: 0,
+            freshness: Freshness::Live,
+            stats: None,
+            extended: ExtendedMetadata::default(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn own_tags(&self) -> &[String] {
+        &self.own_tags
+    }
+
+    pub fn inherited_tags(&self) -> &[String] {
+        &self.inherited_tags
+    }
+}
+
+// Database access for the steps of a scan that need it. The disk is read in
+// between, so a shared connection is only locked while a step runs.
+pub trait ScanDatabase {
+    fn with_database<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Database) -> Result<T, ScanError>,
+    ) -> Result<T, ScanError>;
+}
+
+impl ScanDatabase for &mut Database {
+    fn with_database<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Database) -> Result<T, ScanError>,
+    ) -> Result<T, ScanError> {
+        operation(self)
+    }
+}
+
+impl ScanDatabase for &DbConnection {
+    fn with_database<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Database) -> Result<T, ScanError>,
+    ) -> Result<T, ScanError> {
+        DbConnection::with_database(self, operation)
+    }
+}
+
+// Scans `path` down to `depth` levels and applies the stored tags
+pub fn scan_directory(
+    mut database: impl ScanDatabase,
+    path: &Path,
+    depth: usize,
+    options: &ScanOptions,
+    sort: &SortOptions,
+) -> Result<ScanResult, ScanError> {
+    let filter = ScanFilter::new(path, options)?;
+    let mut budget = ScanBudget::new(options)?;
+    // Only DuckDB keeps a scan cache; other backends read everything from disk
+    let mut cache = database.with_database(|database| match database.duckdb() {
+        Some(connection) => DirectoryCache::load(connection, path, depth, options.use_cache),
+        None => Ok(DirectoryCache::empty()),
+    })?;
+    let mut skipped = Vec::new();
+    let mut entries =
+        platform::collect_entries(path, depth, &filter, &mut cache, &mut skipped, &mut budget)?;
+
+    // A failed cache write only costs a re-read next time, so the scan still succeeds
+    if let Err(err) = database.with_database(|database| match database.duckdb() {
+        Some(connection) => cache.persist(connection),
+        None => Ok(()),
+    }) {
+        warn!("Failed to update scan cache for {:?}: {}", path, err);
+    }
+
+    filter.retain_matching(&mut entries);
+    let tags = fetch_tags_for_scan(&mut database, path, depth, &entries)?;
+    apply_tags(path, &mut entries, &tags);
+    let mut tree = build_directory_tree(path, &entries, sort)?;
+    if !budget.truncated.is_empty() {
+        warn!(
+            "Scan of {:?} hit its budget; {} directories are incomplete",
+            path,
+            budget.truncated.len()
+        );
+        mark_truncated(&mut tree, &budget.truncated);
+    }
+
+    if !skipped.is_empty() {
+        warn!(
+            "Skipped {} unreadable entries under {:?}",
+            skipped.len(),
+            path
+        );
+    }
+    Ok(ScanResult { tree, skipped })
+}
+
+// Scans the current working directory two levels deep
+pub fn scan_current_directory(
+    database: impl ScanDatabase,
+    options: &ScanOptions,
+    sort: &SortOptions,
+) -> Result<ScanResult, ScanError> {
+    let current_dir = env::current_dir().map_err(|e| ScanError::CurrentDir(e.to_string()))?;
+    scan_directory(database, &current_dir, 2, options, sort)
+}
+
+// Searches names, or whole paths, of everything indexed by earlier scans or
+// tagged, regardless of the current scan root. Results are ranked best first.
+// Name searches for three or more consecutive characters go through a
+// trigram index; full-path and fuzzy searches, and the tag table, are read
+// in full.
+pub fn search_files(
+    connection: &duckdb::Connection,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<FileInfo>, ScanError> {
+    search::search(connection, query, options)
+}
+
+// Filters indexed entries by tags and attributes, and counts how the matches
+// break down by tag, extension and kind so the UI can offer drill-downs
+pub fn filter_entries(
+    connection: &duckdb::Connection,
+    filter: &FacetFilter,
+) -> Result<FilterResult, ScanError> {
+    facets::filter_entries(connection, filter)
+}
+
+fn fetch_tags_for_scan(
+    database: &mut impl ScanDatabase,
+    root: &Path,
+    depth: usize,
+    entries: &[FileInfo],
+) -> Result<DirectoryTagSnapshot, ScanError> {
+    database.with_database(|database| {
+        let storage = database.storage();
+        let mut snapshot = get_tags_for_directory(storage, root, depth).map_err(tagging_error)?;
+
+        // Tags may be stored under another spelling of a scanned path on
+        // roots that match case- or normalization-insensitively
+        let rules = storage.path_matching_rules().map_err(tagging_error)?;
+        if !rules.is_empty() {
+            let normalized_root = normalize_path_buf(root);
+            let display_paths: Vec<PathBuf> = entries
+                .iter()
+                .map(|entry| display_key(&entry.path, root, &normalized_root))
+                .collect();
+            snapshot.align_to(&rules, &display_paths);
+        }
+
+        let link_targets = resolved_link_targets(root, entries);
+        if !link_targets.is_empty() {
+            let target_tags = get_tags_for_paths(storage, &link_targets).map_err(tagging_error)?;
+            snapshot.direct_tags.extend(target_tags);
+        }
+
+        Ok(snapshot)
+    })
+}
+
+// A path the tag store cannot resolve is bad input rather than a database
+// failure
+fn tagging_error(err: TaggingError) -> ScanError {
+    match err {
+        TaggingError::RelativePath(_) => ScanError::InvalidPath(err.to_string()),
+        TaggingError::Access(err) => ScanError::Access(err),
+        err => ScanError::Database(err.to_string()),
+    }
+}
+
+// Resolved locations of entries that sit behind a symlink. The directory
+// snapshot is keyed by the scan root, so these need a separate lookup.
+fn resolved_link_targets(root: &Path, entries: &[FileInfo]) -> Vec<PathBuf> {
+    if !entries.iter().any(|entry| entry.is_symlink) {
+        return Vec::new();
+    }
+
+    let normalized_root = normalize_path_buf(root);
+    let targets: BTreeSet<PathBuf> = entries
+        .iter()
+        .filter_map(|entry| {
+            let resolved = fs::canonicalize(&entry.path).ok()?;
+            let display_path = display_key(&entry.path, root, &normalized_root);
+            (resolved != display_path).then_some(resolved)
+        })
+        .collect();
+
+    targets.into_iter().collect()
+}
+
+// Tags are inherited along the path an entry is displayed under, so anything
+// reached through a followed link picks up the tags of the link's parents.
+// Tags stored on the resolved target, which is where `assign_tag_to_paths`
+// records them, count as the entry's own tags and flow down from there.
+fn apply_tags(root: &Path, entries: &mut [FileInfo], tag_snapshot: &DirectoryTagSnapshot) {
+    let mut cache: HashMap<PathBuf, (Vec<String>, Vec<String>)> = HashMap::new();
+    let normalized_root = normalize_path_buf(root);
+    let root_ancestor_tags = &tag_snapshot.root_ancestor_tags;
+
+    let display_paths: Vec<PathBuf> = entries
+        .iter()
+        .map(|entry| display_key(&entry.path, root, &normalized_root))
+        .collect();
+
+    let mut direct_tags = tag_snapshot.direct_tags.clone();
+    for (entry, display_path) in entries.iter().zip(&display_paths) {
+        let resolved_path = normalize_path_buf(&entry.path);
+        if resolved_path == *display_path {
+            continue;
+        }
+        if let Some(target_tags) = tag_snapshot.direct_tags.get(&resolved_path) {
+            let own_tags = direct_tags.entry(display_path.clone()).or_default();
+            let mut merged: BTreeSet<String> = own_tags.drain(..).collect();
+            merged.extend(target_tags.iter().cloned());
+            own_tags.extend(merged);
+        }
+    }
+
+    for (entry, display_path) in entries.iter_mut().zip(&display_paths) {
+        let (own_tags, inherited_tags) = compute_own_and_inherited_tags(
+            display_path,
+            &normalized_root,
+            &direct_tags,
+            root_ancestor_tags,
+            &mut cache,
+        );
+        entry.own_tags = own_tags;
+        entry.inherited_tags = inherited_tags;
+    }
+}
+
+// Maps a scanned path onto the normalized root without resolving symlinks
+// below it
+fn display_key(path: &Path, root: &Path, normalized_root: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => normalized_root.to_path_buf(),
+        Ok(relative) => normalized_root.join(relative),
+        Err(_) => normalize_path_buf(path),
+    }
+}
+
+#[allow(clippy::map_clone)]
+fn compute_own_and_inherited_tags(
+    path: &Path,
+    root: &Path,
+    direct_tags: &BTreeMap<PathBuf, Vec<String>>,
+    root_ancestor_tags: &[String],
+    cache: &mut HashMap<PathBuf, (Vec<String>, Vec<String>)>,
+) -> (Vec<String>, Vec<String>) {
+    if let Some(existing) = cache.get(path) {
+        return existing.clone();
+    }
+
+    let own_tags: Vec<String> = direct_tags
+        .get(path)
+        .map(|tags| tags.clone())
+        .unwrap_or_default();
+
+    let inherited_tags = if path == root {
+        let mut inherited = BTreeSet::new();
+        inherited.extend(root_ancestor_tags.iter().cloned());
+        inherited.into_iter().collect::<Vec<_>>()
+    } else if let Some(parent) = path.parent() {
+        let (parent_own, parent_inherited) =
+            compute_own_and_inherited_tags(parent, root, direct_tags, root_ancestor_tags, cache);
+        let mut inherited = BTreeSet::new();
+        inherited.extend(parent_inherited);
+        inherited.extend(parent_own);
+        inherited.into_iter().collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+
+    let result = (own_tags, inherited_tags);
+    cache.insert(path.to_path_buf(), result.clone());
+    result
+}
+
+fn build_directory_tree(
+    root: &Path,
+    entries: &[FileInfo],
+    sort: &SortOptions,
+) -> Result<DirectoryNode, ScanError> {
+    let mut adjacency: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+
+    for (idx, entry) in entries.iter().enumerate() {
+        if let Some(parent) = entry.path.parent() {
+            adjacency.entry(parent.to_path_buf()).or_default().push(idx);
+        }
+    }
+
+    for children in adjacency.values_mut() {
+        children.sort_unstable();
+    }
+
+    let root_index = entries
+        .iter()
+        .position(|entry| entry.path == root)
+        .ok_or_else(|| ScanError::MissingRoot(root.to_path_buf()))?;
+
+    Ok(build_node(entries, &adjacency, root_index, sort))
+}
+
+fn build_node(
+    entries: &[FileInfo],
+    adjacency: &HashMap<PathBuf, Vec<usize>>,
+    index: usize,
+    sort: &SortOptions,
+) -> DirectoryNode {
+    let mut info = entries[index].clone();
+    let name = info
+        .path
+        .file_name()
+        .map(encode_os_str)
+        .unwrap_or_else(|| encode_path(&info.path));
+
+    let mut children = adjacency
+        .get(&info.path)
+        .into_iter()
+        .flat_map(|indices| indices.iter().copied())
+        .filter(|&child_idx| child_idx != index)
+        .map(|child_idx| build_node(entries, adjacency, child_idx, sort))
+        .collect::<Vec<_>>();
+
+    children.sort_by(|a, b| sort.compare(a, b));
+
+    if info.is_directory {
+        info.stats = Some(aggregate_stats(info.stats, &children));
+    }
+
+    DirectoryNode {
+        name,
+        info,
+        children,
+        truncated: false,
+    }
+}
+
+fn mark_truncated(node: &mut DirectoryNode, truncated: &HashSet<PathBuf>) {
+    node.truncated = truncated.contains(&node.info.path);
+    for child in &mut node.children {
+        mark_truncated(child, truncated);
+    }
+}
+
+// Folds the displayed children into the stats seeded by the collector, which
+// cover whatever lies beyond the scanned depth
+fn aggregate_stats(seed: Option<DirectoryStats>, children: &[DirectoryNode]) -> DirectoryStats {
+    let mut stats = seed.unwrap_or_default();
+    for child in children {
+        if child.info.is_directory {
+            stats.add_directory(&child.info.stats.unwrap_or_default());
+        } else {
+            stats.add_file(child.info.size);
+        }
+    }
+    stats
+}
+
+fn normalize_path_buf(path: &Path) -> PathBuf {
+    match fs::canonicalize(path) {
+        Ok(canonical) => canonical,
+        Err(err) => {
+            warn!(
+                "Failed to canonicalize path during scan; normalizing lexically: {:?} ({})",
+                path, err
+            );
+            // Without a current directory, a relative path stays relative
+            let base = relative_base(path).unwrap_or_default();
+            normalize_lexically(path, &base)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::options::EntryKindFilter;
+    use super::*;
+    use crate::path_codec::decode_path;
+    use crate::storage::StorageBackend;
+    use std::path::Path;
+
+    fn file_info(path: &str, is_directory: bool) -> FileInfo {
+        FileInfo::new(Path::new(path), is_directory)
+    }
+
+    #[test]
+    fn build_tree_simple() {
+        let root = PathBuf::from("/root");
+        let entries = vec![
+            file_info("/root", true),
+            file_info("/root/file1.txt", false),
+            file_info("/root/file2.txt", false),
+        ];
+
+        let tree = build_directory_tree(root.as_path(), &entries, &SortOptions::default()).unwrap();
+        assert_eq!(tree.info.path, PathBuf::from("/root"));
+        assert_eq!(tree.children.len(), 2);
+        assert_eq!(tree.children[0].info.path, PathBuf::from("/root/file1.txt"));
+        assert_eq!(tree.children[1].info.path, PathBuf::from("/root/file2.txt"));
+    }
+
+    #[test]
+    fn build_tree_root_only() {
+        let root = PathBuf::from("/root");
+        let entries = vec![file_info("/root", true)];
+
+        let tree = build_directory_tree(root.as_path(), &entries, &SortOptions::default()).unwrap();
+        assert_eq!(tree.info.path, PathBuf::from("/root"));
+        assert!(tree.children.is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::cmp_owned)]
+    fn build_tree_nested() {
+        let root = PathBuf::from("/root");
+        let entries = vec![
+            file_info("/root", true),
+            file_info("/root/dir1", true),
+            file_info("/root/dir1/file.txt", false),
+            file_info("/root/dir2", true),
+            file_info("/root/dir2/file_a.txt", false),
+        ];
+
+        let tree = build_directory_tree(root.as_path(), &entries, &SortOptions::default()).unwrap();
+        assert_eq!(tree.children.len(), 2);
+
+        let dir1 = tree
+            .children
+            .iter()
+            .find(|child| child.info.path == PathBuf::from("/root/dir1"))
+            .unwrap();
+        assert_eq!(dir1.children.len(), 1);
+        assert_eq!(
+            dir1.children[0].info.path,
+            PathBuf::from("/root/dir1/file.txt")
+        );
+
+        let dir2 = tree
+            .children
+            .iter()
+            .find(|child| child.info.path == PathBuf::from("/root/dir2"))
+            .unwrap();
+        assert_eq!(dir2.children.len(), 1);
+        assert_eq!(
+            dir2.children[0].info.path,
+            PathBuf::from("/root/dir2/file_a.txt")
+        );
+    }
+
+    #[test]
+    fn build_tree_missing_root() {
+        let root = PathBuf::from("/root");
+        let entries = vec![file_info("/other", true)];
+
+        let result = build_directory_tree(root.as_path(), &entries, &SortOptions::default());
+        assert!(matches!(result, Err(ScanError::MissingRoot(_))));
+    }
+
+    #[test]
+    fn build_tree_applies_sort_options() {
+        let root = PathBuf::from("/root");
+        let sized = |path: &str, size: u64| FileInfo {
+            size,
+            ..file_info(path, false)
+        };
+        let entries = vec![
+            file_info("/root", true),
+            file_info("/root/dir", true),
+            sized("/root/dir/inner.bin", 50),
+            sized("/root/file10.txt", 100),
+            sized("/root/file2.txt", 10),
+        ];
+        let child_names = |tree: &DirectoryNode| -> Vec<String> {
+            tree.children
+                .iter()
+                .map(|child| child.name.clone())
+                .collect()
+        };
+
+        let natural = SortOptions {
+            name_comparison: sort::NameComparison::Natural,
+            ..SortOptions::default()
+        };
+        let tree = build_directory_tree(root.as_path(), &entries, &natural).unwrap();
+        assert_eq!(child_names(&tree), vec!["dir", "file2.txt", "file10.txt"]);
+
+        let largest_first = SortOptions {
+            key: sort::SortKey::Size,
+            direction: sort::SortDirection::Descending,
+            directories_first: false,
+            ..SortOptions::default()
+        };
+        let tree = build_directory_tree(root.as_path(), &entries, &largest_first).unwrap();
+        assert_eq!(child_names(&tree), vec!["file10.txt", "dir", "file2.txt"]);
+    }
+
+    #[test]
+    fn build_tree_aggregates_directory_stats() {
+        let root = PathBuf::from("/root");
+        let sized = |path: &str, size: u64| FileInfo {
+            size,
+            ..file_info(path, false)
+        };
+        let entries = vec![
+            file_info("/root", true),
+            file_info("/root/dir1", true),
+            sized("/root/dir1/a.txt", 10),
+            sized("/root/dir1/b.txt", 5),
+            FileInfo {
+                stats: Some(DirectoryStats {
+                    total_size: 100,
+                    file_count: 3,
+                    directory_count: 1,
+                    is_partial: false,
+                }),
+                ..file_info("/root/dir1/deep", true)
+            },
+            FileInfo {
+                stats: Some(DirectoryStats::partial()),
+                ..file_info("/root/locked", true)
+            },
+            sized("/root/top.txt", 1),
+        ];
+
+        let tree = build_directory_tree(root.as_path(), &entries, &SortOptions::default()).unwrap();
+        let dir1 = tree
+            .children
+            .iter()
+            .find(|child| child.info.path == Path::new("/root/dir1"))
+            .unwrap();
+        assert_eq!(
+            dir1.info.stats,
+            Some(DirectoryStats {
+                total_size: 115,
+                file_count: 5,
+                directory_count: 2,
+                is_partial: false,
+            })
+        );
+        assert_eq!(
+            tree.info.stats,
+            Some(DirectoryStats {
+                total_size: 116,
+                file_count: 6,
+                directory_count: 4,
+                is_partial: true,
+            })
+        );
+    }
+
+    #[test]
+    fn apply_tags_propagates_and_sorts() {
+        let root = PathBuf::from("/root");
+        let mut entries = vec![
+            file_info("/root", true),
+            file_info("/root/folder", true),
+            file_info("/root/folder/file.txt", false),
+        ];
+
+        let mut tags_map = BTreeMap::new();
+        tags_map.insert(PathBuf::from("/root"), vec!["root-tag".to_string()]);
+        tags_map.insert(
+            PathBuf::from("/root/folder"),
+            vec!["folder-tag".to_string(), "alpha".to_string()],
+        );
+        tags_map.insert(
+            PathBuf::from("/root/folder/file.txt"),
+            vec!["file-tag".to_string()],
+        );
+
+        let snapshot = DirectoryTagSnapshot {
+            direct_tags: tags_map,
+            root_ancestor_tags: Vec::new(),
+        };
+
+        apply_tags(root.as_path(), &mut entries, &snapshot);
+
+        // Root: own_tags only
+        assert_eq!(entries[0].own_tags, vec!["root-tag".to_string()]);
+        assert_eq!(entries[0].inherited_tags, Vec::<String>::new());
+
+        // Folder: own_tags + inherited from root
+        assert_eq!(
+            entries[1].own_tags,
+            vec!["folder-tag".to_string(), "alpha".to_string()]
+        );
+        assert_eq!(entries[1].inherited_tags, vec!["root-tag".to_string()]);
+
+        // File: own_tags + inherited from folder and root
+        assert_eq!(entries[2].own_tags, vec!["file-tag".to_string()]);
+        assert_eq!(
+            entries[2].inherited_tags,
+            vec![
+                "alpha".to_string(),
+                "folder-tag".to_string(),
+                "root-tag".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_tags_assigns_root_ancestor_tags() {
+        let root = PathBuf::from("/root/project");
+        let mut entries = vec![
+            file_info("/root/project", true),
+            file_info("/root/project/nested", true),
+        ];
+
+        let snapshot = DirectoryTagSnapshot {
+            direct_tags: BTreeMap::new(),
+            root_ancestor_tags: vec!["alpha".to_string(), "beta".to_string()],
+        };
+
+        apply_tags(root.as_path(), &mut entries, &snapshot);
+
+        assert!(entries[0].own_tags.is_empty());
+        assert_eq!(
+            entries[0].inherited_tags,
+            vec!["alpha".to_string(), "beta".to_string()]
+        );
+
+        assert!(entries[1].own_tags.is_empty());
+        assert_eq!(
+            entries[1].inherited_tags,
+            vec!["alpha".to_string(), "beta".to_string()]
+        );
+    }
+
+    #[test]
+    fn apply_tags_handles_empty_direct_map() {
+        let root = PathBuf::from("/root");
+        let mut entries = vec![file_info("/root", true)];
+        let snapshot = DirectoryTagSnapshot {
+            direct_tags: BTreeMap::new(),
+            root_ancestor_tags: Vec::new(),
+        };
+
+        apply_tags(root.as_path(), &mut entries, &snapshot);
+        assert!(entries[0].own_tags.is_empty());
+        assert!(entries[0].inherited_tags.is_empty());
+    }
+
+    #[cfg(unix)]
+    fn symlink_fixture() -> (tempfile::TempDir, PathBuf, PathBuf) {
+        use std::os::unix::fs::symlink;
+
+        let temp = tempfile::tempdir().expect("temp dir");
+        let base = fs::canonicalize(temp.path()).expect("canonical temp dir");
+        let root = base.join("root");
+        let outside = base.join("outside");
+        fs::create_dir_all(root.join("nested")).expect("create root");
+        fs::create_dir_all(&outside).expect("create outside");
+        fs::write(outside.join("target.txt"), "data").expect("write target");
+
+        symlink(&outside, root.join("link")).expect("dir link");
+        symlink(&root, root.join("nested").join("loop")).expect("loop link");
+        symlink(base.join("missing"), root.join("broken")).expect("broken link");
+
+        (temp, root, outside)
+    }
+
+    #[cfg(unix)]
+    fn find_entry<'a>(entries: &'a [FileInfo], path: &Path) -> Option<&'a FileInfo> {
+        entries.iter().find(|entry| entry.path == path)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collect_entries_reports_links_without_following_by_default() {
+        let (_temp, root, outside) = symlink_fixture();
+        let filter = ScanFilter::new(&root, &ScanOptions::default()).unwrap();
+
+        let entries = platform::collect_entries(
+            &root,
+            5,
+            &filter,
+            &mut DirectoryCache::empty(),
+            &mut Vec::new(),
+            &mut ScanBudget::unlimited(),
+        )
+        .unwrap();
+
+        let link = find_entry(&entries, &root.join("link")).expect("link entry");
+        assert!(link.is_symlink);
+        assert!(link.is_directory);
+        assert_eq!(link.symlink_target.as_deref(), Some(outside.as_path()));
+        assert!(find_entry(&entries, &root.join("link").join("target.txt")).is_none());
+
+        let broken = find_entry(&entries, &root.join("broken")).expect("broken entry");
+        assert!(broken.is_broken_symlink);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collect_entries_follows_links_and_stops_at_cycles() {
+        let (_temp, root, _outside) = symlink_fixture();
+        let options = ScanOptions {
+            follow_symlinks: true,
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(&root, &options).unwrap();
+
+        let entries = platform::collect_entries(
+            &root,
+            10,
+            &filter,
+            &mut DirectoryCache::empty(),
+            &mut Vec::new(),
+            &mut ScanBudget::unlimited(),
+        )
+        .unwrap();
+
+        assert!(find_entry(&entries, &root.join("link").join("target.txt")).is_some());
+
+        let loop_link =
+            find_entry(&entries, &root.join("nested").join("loop")).expect("loop entry");
+        assert!(loop_link.is_symlink);
+        assert!(!entries.iter().any(|entry| entry
+            .path
+            .starts_with(root.join("nested").join("loop").join("nested"))));
+
+        let broken = find_entry(&entries, &root.join("broken")).expect("broken entry");
+        assert!(broken.is_broken_symlink);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn apply_tags_merges_target_tags_through_links() {
+        let (_temp, root, outside) = symlink_fixture();
+        let options = ScanOptions {
+            follow_symlinks: true,
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(&root, &options).unwrap();
+        let mut entries = platform::collect_entries(
+            &root,
+            3,
+            &filter,
+            &mut DirectoryCache::empty(),
+            &mut Vec::new(),
+            &mut ScanBudget::unlimited(),
+        )
+        .unwrap();
+
+        let mut direct_tags = BTreeMap::new();
+        direct_tags.insert(root.clone(), vec!["root-tag".to_string()]);
+        direct_tags.insert(outside.clone(), vec!["target-tag".to_string()]);
+        let snapshot = DirectoryTagSnapshot {
+            direct_tags,
+            root_ancestor_tags: Vec::new(),
+        };
+
+        assert_eq!(
+            resolved_link_targets(&root, &entries).first(),
+            Some(&outside)
+        );
+
+        apply_tags(&root, &mut entries, &snapshot);
+
+        let link = find_entry(&entries, &root.join("link")).unwrap();
+        assert_eq!(link.own_tags, vec!["target-tag".to_string()]);
+        assert_eq!(link.inherited_tags, vec!["root-tag".to_string()]);
+
+        let file = find_entry(&entries, &root.join("link").join("target.txt")).unwrap();
+        assert!(file.own_tags.is_empty());
+        assert_eq!(
+            file.inherited_tags,
+            vec!["root-tag".to_string(), "target-tag".to_string()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collect_entries_reports_unreadable_directories_and_continues() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = temp.path().to_path_buf();
+        let locked = root.join("locked");
+        fs::create_dir_all(locked.join("inner")).expect("create locked");
+        fs::write(root.join("visible.txt"), "data").expect("write");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).expect("lock");
+
+        // Privileged users can read the directory anyway, which leaves nothing to report
+        let readable = fs::read_dir(&locked).is_ok();
+        let filter = ScanFilter::new(&root, &ScanOptions::default()).unwrap();
+        let mut skipped = Vec::new();
+        let entries = platform::collect_entries(
+            &root,
+            3,
+            &filter,
+            &mut DirectoryCache::empty(),
+            &mut skipped,
+            &mut ScanBudget::unlimited(),
+        );
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).expect("unlock");
+
+        let entries = entries.expect("scan continues past unreadable directories");
+        assert!(find_entry(&entries, &root.join("visible.txt")).is_some());
+        assert!(find_entry(&entries, &locked).is_some());
+        if readable {
+            return;
+        }
+
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].path, locked);
+        assert_eq!(skipped[0].kind, SkipKind::PermissionDenied);
+        let locked_info = find_entry(&entries, &locked).unwrap();
+        assert!(locked_info.stats.is_some_and(|stats| stats.is_partial));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_survive_the_cache_and_serialization() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = temp.path().to_path_buf();
+        let odd = root.join(OsStr::from_bytes(b"caf\xe9.txt"));
+        // Some filesystems only accept UTF-8 names
+        if fs::write(&odd, "data").is_err() {
+            return;
+        }
+
+        let mut connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_cache_schema(&connection).expect("cache schema");
+        let filter = ScanFilter::new(&root, &ScanOptions::default()).unwrap();
+        let scan = |connection: &mut duckdb::Connection| {
+            let mut cache = DirectoryCache::load(connection, &root, 1, true).expect("load");
+            let entries = platform::collect_entries(
+                &root,
+                1,
+                &filter,
+                &mut cache,
+                &mut Vec::new(),
+                &mut ScanBudget::unlimited(),
+            )
+            .expect("scan");
+            cache.persist(connection).expect("persist");
+            entries
+        };
+
+        scan(&mut connection);
+        let entries = scan(&mut connection);
+        let cached = find_entry(&entries, &odd).expect("entry with the original bytes");
+        assert_eq!(cached.freshness, Freshness::Cached);
+
+        let tree = build_directory_tree(&root, &entries, &SortOptions::default()).unwrap();
+        let json = serde_json::to_value(&tree).expect("serializable tree");
+        let child = &json["children"][0];
+        assert_eq!(child["name"], "caf\u{FFFD}E9.txt");
+        let encoded = child["info"]["path"].as_str().expect("path string");
+        assert_eq!(decode_path(encoded), odd);
+    }
+
+    #[test]
+    fn files_only_keeps_the_directories_above_nested_files() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("docs").join("notes")).expect("create docs");
+        fs::create_dir_all(root.join("empty")).expect("create empty");
+        fs::write(root.join("top.txt"), "data").expect("write");
+        fs::write(root.join("docs").join("notes").join("deep.txt"), "data").expect("write");
+
+        let options = ScanOptions {
+            entry_kind: EntryKindFilter::FilesOnly,
+            ..ScanOptions::default()
+        };
+        let mut database = Database::open_in_memory(StorageBackend::DuckDb).expect("database");
+        let result = scan_directory(&mut database, &root, 5, &options, &SortOptions::default())
+            .expect("scan");
+
+        let docs = &result.tree.children[0];
+        assert_eq!(docs.info.path, root.join("docs"));
+        assert_eq!(
+            docs.children[0].children[0].info.path,
+            root.join("docs").join("notes").join("deep.txt")
+        );
+        assert_eq!(result.tree.children[1].info.path, root.join("top.txt"));
+        assert_eq!(result.tree.children.len(), 2);
+    }
+
+    #[test]
+    fn cached_listings_read_sidecar_tags_again() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = temp.path().to_path_buf();
+        let photo = root.join("photo.jpg");
+        fs::write(&photo, "jpg").expect("write photo");
+        let shared = crate::xmp_sidecar::SharedStems::next_to(&photo);
+        let sidecar = crate::xmp_sidecar::write_tags(&photo, &["beach".to_string()], &shared)
+            .expect("write sidecar")
+            .expect("sidecar path");
+
+        let mut database = Database::open_in_memory(StorageBackend::DuckDb).expect("database");
+        let options = ScanOptions::default();
+        scan_directory(&mut database, &root, 1, &options, &SortOptions::default()).expect("scan");
+
+        // Rewritten in place, the way editors do, so the directory mtime stays put
+        let xmp = fs::read_to_string(&sidecar).expect("read sidecar");
+        fs::write(&sidecar, xmp.replace("beach", "sea")).expect("rewrite sidecar");
+
+        let result = scan_directory(&mut database, &root, 1, &options, &SortOptions::default())
+            .expect("rescan");
+        let photo_node = result
+            .tree
+            .children
+            .iter()
+            .find(|child| child.info.path == photo)
+            .expect("photo entry");
+        assert_eq!(photo_node.info.freshness, Freshness::Cached);
+        assert_eq!(photo_node.info.sidecar_tags, vec!["sea".to_string()]);
+    }
+
+    #[test]
+    fn relative_scan_roots_pick_up_tags_stored_on_absolute_paths() {
+        // Tests run from the crate directory
+        let root = Path::new("src/scan");
+        let platform = fs::canonicalize(root.join("platform")).expect("canonical");
+        let mut database = Database::open_in_memory(StorageBackend::DuckDb).expect("database");
+        crate::tagging::assign_tag_to_paths(&mut database, &[platform], "code").expect("tag");
+
+        let result = scan_directory(
+            &mut database,
+            root,
+            1,
+            &ScanOptions::default(),
+            &SortOptions::default(),
+        )
+        .expect("scan");
+        let platform_node = result
+            .tree
+            .children
+            .iter()
+            .find(|child| child.info.path == root.join("platform"))
+            .expect("platform entry");
+        assert_eq!(platform_node.info.own_tags, vec!["code".to_string()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collect_entries_marks_directories_cut_short_by_the_budget() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = temp.path().to_path_buf();
+        let wide = root.join("wide");
+        fs::create_dir_all(wide.join("sub")).expect("create wide");
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(wide.join(name), "data").expect("write");
+        }
+        fs::write(wide.join("sub").join("deep.txt"), "data").expect("write");
+
+        let options = ScanOptions {
+            max_entries_per_directory: Some(2),
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(&root, &options).unwrap();
+        let mut budget = ScanBudget::new(&options).unwrap();
+        let entries = platform::collect_entries(
+            &root,
+            5,
+            &filter,
+            &mut DirectoryCache::empty(),
+            &mut Vec::new(),
+            &mut budget,
+        )
+        .unwrap();
+
+        // "sub" sorts after the files that used up the cap, so it is never walked
+        assert!(find_entry(&entries, &wide.join("a.txt")).is_some());
+        assert!(find_entry(&entries, &wide.join("b.txt")).is_some());
+        assert!(find_entry(&entries, &wide.join("c.txt")).is_none());
+        assert!(find_entry(&entries, &wide.join("sub").join("deep.txt")).is_none());
+        assert_eq!(budget.truncated, HashSet::from([wide.clone()]));
+
+        let mut tree = build_directory_tree(&root, &entries, &SortOptions::default()).unwrap();
+        mark_truncated(&mut tree, &budget.truncated);
+        assert!(!tree.truncated);
+        assert!(tree.children[0].truncated);
+        assert!(tree.children[0]
+            .info
+            .stats
+            .is_some_and(|stats| stats.is_partial));
+    }
+}
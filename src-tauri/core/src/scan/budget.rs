@@ -0,0 +1,133 @@
+use super::options::ScanOptions;
+use super::ScanError;
+use log::debug;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Limits on how much a single scan may collect. Once a limit is hit the
+// collectors stop listing and record every directory whose children were cut
+// short, so the tree can flag those nodes for a follow-up scan.
+pub(crate) struct ScanBudget {
+    max_entries: Option<usize>,
+    max_entries_per_directory: Option<usize>,
+    deadline: Option<Instant>,
+    pub(crate) truncated: HashSet<PathBuf>,
+}
+
+impl ScanBudget {
+    pub(crate) fn new(options: &ScanOptions) -> Result<Self, ScanError> {
+        for (field, value) in [
+            ("max_entries", options.max_entries),
+            (
+                "max_entries_per_directory",
+                options.max_entries_per_directory,
+            ),
+        ] {
+            if value == Some(0) {
+                return Err(ScanError::InvalidOptions(format!(
+                    "{field} must be greater than zero"
+                )));
+            }
+        }
+
+        Ok(Self {
+            max_entries: options.max_entries,
+            max_entries_per_directory: options.max_entries_per_directory,
+            deadline: options
+                .max_duration_ms
+                .map(|millis| Instant::now() + Duration::from_millis(millis)),
+            truncated: HashSet::new(),
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn unlimited() -> Self {
+        Self {
+            max_entries: None,
+            max_entries_per_directory: None,
+            deadline: None,
+            truncated: HashSet::new(),
+        }
+    }
+
+    // Whether another directory may be listed given the entries collected so far
+    pub(crate) fn can_continue(&self, collected: usize) -> bool {
+        if self.max_entries.is_some_and(|max| collected >= max) {
+            return false;
+        }
+        if self.out_of_time() {
+            debug!("Scan time limit reached");
+            return false;
+        }
+        true
+    }
+
+    // Checked inside listings and subtree walks as well, so one huge directory
+    // cannot run far past the limit
+    pub(crate) fn out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // Whether one more entry may be reported for a directory that already
+    // reported `in_directory` of them
+    pub(crate) fn admits(&self, collected: usize, in_directory: usize) -> bool {
+        self.max_entries.is_none_or(|max| collected < max)
+            && self
+                .max_entries_per_directory
+                .is_none_or(|max| in_directory < max)
+            && !self.out_of_time()
+    }
+
+    pub(crate) fn mark_truncated(&mut self, directory: &Path) {
+        self.truncated.insert(directory.to_path_buf());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enforces_entry_caps() {
+        let options = ScanOptions {
+            max_entries: Some(10),
+            max_entries_per_directory: Some(3),
+            ..ScanOptions::default()
+        };
+        let budget = ScanBudget::new(&options).unwrap();
+
+        assert!(budget.admits(5, 2));
+        assert!(!budget.admits(5, 3));
+        assert!(!budget.admits(10, 0));
+        assert!(budget.can_continue(9));
+        assert!(!budget.can_continue(10));
+    }
+
+    #[test]
+    fn stops_once_the_deadline_passes() {
+        let options = ScanOptions {
+            max_duration_ms: Some(0),
+            ..ScanOptions::default()
+        };
+        let budget = ScanBudget::new(&options).unwrap();
+
+        assert!(!budget.can_continue(0));
+        assert!(!budget.admits(0, 0));
+        assert!(ScanBudget::unlimited().can_continue(usize::MAX));
+    }
+
+    #[test]
+    fn rejects_zero_entry_caps() {
+        let options = ScanOptions {
+            max_entries_per_directory: Some(0),
+            ..ScanOptions::default()
+        };
+
+        assert!(matches!(
+            ScanBudget::new(&options),
+            Err(ScanError::InvalidOptions(_))
+        ));
+    }
+}
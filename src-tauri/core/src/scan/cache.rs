@@ -0,0 +1,658 @@
+use super::helpers::{collect_path_hierarchy, system_time_to_rfc3339};
+use super::{FileInfo, Freshness, ScanError};
+use crate::path_codec::{decode_path, encode_os_str, encode_path};
+use crate::tagging::{calculate_path_depth, descendant_like_pattern};
+use log::debug;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub(super) const NAME_GRAM_LENGTH: usize = 3;
+
+// Columns read by `cached_file_info`, in order
+const FILE_INFO_COLUMNS: [&str; 12] = [
+    "path",
+    "is_directory",
+    "is_symlink",
+    "symlink_target",
+    "is_broken_symlink",
+    "size",
+    "modified",
+    "windows_tags",
+    "extended_metadata",
+    "platform_tags",
+    "sidecar_tags",
+    "manifest_tags",
+];
+
+// A directory listing as it was stored by a previous scan
+struct CachedListing {
+    listed_modified: String,
+    children: Vec<FileInfo>,
+}
+
+// A directory that was read from disk during this scan
+struct ListingUpdate {
+    directory: PathBuf,
+    listed_modified: String,
+    children: Vec<FileInfo>,
+}
+
+// In-memory view of the `files` table for one scan.
+//
+// Listings are loaded before the walk and written back afterwards, so the
+// database lock is not held while the disk is being read. A directory's
+// listing is reused while its own mtime matches the one recorded when it was
+// listed; that mtime changes whenever entries are added, removed or renamed.
+// Tags from xattrs, sidecars and manifests are stored too, but the collectors
+// read them again for cached entries because editing them leaves it alone.
+pub(crate) struct DirectoryCache {
+    listings: HashMap<PathBuf, CachedListing>,
+    updates: Vec<ListingUpdate>,
+    read_enabled: bool,
+}
+
+impl DirectoryCache {
+    pub(crate) fn empty() -> Self {
+        Self {
+            listings: HashMap::new(),
+            updates: Vec::new(),
+            read_enabled: true,
+        }
+    }
+
+    pub(crate) fn load(
+        connection: &duckdb::Connection,
+        root: &Path,
+        max_depth: usize,
+        read_enabled: bool,
+    ) -> Result<Self, ScanError> {
+        let mut cache = Self {
+            read_enabled,
+            ..Self::empty()
+        };
+        if !read_enabled {
+            return Ok(cache);
+        }
+
+        let root_string = encode_path(root);
+        let root_depth = calculate_path_depth(root);
+        let depth_offset = i64::try_from(max_depth).unwrap_or(i64::MAX);
+        let max_listed_depth = root_depth.saturating_add(depth_offset);
+
+        let sql = format!(
+            "
+                SELECT d.path, d.listed_modified, {columns}
+                FROM files d
+                LEFT JOIN files c ON c.parent_path = d.path
+                WHERE d.listed_modified IS NOT NULL
+                  AND d.path_depth >= ?1
+                  AND d.path_depth < ?2
+                  AND (d.path = ?3 OR d.path LIKE ?4 ESCAPE '\\')
+                ",
+            columns = file_info_columns("c"),
+        );
+        let mut statement = connection.prepare(&sql).map_err(database_error)?;
+
+        let mut rows = statement
+            .query(duckdb::params![
+                root_depth,
+                max_listed_depth,
+                root_string,
+                descendant_like_pattern(&root_string)
+            ])
+            .map_err(database_error)?;
+
+        while let Some(row) = rows.next().map_err(database_error)? {
+            let directory: String = row.get(0).map_err(database_error)?;
+            let listed_modified: String = row.get(1).map_err(database_error)?;
+            let listing = cache
+                .listings
+                .entry(decode_path(&directory))
+                .or_insert_with(|| CachedListing {
+                    listed_modified,
+                    children: Vec::new(),
+                });
+
+            let child_path: Option<String> = row.get(2).map_err(database_error)?;
+            if child_path.is_some() {
+                listing.children.push(cached_file_info(row, 2)?);
+            }
+        }
+
+        debug!(
+            "Loaded {} cached directory listings under {:?}",
+            cache.listings.len(),
+            root
+        );
+        Ok(cache)
+    }
+
+    // Returns the children of `directory`, reading them with `list` only when
+    // the cached listing is missing or out of date
+    pub(crate) fn list_with<E>(
+        &mut self,
+        directory: &Path,
+        list: impl FnOnce() -> Result<Vec<FileInfo>, E>,
+    ) -> Result<Vec<FileInfo>, E> {
+        let listed_modified = fs::metadata(directory)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(system_time_to_rfc3339);
+
+        if let Some(modified) = listed_modified.as_deref() {
+            if let Some(children) = self.lookup(directory, modified) {
+                debug!("Serving {:?} from the scan cache", directory);
+                return Ok(children);
+            }
+        }
+
+        let children = list()?;
+        if let Some(listed_modified) = listed_modified {
+            self.updates.push(ListingUpdate {
+                directory: directory.to_path_buf(),
+                listed_modified,
+                children: children.clone(),
+            });
+        }
+        Ok(children)
+    }
+
+    // Drops a listing read during this scan so it is not stored, e.g. because
+    // some of its entries could not be read and should be retried next time
+    pub(crate) fn discard(&mut self, directory: &Path) {
+        self.updates.retain(|update| update.directory != directory);
+    }
+
+    fn lookup(&self, directory: &Path, modified: &str) -> Option<Vec<FileInfo>> {
+        if !self.read_enabled {
+            return None;
+        }
+
+        self.listings
+            .get(directory)
+            .filter(|listing| listing.listed_modified == modified)
+            .map(|listing| listing.children.clone())
+    }
+
+    pub(crate) fn persist(self, connection: &mut duckdb::Connection) -> Result<(), ScanError> {
+        if self.updates.is_empty() {
+            return Ok(());
+        }
+
+        let transaction = connection.transaction().map_err(database_error)?;
+        let mut removed_any = false;
+        {
+            let mut grams = transaction
+                .appender("file_name_grams")
+                .map_err(database_error)?;
+            for update in &self.updates {
+                removed_any |= persist_listing(&transaction, &mut grams, update)?;
+            }
+            grams.flush().map_err(database_error)?;
+        }
+        // Entries below a removed directory go with it, so their grams are
+        // found by what is left rather than tracked one by one
+        if removed_any {
+            transaction
+                .execute(
+                    "DELETE FROM file_name_grams g
+                     WHERE NOT EXISTS (SELECT 1 FROM files f WHERE f.path = g.path)",
+                    [],
+                )
+                .map_err(database_error)?;
+        }
+        transaction.commit().map_err(database_error)?;
+
+        debug!("Stored {} refreshed directory listings", self.updates.len());
+        Ok(())
+    }
+}
+
+// Condition matching the rows written for a scan root, which only carry
+// listing state. Their entry metadata was never read, so `modified` is empty.
+pub(super) fn listing_only(alias: &str) -> String {
+    format!("({alias}.modified IS NULL AND {alias}.listed_modified IS NOT NULL)")
+}
+
+// `FILE_INFO_COLUMNS` qualified with a table alias, for use in a SELECT list
+pub(super) fn file_info_columns(alias: &str) -> String {
+    FILE_INFO_COLUMNS
+        .iter()
+        .map(|column| format!("{alias}.{column}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Builds an entry from `file_info_columns`, starting at column `first`
+pub(super) fn cached_file_info(row: &duckdb::Row, first: usize) -> Result<FileInfo, ScanError> {
+    let column = |offset: usize| first + offset;
+    let path = decode_path(&row.get::<_, String>(column(0)).map_err(database_error)?);
+    let symlink_target: Option<String> = row.get(column(3)).map_err(database_error)?;
+    let size: i64 = row.get(column(5)).map_err(database_error)?;
+    let windows_tags: Option<String> = row.get(column(7)).map_err(database_error)?;
+    let extended: Option<String> = row.get(column(8)).map_err(database_error)?;
+    let platform_tags: Option<String> = row.get(column(9)).map_err(database_error)?;
+    let sidecar_tags: Option<String> = row.get(column(10)).map_err(database_error)?;
+    let manifest_tags: Option<String> = row.get(column(11)).map_err(database_error)?;
+
+    Ok(FileInfo {
+        hierarchy: collect_path_hierarchy(&path),
+        path,
+        is_directory: row.get(column(1)).map_err(database_error)?,
+        is_symlink: row.get(column(2)).map_err(database_error)?,
+        symlink_target: symlink_target.as_deref().map(decode_path),
+        is_broken_symlink: row.get(column(4)).map_err(database_error)?,
+        size: u64::try_from(size).unwrap_or_default(),
+        modified: row.get(column(6)).map_err(database_error)?,
+        own_tags: Vec::new(),
+        inherited_tags: Vec::new(),
+        windows_tags: windows_tags
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default(),
+        platform_tags: platform_tags
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default(),
+        sidecar_tags: sidecar_tags
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default(),
+        manifest_tags: manifest_tags
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default(),
+        ignored_children: 0,
+        freshness: Freshness::Cached,
+        stats: None,
+        extended: extended
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default(),
+    })
+}
+
+// Final path component as stored in the `name` column, which filename search reads
+pub(super) fn entry_name(path: &Path) -> String {
+    path.file_name()
+        .map(encode_os_str)
+        .unwrap_or_else(|| encode_path(path))
+}
+
+pub(crate) fn ensure_cache_schema(connection: &duckdb::Connection) -> Result<(), ScanError> {
+    connection
+        .execute(
+            "
+            CREATE TABLE IF NOT EXISTS files (
+                path TEXT NOT NULL PRIMARY KEY,
+                parent_path TEXT,
+                name TEXT,
+                path_depth INTEGER,
+                is_directory BOOLEAN NOT NULL DEFAULT FALSE,
+                is_symlink BOOLEAN NOT NULL DEFAULT FALSE,
+                symlink_target TEXT,
+                is_broken_symlink BOOLEAN NOT NULL DEFAULT FALSE,
+                size BIGINT NOT NULL DEFAULT 0,
+                modified TEXT,
+                windows_tags TEXT,
+                extended_metadata TEXT,
+                platform_tags TEXT,
+                sidecar_tags TEXT,
+                manifest_tags TEXT,
+                listed_modified TEXT,
+                scanned_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )
+            ",
+            [],
+        )
+        .map_err(database_error)?;
+
+    connection
+        .execute(
+            "CREATE INDEX IF NOT EXISTS files_parent_path_idx ON files (parent_path)",
+            [],
+        )
+        .map_err(database_error)?;
+
+    // Filename search looks names up by their trigrams
+    connection
+        .execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS file_name_grams (
+                gram TEXT NOT NULL,
+                path TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS file_name_grams_gram_idx ON file_name_grams (gram);
+            ",
+        )
+        .map_err(database_error)?;
+
+    Ok(())
+}
+
+// Distinct three-character windows of a lowercased name, as stored in
+// `file_name_grams`. Shorter names have none.
+pub(super) fn name_grams(name: &str) -> BTreeSet<String> {
+    let chars: Vec<char> = name.to_lowercase().chars().collect();
+    chars
+        .windows(NAME_GRAM_LENGTH)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+fn tags_json(tags: &[String]) -> Option<String> {
+    if tags.is_empty() {
+        None
+    } else {
+        serde_json::to_string(tags).ok()
+    }
+}
+
+// Stores one listing, adding the name grams of entries the cache did not hold
+// yet. Returns whether any entry was removed.
+fn persist_listing(
+    connection: &duckdb::Connection,
+    grams: &mut duckdb::Appender,
+    update: &ListingUpdate,
+) -> Result<bool, ScanError> {
+    let directory = encode_path(&update.directory);
+    let current: BTreeSet<String> = update
+        .children
+        .iter()
+        .map(|child| encode_path(&child.path))
+        .collect();
+
+    let mut known = BTreeSet::new();
+    let mut stale = Vec::new();
+    {
+        let mut statement = connection
+            .prepare("SELECT path FROM files WHERE parent_path = ?1")
+            .map_err(database_error)?;
+        let mut rows = statement
+            .query(duckdb::params![directory])
+            .map_err(database_error)?;
+        while let Some(row) = rows.next().map_err(database_error)? {
+            let path: String = row.get(0).map_err(database_error)?;
+            if current.contains(&path) {
+                known.insert(path);
+            } else {
+                stale.push(path);
+            }
+        }
+    }
+    let directory_known: bool = connection
+        .query_row(
+            "SELECT count(*) > 0 FROM files WHERE path = ?1",
+            duckdb::params![directory],
+            |row| row.get(0),
+        )
+        .map_err(database_error)?;
+    let removed_any = !stale.is_empty();
+
+    for path in stale {
+        connection
+            .execute(
+                "DELETE FROM files WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'",
+                duckdb::params![path, descendant_like_pattern(&path)],
+            )
+            .map_err(database_error)?;
+    }
+
+    let mut upsert = connection
+        .prepare(
+            "
+            INSERT INTO files (
+                path, parent_path, path_depth, is_directory, is_symlink,
+                symlink_target, is_broken_symlink, size, modified, windows_tags,
+                extended_metadata, platform_tags, name, sidecar_tags, manifest_tags
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+            ON CONFLICT (path) DO UPDATE SET
+                is_directory = excluded.is_directory,
+                is_symlink = excluded.is_symlink,
+                symlink_target = excluded.symlink_target,
+                is_broken_symlink = excluded.is_broken_symlink,
+                size = excluded.size,
+                modified = excluded.modified,
+                windows_tags = excluded.windows_tags,
+                extended_metadata = excluded.extended_metadata,
+                platform_tags = excluded.platform_tags,
+                sidecar_tags = excluded.sidecar_tags,
+                manifest_tags = excluded.manifest_tags,
+                scanned_at = now()
+            ",
+        )
+        .map_err(database_error)?;
+
+    for child in &update.children {
+        let path = encode_path(&child.path);
+        if !known.contains(&path) {
+            append_grams(grams, &path, &entry_name(&child.path))?;
+        }
+        upsert
+            .execute(duckdb::params![
+                path,
+                directory,
+                calculate_path_depth(&child.path),
+                child.is_directory,
+                child.is_symlink,
+                child
+                    .symlink_target
+                    .as_ref()
+                    .map(|target| encode_path(target)),
+                child.is_broken_symlink,
+                i64::try_from(child.size).unwrap_or(i64::MAX),
+                child.modified,
+                tags_json(&child.windows_tags),
+                serde_json::to_string(&child.extended).ok(),
+                tags_json(&child.platform_tags),
+                entry_name(&child.path),
+                tags_json(&child.sidecar_tags),
+                tags_json(&child.manifest_tags),
+            ])
+            .map_err(database_error)?;
+    }
+
+    connection
+        .execute(
+            "
+            INSERT INTO files (path, parent_path, path_depth, is_directory, listed_modified, name)
+            VALUES (?1, ?2, ?3, TRUE, ?4, ?5)
+            ON CONFLICT (path) DO UPDATE SET
+                listed_modified = excluded.listed_modified,
+                scanned_at = now()
+            ",
+            duckdb::params![
+                directory,
+                update.directory.parent().map(encode_path),
+                calculate_path_depth(&update.directory),
+                update.listed_modified,
+                entry_name(&update.directory),
+            ],
+        )
+        .map_err(database_error)?;
+    if !directory_known {
+        append_grams(grams, &directory, &entry_name(&update.directory))?;
+    }
+
+    Ok(removed_any)
+}
+
+fn append_grams(grams: &mut duckdb::Appender, path: &str, name: &str) -> Result<(), ScanError> {
+    for gram in name_grams(name) {
+        grams
+            .append_row(duckdb::params![gram, path])
+            .map_err(database_error)?;
+    }
+    Ok(())
+}
+
+fn database_error(err: duckdb::Error) -> ScanError {
+    ScanError::Database(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::metadata::ExtendedMetadata;
+    use super::*;
+
+    fn child(path: &Path, is_directory: bool) -> FileInfo {
+        FileInfo {
+            size: 7,
+            modified: Some("2024-01-01T00:00:00+00:00".to_string()),
+            extended: ExtendedMetadata {
+                mode: Some(0o755),
+                ..ExtendedMetadata::default()
+            },
+            ..FileInfo::new(path, is_directory)
+        }
+    }
+
+    fn listed_paths(children: &[FileInfo]) -> BTreeSet<PathBuf> {
+        children.iter().map(|child| child.path.clone()).collect()
+    }
+
+    #[test]
+    fn serves_unchanged_directories_from_cache() {
+        let mut connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_cache_schema(&connection).expect("schema");
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        let listing = vec![
+            child(&root.join("a.txt"), false),
+            child(&root.join("sub"), true),
+        ];
+
+        let mut first = DirectoryCache::load(&connection, root, 2, true).expect("load");
+        let listed = first
+            .list_with(root, || Ok::<_, ScanError>(listing.clone()))
+            .expect("list");
+        assert!(listed
+            .iter()
+            .all(|entry| entry.freshness == Freshness::Live));
+        first.persist(&mut connection).expect("persist");
+
+        let mut second = DirectoryCache::load(&connection, root, 2, true).expect("reload");
+        let cached = second
+            .list_with(root, || -> Result<Vec<FileInfo>, ScanError> {
+                panic!("unchanged directory must not be re-read")
+            })
+            .expect("cached list");
+
+        assert_eq!(listed_paths(&cached), listed_paths(&listing));
+        assert!(cached
+            .iter()
+            .all(|entry| entry.freshness == Freshness::Cached));
+        let sub = cached.iter().find(|entry| entry.is_directory).unwrap();
+        assert_eq!(sub.size, 7);
+        assert_eq!(sub.extended.mode, Some(0o755));
+    }
+
+    #[test]
+    fn rereads_directories_whose_mtime_changed() {
+        let mut connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_cache_schema(&connection).expect("schema");
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        let old_listing = vec![child(&root.join("gone"), true)];
+
+        let mut cache = DirectoryCache::load(&connection, root, 2, true).expect("load");
+        cache
+            .list_with(root, || Ok::<_, ScanError>(old_listing.clone()))
+            .expect("list");
+        cache.persist(&mut connection).expect("persist");
+        connection
+            .execute(
+                "UPDATE files SET listed_modified = 'outdated' WHERE path = ?1",
+                duckdb::params![root.to_string_lossy().to_string()],
+            )
+            .expect("age listing");
+
+        let new_listing = vec![child(&root.join("fresh.txt"), false)];
+        let mut cache = DirectoryCache::load(&connection, root, 2, true).expect("reload");
+        let listed = cache
+            .list_with(root, || Ok::<_, ScanError>(new_listing.clone()))
+            .expect("list");
+        assert!(listed
+            .iter()
+            .all(|entry| entry.freshness == Freshness::Live));
+        cache.persist(&mut connection).expect("persist");
+
+        let cache = DirectoryCache::load(&connection, root, 2, true).expect("reload");
+        let stored = &cache.listings.get(root).expect("listing").children;
+        assert_eq!(listed_paths(stored), listed_paths(&new_listing));
+    }
+
+    #[test]
+    fn keeps_name_grams_in_step_with_listings() {
+        let mut connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_cache_schema(&connection).expect("schema");
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        let grams = |connection: &duckdb::Connection, path: &Path| -> Vec<String> {
+            let mut statement = connection
+                .prepare("SELECT gram FROM file_name_grams WHERE path = ?1 ORDER BY gram")
+                .expect("prepare");
+            statement
+                .query_map(duckdb::params![encode_path(path)], |row| row.get(0))
+                .expect("query")
+                .collect::<Result<_, _>>()
+                .expect("grams")
+        };
+        let listings = [
+            vec![
+                child(&root.join("Old.txt"), false),
+                child(&root.join("kept"), true),
+            ],
+            vec![
+                child(&root.join("kept"), true),
+                child(&root.join("new.md"), false),
+            ],
+        ];
+
+        for listing in listings {
+            let mut cache = DirectoryCache::load(&connection, root, 1, false).expect("load");
+            cache
+                .list_with(root, || Ok::<_, ScanError>(listing.clone()))
+                .expect("list");
+            cache.persist(&mut connection).expect("persist");
+        }
+
+        assert!(grams(&connection, &root.join("Old.txt")).is_empty());
+        assert_eq!(grams(&connection, &root.join("kept")), vec!["ept", "kep"]);
+        assert_eq!(
+            grams(&connection, &root.join("new.md")),
+            vec![".md", "ew.", "new", "w.m"]
+        );
+        let root_name = entry_name(root);
+        assert_eq!(
+            grams(&connection, root),
+            name_grams(&root_name).into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn bypasses_cache_when_reads_are_disabled() {
+        let mut connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_cache_schema(&connection).expect("schema");
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        let listing = vec![child(&root.join("a.txt"), false)];
+
+        let mut cache = DirectoryCache::load(&connection, root, 1, true).expect("load");
+        cache
+            .list_with(root, || Ok::<_, ScanError>(listing.clone()))
+            .expect("list");
+        cache.persist(&mut connection).expect("persist");
+
+        let mut uncached = DirectoryCache::load(&connection, root, 1, false).expect("load");
+        let mut reread = false;
+        uncached
+            .list_with(root, || {
+                reread = true;
+                Ok::<_, ScanError>(listing.clone())
+            })
+            .expect("list");
+        assert!(reread);
+    }
+}
@@ -0,0 +1,577 @@
+use super::cache::{cached_file_info, file_info_columns, listing_only};
+use super::options::{parse_timestamp, EntryKindFilter};
+use super::search::apply_tags;
+use super::{FileInfo, ScanError};
+use crate::path_codec::{decode_path, encode_path};
+use crate::path_matching::PathMatchingRules;
+use crate::tag_query::TagQuery;
+use crate::tagging::descendant_like_pattern;
+use duckdb::types::Value;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: usize = 500;
+
+// Lowercased text after the last dot of a file name; empty when there is
+// none, including for dotfiles such as `.bashrc`
+const EXTENSION: &str = "lower(regexp_extract(f.name, '^.+\\.([^.]+)$', 1))";
+
+const SEPARATOR: &str = std::path::MAIN_SEPARATOR_STR;
+
+// Temporary tables holding one evaluation; the connection lock keeps them private
+const TEMP_TABLES: [&str; 3] = ["facet_candidates", "facet_candidate_tags", "facet_matches"];
+
+// Filters accepted by `filter_entries`; every field is optional on the wire.
+// Attribute filters apply to every indexed entry, directories included.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FacetFilter {
+    // Only entries at or below this path
+    pub root: Option<String>,
+    // Saved-search syntax, matched against own and inherited tags
+    pub tags: Option<String>,
+    // Without the dot, compared case-insensitively; "" selects files without one
+    pub extensions: Vec<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub modified_after: Option<String>,
+    pub modified_before: Option<String>,
+    pub entry_kind: EntryKindFilter,
+    // `true` keeps only symlinks, `false` leaves them out
+    pub symlinks: Option<bool>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FacetCount {
+    value: String,
+    count: u64,
+}
+
+// Breakdown of every matching entry, not just the returned page
+#[derive(Debug, Default, Serialize)]
+pub struct Facets {
+    tags: Vec<FacetCount>,
+    extensions: Vec<FacetCount>,
+    files: u64,
+    directories: u64,
+    symlinks: u64,
+}
+
+#[derive(Serialize)]
+pub struct FilterResult {
+    entries: Vec<FileInfo>,
+    total: u64,
+    facets: Facets,
+}
+
+pub(super) fn filter_entries(
+    connection: &duckdb::Connection,
+    filter: &FacetFilter,
+) -> Result<FilterResult, ScanError> {
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 {
+        return Err(ScanError::InvalidOptions(
+            "limit must be greater than zero".into(),
+        ));
+    }
+    let query = filter
+        .tags
+        .as_deref()
+        .filter(|tags| !tags.trim().is_empty())
+        .map(TagQuery::parse)
+        .transpose()
+        .map_err(|err| ScanError::InvalidOptions(format!("Invalid tag query: {err}")))?;
+
+    let result = evaluate(connection, filter, query.as_ref(), limit);
+    for table in TEMP_TABLES {
+        let _ = connection.execute(&format!("DROP TABLE IF EXISTS {table}"), []);
+    }
+    result
+}
+
+fn evaluate(
+    connection: &duckdb::Connection,
+    filter: &FacetFilter,
+    query: Option<&TagQuery>,
+    limit: usize,
+) -> Result<FilterResult, ScanError> {
+    let (conditions, params) = attribute_conditions(filter)?;
+    execute(
+        connection,
+        &format!(
+            "
+            CREATE OR REPLACE TEMP TABLE facet_candidates AS
+            SELECT {columns},
+                   CASE WHEN f.is_directory THEN NULL ELSE {EXTENSION} END AS extension
+            FROM files f
+            WHERE {conditions}
+            ",
+            columns = file_info_columns("f"),
+            conditions = conditions.join(" AND "),
+        ),
+        params,
+    )?;
+
+    // Own tags join on the path; inherited ones on a range that holds exactly
+    // the paths below the tagged one, which DuckDB can run as a range join.
+    // Once any root folds, both join on the loosely folded spelling and the
+    // candidates are narrowed down to what each root's folding allows.
+    let rules =
+        PathMatchingRules::load(connection).map_err(|err| ScanError::Database(err.to_string()))?;
+    let column = rules.column();
+    let candidate = rules.parameter("c.path");
+    let mut params = vec![Value::Text(SEPARATOR.to_string())];
+    let same_path = folded_relation(&rules, &mut params, |candidate, tagged| {
+        format!("{candidate} = {tagged}")
+    });
+    let below = folded_relation(&rules, &mut params, |candidate, tagged| {
+        format!("starts_with({candidate}, rtrim({tagged}, ?1) || ?1)")
+    });
+    execute(
+        connection,
+        &format!(
+            "
+            CREATE OR REPLACE TEMP TABLE facet_candidate_tags AS
+            SELECT c.path, t.tag
+            FROM facet_candidates c JOIN path_tags t ON {candidate} = t.{column}
+            WHERE {same_path}
+            UNION
+            SELECT c.path, t.tag
+            FROM facet_candidates c
+            JOIN (
+                SELECT DISTINCT path, rtrim({column}, ?1) || ?1 AS prefix, tag FROM path_tags
+            ) t ON {candidate} > t.prefix AND {candidate} < t.prefix || chr(1114111)
+            WHERE {below}
+            "
+        ),
+        params,
+    )?;
+
+    let mut params = Vec::new();
+    let predicate = query
+        .map(|query| tag_predicate(query, &mut params))
+        .unwrap_or_else(|| "TRUE".into());
+    execute(
+        connection,
+        &format!(
+            "CREATE OR REPLACE TEMP TABLE facet_matches AS
+             SELECT * FROM facet_candidates c WHERE {predicate}"
+        ),
+        params,
+    )?;
+
+    let mut facets = Facets::default();
+    let total = {
+        let mut statement = connection
+            .prepare(
+                "
+                SELECT count(*),
+                       count(*) FILTER (WHERE NOT is_directory),
+                       count(*) FILTER (WHERE is_directory),
+                       count(*) FILTER (WHERE is_symlink)
+                FROM facet_matches
+                ",
+            )
+            .map_err(database_error)?;
+        let mut rows = statement.query([]).map_err(database_error)?;
+        let row = rows
+            .next()
+            .map_err(database_error)?
+            .ok_or_else(|| ScanError::Database("Facet count returned no rows".into()))?;
+        facets.files = row.get(1).map_err(database_error)?;
+        facets.directories = row.get(2).map_err(database_error)?;
+        facets.symlinks = row.get(3).map_err(database_error)?;
+        row.get(0).map_err(database_error)?
+    };
+    facets.tags = facet_counts(
+        connection,
+        "
+        SELECT tag, count(*) FROM facet_candidate_tags
+        WHERE path IN (SELECT path FROM facet_matches)
+        GROUP BY tag ORDER BY count(*) DESC, tag
+        ",
+    )?;
+    facets.extensions = facet_counts(
+        connection,
+        "
+        SELECT extension, count(*) FROM facet_matches
+        WHERE extension IS NOT NULL
+        GROUP BY extension ORDER BY count(*) DESC, extension
+        ",
+    )?;
+
+    let sql = format!(
+        "SELECT {columns} FROM facet_matches m ORDER BY m.path LIMIT ?1",
+        columns = file_info_columns("m"),
+    );
+    let mut statement = connection.prepare(&sql).map_err(database_error)?;
+    let mut rows = statement
+        .query(duckdb::params![limit as i64])
+        .map_err(database_error)?;
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next().map_err(database_error)? {
+        entries.push(cached_file_info(row, 0)?);
+    }
+    apply_tags(connection, &mut entries)?;
+
+    Ok(FilterResult {
+        entries,
+        total,
+        facets,
+    })
+}
+
+// SQL condition relating `c.path` to the tagged `t.path` under the folding of
+// the deepest root holding `c.path`; `relation` receives both operands folded
+// for that root. Without rules the join itself is exact, so nothing is left
+// to check.
+fn folded_relation(
+    rules: &PathMatchingRules,
+    params: &mut Vec<Value>,
+    relation: impl Fn(&str, &str) -> String,
+) -> String {
+    if rules.is_empty() {
+        return "TRUE".into();
+    }
+
+    let mut arms = String::new();
+    for (root, matching) in rules.roots() {
+        let root = encode_path(root);
+        let below = format!("{}{SEPARATOR}", root.trim_end_matches(SEPARATOR));
+        params.push(Value::Text(root));
+        let root = matching.fold_sql(&format!("?{}", params.len()));
+        params.push(Value::Text(below));
+        let below = matching.fold_sql(&format!("?{}", params.len()));
+        let candidate = matching.fold_sql("c.path");
+        arms.push_str(&format!(
+            " WHEN {candidate} = {root} OR starts_with({candidate}, {below}) THEN {}",
+            relation(&candidate, &matching.fold_sql("t.path"))
+        ));
+    }
+    format!("CASE{arms} ELSE {} END", relation("c.path", "t.path"))
+}
+
+fn attribute_conditions(filter: &FacetFilter) -> Result<(Vec<String>, Vec<Value>), ScanError> {
+    if let (Some(min), Some(max)) = (filter.min_size, filter.max_size) {
+        if min > max {
+            return Err(ScanError::InvalidOptions(format!(
+                "min_size ({min}) is greater than max_size ({max})"
+            )));
+        }
+    }
+
+    let mut conditions = vec![format!("NOT {}", listing_only("f"))];
+    let mut params = Vec::new();
+    let mut placeholder = |value: Value| {
+        params.push(value);
+        format!("?{}", params.len())
+    };
+
+    if let Some(root) = filter.root.as_deref() {
+        let root = encode_path(&decode_path(root));
+        let below = placeholder(Value::Text(descendant_like_pattern(&root)));
+        let exact = placeholder(Value::Text(root));
+        conditions.push(format!(
+            "(f.path = {exact} OR f.path LIKE {below} ESCAPE '\\')"
+        ));
+    }
+
+    if !filter.extensions.is_empty() {
+        let extensions: Vec<String> = filter
+            .extensions
+            .iter()
+            .map(|extension| {
+                let extension = extension.trim().trim_start_matches('.').to_lowercase();
+                placeholder(Value::Text(extension))
+            })
+            .collect();
+        conditions.push(format!(
+            "NOT f.is_directory AND {EXTENSION} IN ({})",
+            extensions.join(", ")
+        ));
+    }
+
+    if let Some(min) = filter.min_size {
+        conditions.push(format!("f.size >= {}", placeholder(Value::UBigInt(min))));
+    }
+    if let Some(max) = filter.max_size {
+        conditions.push(format!("f.size <= {}", placeholder(Value::UBigInt(max))));
+    }
+
+    let modified = "TRY_CAST(f.modified AS TIMESTAMPTZ)";
+    if let Some(after) = parse_timestamp("modified_after", &filter.modified_after)? {
+        let after = placeholder(Value::Text(after.to_rfc3339()));
+        conditions.push(format!("{modified} >= CAST({after} AS TIMESTAMPTZ)"));
+    }
+    if let Some(before) = parse_timestamp("modified_before", &filter.modified_before)? {
+        let before = placeholder(Value::Text(before.to_rfc3339()));
+        conditions.push(format!("{modified} <= CAST({before} AS TIMESTAMPTZ)"));
+    }
+
+    match filter.entry_kind {
+        EntryKindFilter::All => {}
+        EntryKindFilter::FilesOnly => conditions.push("NOT f.is_directory".into()),
+        EntryKindFilter::DirectoriesOnly => conditions.push("f.is_directory".into()),
+    }
+    match filter.symlinks {
+        Some(true) => conditions.push("f.is_symlink".into()),
+        Some(false) => conditions.push("NOT f.is_symlink".into()),
+        None => {}
+    }
+
+    Ok((conditions, params))
+}
+
+// SQL condition on a `facet_candidates c` row; placeholders are numbered
+// from the end of `params`
+fn tag_predicate(query: &TagQuery, params: &mut Vec<Value>) -> String {
+    let combine = |terms: &[TagQuery], operator: &str, params: &mut Vec<Value>| {
+        let terms: Vec<String> = terms
+            .iter()
+            .map(|term| tag_predicate(term, params))
+            .collect();
+        format!("({})", terms.join(operator))
+    };
+
+    match query {
+        TagQuery::Tag(tag) => {
+            params.push(Value::Text(tag.clone()));
+            format!(
+                "EXISTS (SELECT 1 FROM facet_candidate_tags t WHERE t.path = c.path AND t.tag = ?{})",
+                params.len()
+            )
+        }
+        TagQuery::Not(inner) => format!("NOT {}", tag_predicate(inner, params)),
+        TagQuery::And(terms) => combine(terms, " AND ", params),
+        TagQuery::Or(terms) => combine(terms, " OR ", params),
+    }
+}
+
+fn facet_counts(connection: &duckdb::Connection, sql: &str) -> Result<Vec<FacetCount>, ScanError> {
+    let mut statement = connection.prepare(sql).map_err(database_error)?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(FacetCount {
+                value: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })
+        .map_err(database_error)?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(database_error)
+}
+
+fn execute(
+    connection: &duckdb::Connection,
+    sql: &str,
+    params: Vec<Value>,
+) -> Result<(), ScanError> {
+    connection
+        .execute(sql, duckdb::params_from_iter(params))
+        .map(|_| ())
+        .map_err(database_error)
+}
+
+fn database_error(err: duckdb::Error) -> ScanError {
+    ScanError::Database(err.to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::super::budget::ScanBudget;
+    use super::super::cache::{ensure_cache_schema, DirectoryCache};
+    use super::super::options::{ScanFilter, ScanOptions};
+    use super::super::platform;
+    use super::*;
+    use crate::path_matching::PathMatching;
+    use crate::storage::TagStorage;
+    use std::fs;
+
+    fn counts(facets: &[FacetCount]) -> Vec<(&str, u64)> {
+        facets
+            .iter()
+            .map(|facet| (facet.value.as_str(), facet.count))
+            .collect()
+    }
+
+    #[test]
+    fn filters_by_tags_and_attributes_and_counts_facets() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = fs::canonicalize(temp.path()).expect("canonical temp dir");
+        let finance = root.join("finance");
+        fs::create_dir_all(finance.join("2019")).expect("create dirs");
+        fs::write(finance.join("a.PDF"), "1234").expect("write");
+        fs::write(finance.join("b.pdf"), "12345678").expect("write");
+        fs::write(finance.join("notes.txt"), "12").expect("write");
+        fs::write(finance.join("2019").join("old.pdf"), "1234").expect("write");
+        fs::write(root.join("readme"), "1").expect("write");
+
+        let mut connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_cache_schema(&connection).expect("cache schema");
+        crate::tagging::ensure_schema(&connection).expect("tag schema");
+        let filter = ScanFilter::new(&root, &ScanOptions::default()).unwrap();
+        let mut cache = DirectoryCache::load(&connection, &root, 3, true).expect("load");
+        platform::collect_entries(
+            &root,
+            3,
+            &filter,
+            &mut cache,
+            &mut Vec::new(),
+            &mut ScanBudget::unlimited(),
+        )
+        .expect("scan");
+        cache.persist(&mut connection).expect("persist");
+        for (path, tag) in [
+            (finance.clone(), "finance"),
+            (finance.join("2019"), "archived"),
+            (finance.join("a.PDF"), "status=open"),
+        ] {
+            connection
+                .execute(
+                    "INSERT INTO path_tags (path, tag, path_depth) VALUES (?1, ?2, 0)",
+                    duckdb::params![encode_path(&path), tag],
+                )
+                .expect("tag");
+        }
+
+        let everything = filter_entries(
+            &connection,
+            &FacetFilter {
+                root: Some(encode_path(&root)),
+                ..FacetFilter::default()
+            },
+        )
+        .expect("filter");
+        assert_eq!(everything.total, 7);
+        assert_eq!(
+            (everything.facets.files, everything.facets.directories),
+            (5, 2)
+        );
+        assert_eq!(
+            counts(&everything.facets.extensions),
+            vec![("pdf", 3), ("", 1), ("txt", 1)]
+        );
+        assert_eq!(
+            counts(&everything.facets.tags),
+            vec![("finance", 6), ("archived", 2), ("status=open", 1)]
+        );
+
+        let invoices = filter_entries(
+            &connection,
+            &FacetFilter {
+                tags: Some("finance AND NOT archived".into()),
+                extensions: vec![".pdf".into()],
+                min_size: Some(3),
+                max_size: Some(6),
+                modified_after: Some("2000-01-01T00:00:00Z".into()),
+                ..FacetFilter::default()
+            },
+        )
+        .expect("filter");
+        assert_eq!(invoices.total, 1);
+        assert_eq!(invoices.entries[0].path, finance.join("a.PDF"));
+        assert_eq!(invoices.entries[0].own_tags, vec!["status=open"]);
+        assert_eq!(invoices.entries[0].inherited_tags, vec!["finance"]);
+        assert_eq!(
+            counts(&invoices.facets.tags),
+            vec![("finance", 1), ("status=open", 1)]
+        );
+
+        let future = filter_entries(
+            &connection,
+            &FacetFilter {
+                modified_after: Some("2999-01-01T00:00:00Z".into()),
+                ..FacetFilter::default()
+            },
+        )
+        .expect("filter");
+        assert_eq!(future.total, 0);
+        assert!(matches!(
+            filter_entries(
+                &connection,
+                &FacetFilter {
+                    tags: Some("finance AND".into()),
+                    ..FacetFilter::default()
+                },
+            ),
+            Err(ScanError::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn counts_tags_stored_under_other_spellings_on_folding_roots() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = fs::canonicalize(temp.path()).expect("canonical temp dir");
+        let folded = root.join("folded");
+        let exact = root.join("exact");
+        fs::create_dir_all(folded.join("Photos")).expect("create dirs");
+        fs::create_dir_all(&exact).expect("create dirs");
+        fs::write(folded.join("Photos").join("Beach.JPG"), "1").expect("write");
+        fs::write(exact.join("Notes.txt"), "1").expect("write");
+
+        let mut connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_cache_schema(&connection).expect("cache schema");
+        crate::tagging::ensure_schema(&connection).expect("tag schema");
+        let filter = ScanFilter::new(&root, &ScanOptions::default()).unwrap();
+        let mut cache = DirectoryCache::load(&connection, &root, 3, true).expect("load");
+        platform::collect_entries(
+            &root,
+            3,
+            &filter,
+            &mut cache,
+            &mut Vec::new(),
+            &mut ScanBudget::unlimited(),
+        )
+        .expect("scan");
+        cache.persist(&mut connection).expect("persist");
+        connection
+            .set_path_matching(
+                &encode_path(&folded),
+                PathMatching {
+                    case_insensitive: true,
+                    unicode_normalized: false,
+                },
+            )
+            .expect("folding root");
+        for (path, tag) in [
+            (folded.join("photos"), "trip"),
+            (folded.join("photos").join("beach.jpg"), "sea"),
+            (exact.join("notes.txt"), "todo"),
+        ] {
+            connection
+                .execute(
+                    "INSERT INTO path_tags (path, tag, path_depth, folded_path)
+                     VALUES (?1, ?2, 0, nfc_normalize(lower(?1)))",
+                    duckdb::params![encode_path(&path), tag],
+                )
+                .expect("tag");
+        }
+
+        let everything = filter_entries(
+            &connection,
+            &FacetFilter {
+                root: Some(encode_path(&root)),
+                ..FacetFilter::default()
+            },
+        )
+        .expect("filter");
+        assert_eq!(
+            counts(&everything.facets.tags),
+            vec![("trip", 2), ("sea", 1)]
+        );
+
+        let beach = filter_entries(
+            &connection,
+            &FacetFilter {
+                tags: Some("sea".into()),
+                ..FacetFilter::default()
+            },
+        )
+        .expect("filter");
+        assert_eq!(beach.total, 1);
+        assert_eq!(
+            beach.entries[0].path,
+            folded.join("Photos").join("Beach.JPG")
+        );
+    }
+}
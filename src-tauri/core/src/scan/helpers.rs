@@ -1,5 +1,7 @@
+use crate::path_codec::encode_os_str;
 use chrono::{DateTime, Utc};
-use std::path::{Component, Path};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
 use std::time::SystemTime;
 
 pub(crate) fn collect_path_hierarchy(path: &Path) -> Vec<String> {
@@ -9,7 +11,7 @@ pub(crate) fn collect_path_hierarchy(path: &Path) -> Vec<String> {
     for component in path.components() {
         match component {
             Component::Prefix(prefix_component) => {
-                pending_prefix = Some(prefix_component.as_os_str().to_string_lossy().to_string());
+                pending_prefix = Some(encode_os_str(prefix_component.as_os_str()));
             }
             Component::RootDir => {
                 if let Some(prefix) = pending_prefix.take() {
@@ -22,7 +24,7 @@ pub(crate) fn collect_path_hierarchy(path: &Path) -> Vec<String> {
                 if let Some(prefix) = pending_prefix.take() {
                     segments.push(prefix);
                 }
-                segments.push(os_str.to_string_lossy().to_string());
+                segments.push(encode_os_str(os_str));
             }
             Component::CurDir => {}
             Component::ParentDir => segments.push(String::from("..")),
@@ -39,3 +41,10 @@ pub(crate) fn collect_path_hierarchy(path: &Path) -> Vec<String> {
 pub(crate) fn system_time_to_rfc3339(time: SystemTime) -> String {
     DateTime::<Utc>::from(time).to_rfc3339()
 }
+
+// Returns the raw link target and whether following it fails
+pub(crate) fn read_symlink_details(path: &Path) -> (Option<PathBuf>, bool) {
+    let target = fs::read_link(path).ok();
+    let is_broken = fs::metadata(path).is_err();
+    (target, is_broken)
+}
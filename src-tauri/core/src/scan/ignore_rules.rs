@@ -0,0 +1,167 @@
+use super::FileInfo;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const GIT_IGNORE_FILE_NAME: &str = ".gitignore";
+pub(crate) const TAG_IGNORE_FILE_NAME: &str = ".tagignore";
+
+// Ignore matchers gathered while walking, keyed by the directory that owns them
+pub(crate) struct IgnoreRules {
+    global: Gitignore,
+    per_directory: HashMap<PathBuf, Gitignore>,
+    ignored_children: HashMap<PathBuf, u64>,
+}
+
+impl IgnoreRules {
+    pub(crate) fn new() -> Self {
+        let (global, err) = Gitignore::global();
+        if let Some(err) = err {
+            warn!("Failed to load global git excludes: {}", err);
+        }
+
+        Self {
+            global,
+            per_directory: HashMap::new(),
+            ignored_children: HashMap::new(),
+        }
+    }
+
+    // Reads `.gitignore` and `.tagignore` from a directory; `.tagignore` wins on conflicts
+    pub(crate) fn load_directory(&mut self, dir: &Path) {
+        if self.per_directory.contains_key(dir) {
+            return;
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found_any = false;
+        for file_name in [GIT_IGNORE_FILE_NAME, TAG_IGNORE_FILE_NAME] {
+            let candidate = dir.join(file_name);
+            if !candidate.is_file() {
+                continue;
+            }
+            found_any = true;
+            if let Some(err) = builder.add(&candidate) {
+                warn!("Failed to parse ignore file {:?}: {}", candidate, err);
+            }
+        }
+
+        if !found_any {
+            return;
+        }
+
+        match builder.build() {
+            Ok(matcher) => {
+                debug!("Loaded ignore rules for {:?}", dir);
+                self.per_directory.insert(dir.to_path_buf(), matcher);
+            }
+            Err(err) => warn!("Failed to build ignore rules for {:?}: {}", dir, err),
+        }
+    }
+
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for ancestor in path.ancestors().skip(1) {
+            if let Some(matcher) = self.per_directory.get(ancestor) {
+                match matcher.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+        }
+
+        self.global.matched(path, is_dir).is_ignore()
+    }
+
+    // Like `is_ignored`, but records the hit against the parent directory
+    pub(crate) fn filter_out(&mut self, path: &Path, is_dir: bool) -> bool {
+        if !self.is_ignored(path, is_dir) {
+            return false;
+        }
+
+        if let Some(parent) = path.parent() {
+            *self
+                .ignored_children
+                .entry(parent.to_path_buf())
+                .or_default() += 1;
+        }
+        true
+    }
+
+    pub(crate) fn apply_ignored_counts(&self, entries: &mut [FileInfo]) {
+        for entry in entries.iter_mut() {
+            if let Some(count) = self.ignored_children.get(&entry.path) {
+                entry.ignored_children = *count;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn ignores_entries_listed_in_gitignore() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        fs::write(dir.path().join(GIT_IGNORE_FILE_NAME), "target/\n*.log\n").expect("write");
+
+        let mut rules = IgnoreRules::new();
+        rules.load_directory(dir.path());
+
+        assert!(rules.is_ignored(&dir.path().join("target"), true));
+        assert!(rules.is_ignored(&dir.path().join("debug.log"), false));
+        assert!(!rules.is_ignored(&dir.path().join("src"), true));
+        // A directory-only pattern must not hide a plain file with the same name
+        assert!(!rules.is_ignored(&dir.path().join("target"), false));
+    }
+
+    #[test]
+    fn tagignore_overrides_gitignore() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        fs::write(dir.path().join(GIT_IGNORE_FILE_NAME), "*.log\n").expect("write");
+        fs::write(dir.path().join(TAG_IGNORE_FILE_NAME), "!keep.log\nscratch\n").expect("write");
+
+        let mut rules = IgnoreRules::new();
+        rules.load_directory(dir.path());
+
+        assert!(rules.is_ignored(&dir.path().join("other.log"), false));
+        assert!(!rules.is_ignored(&dir.path().join("keep.log"), false));
+        assert!(rules.is_ignored(&dir.path().join("scratch"), true));
+    }
+
+    #[test]
+    fn nested_rules_take_precedence_over_parent_rules() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested).expect("create nested");
+        fs::write(dir.path().join(GIT_IGNORE_FILE_NAME), "*.tmp\n").expect("write");
+        fs::write(nested.join(GIT_IGNORE_FILE_NAME), "!wanted.tmp\n").expect("write");
+
+        let mut rules = IgnoreRules::new();
+        rules.load_directory(dir.path());
+        rules.load_directory(&nested);
+
+        assert!(rules.is_ignored(&dir.path().join("a.tmp"), false));
+        assert!(rules.is_ignored(&nested.join("b.tmp"), false));
+        assert!(!rules.is_ignored(&nested.join("wanted.tmp"), false));
+    }
+
+    #[test]
+    fn filter_out_counts_hidden_children_per_parent() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        fs::write(dir.path().join(GIT_IGNORE_FILE_NAME), "node_modules/\n*.log\n").expect("write");
+
+        let mut rules = IgnoreRules::new();
+        rules.load_directory(dir.path());
+
+        assert!(rules.filter_out(&dir.path().join("node_modules"), true));
+        assert!(rules.filter_out(&dir.path().join("a.log"), false));
+        assert!(!rules.filter_out(&dir.path().join("main.rs"), false));
+
+        assert_eq!(rules.ignored_children.get(dir.path()), Some(&2));
+    }
+}
@@ -0,0 +1,161 @@
+use super::helpers::system_time_to_rfc3339;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// Kind of the entry itself; links are reported as `Symlink` here even though
+// `FileInfo::is_directory` follows them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+    #[default]
+    Unknown,
+}
+
+// Metadata beyond what the tree needs, kept for audits and UI filtering.
+// Fields the platform or filesystem cannot provide are left as `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtendedMetadata {
+    pub file_type: FileType,
+    pub created: Option<String>,
+    pub accessed: Option<String>,
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub inode: Option<u64>,
+    pub device: Option<u64>,
+    pub hard_links: Option<u64>,
+}
+
+impl ExtendedMetadata {
+    // Expects `symlink_metadata`, so links describe themselves rather than their target
+    pub(crate) fn from_metadata(metadata: &fs::Metadata) -> Self {
+        let extended = Self {
+            file_type: file_type(&metadata.file_type()),
+            created: metadata.created().ok().map(system_time_to_rfc3339),
+            accessed: metadata.accessed().ok().map(system_time_to_rfc3339),
+            ..Self::default()
+        };
+
+        #[cfg(unix)]
+        let extended = extended.with_unix_fields(metadata);
+
+        extended
+    }
+
+    #[cfg(unix)]
+    fn with_unix_fields(self, metadata: &fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Self {
+            mode: Some(metadata.mode()),
+            uid: Some(metadata.uid()),
+            gid: Some(metadata.gid()),
+            owner: owner_name(metadata.uid()),
+            group: group_name(metadata.gid()),
+            inode: Some(metadata.ino()),
+            device: Some(metadata.dev()),
+            hard_links: Some(metadata.nlink()),
+            ..self
+        }
+    }
+}
+
+fn file_type(file_type: &fs::FileType) -> FileType {
+    if file_type.is_symlink() {
+        return FileType::Symlink;
+    }
+    if file_type.is_dir() {
+        return FileType::Directory;
+    }
+    if file_type.is_file() {
+        return FileType::Regular;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_fifo() {
+            return FileType::Fifo;
+        }
+        if file_type.is_socket() {
+            return FileType::Socket;
+        }
+        if file_type.is_block_device() {
+            return FileType::BlockDevice;
+        }
+        if file_type.is_char_device() {
+            return FileType::CharDevice;
+        }
+    }
+
+    FileType::Unknown
+}
+
+#[cfg(unix)]
+thread_local! {
+    // Every entry of a scan usually shares a handful of owners, so lookups are memoized
+    static USERS: uzers::UsersCache = uzers::UsersCache::new();
+}
+
+#[cfg(unix)]
+fn owner_name(uid: u32) -> Option<String> {
+    use uzers::Users;
+
+    USERS.with(|users| {
+        users
+            .get_user_by_uid(uid)
+            .map(|user| user.name().to_string_lossy().into_owned())
+    })
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+    use uzers::Groups;
+
+    USERS.with(|users| {
+        users
+            .get_group_by_gid(gid)
+            .map(|group| group.name().to_string_lossy().into_owned())
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn reads_unix_metadata_for_files_dirs_and_links() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let file = dir.path().join("file.txt");
+        fs::write(&file, b"data").expect("write");
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).expect("chmod");
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&file, &link).expect("symlink");
+
+        let file_meta = ExtendedMetadata::from_metadata(&fs::symlink_metadata(&file).unwrap());
+        assert_eq!(file_meta.file_type, FileType::Regular);
+        assert_eq!(file_meta.mode.map(|mode| mode & 0o777), Some(0o640));
+        assert_eq!(file_meta.hard_links, Some(1));
+        assert!(file_meta.inode.is_some());
+        assert!(file_meta.accessed.is_some());
+
+        let dir_meta = ExtendedMetadata::from_metadata(&fs::symlink_metadata(dir.path()).unwrap());
+        assert_eq!(dir_meta.file_type, FileType::Directory);
+
+        let link_meta = ExtendedMetadata::from_metadata(&fs::symlink_metadata(&link).unwrap());
+        assert_eq!(link_meta.file_type, FileType::Symlink);
+        assert_ne!(link_meta.inode, file_meta.inode);
+    }
+}
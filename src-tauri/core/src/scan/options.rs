@@ -0,0 +1,396 @@
+use super::{FileInfo, ScanError};
+use chrono::{DateTime, Utc};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKindFilter {
+    #[default]
+    All,
+    FilesOnly,
+    DirectoriesOnly,
+}
+
+// Options accepted by the scan commands; every field is optional on the wire
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub show_hidden: bool,
+    pub entry_kind: EntryKindFilter,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub modified_after: Option<String>,
+    pub modified_before: Option<String>,
+    pub respect_ignore_files: bool,
+    pub follow_symlinks: bool,
+    pub use_cache: bool,
+    pub full_subtree_stats: bool,
+    // Scan budgets; a scan that hits one returns what it has so far
+    pub max_entries: Option<usize>,
+    pub max_entries_per_directory: Option<usize>,
+    pub max_duration_ms: Option<u64>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            show_hidden: true,
+            entry_kind: EntryKindFilter::All,
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            respect_ignore_files: true,
+            follow_symlinks: false,
+            use_cache: true,
+            full_subtree_stats: false,
+            max_entries: None,
+            max_entries_per_directory: None,
+            max_duration_ms: None,
+        }
+    }
+}
+
+// Compiled form of `ScanOptions` shared by the platform collectors.
+//
+// `prunes` runs before an entry is descended into, so excluded and hidden
+// directories are never walked. `accepts` decides whether a visited entry is
+// reported. Directories are kept as containers for accepted files, which is
+// why `retain_matching` drops only the ones left empty by file-level filters.
+pub(crate) struct ScanFilter {
+    root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    show_hidden: bool,
+    entry_kind: EntryKindFilter,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<DateTime<Utc>>,
+    modified_before: Option<DateTime<Utc>>,
+    pub(crate) respect_ignore_files: bool,
+    pub(crate) follow_symlinks: bool,
+    pub(crate) full_subtree_stats: bool,
+}
+
+impl ScanFilter {
+    pub(crate) fn new(root: &Path, options: &ScanOptions) -> Result<Self, ScanError> {
+        if let (Some(min), Some(max)) = (options.min_size, options.max_size) {
+            if min > max {
+                return Err(ScanError::InvalidOptions(format!(
+                    "min_size ({min}) is greater than max_size ({max})"
+                )));
+            }
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            include: build_glob_set(&options.include)?,
+            exclude: build_glob_set(&options.exclude)?,
+            show_hidden: options.show_hidden,
+            entry_kind: options.entry_kind,
+            min_size: options.min_size,
+            max_size: options.max_size,
+            modified_after: parse_timestamp("modified_after", &options.modified_after)?,
+            modified_before: parse_timestamp("modified_before", &options.modified_before)?,
+            respect_ignore_files: options.respect_ignore_files,
+            follow_symlinks: options.follow_symlinks,
+            full_subtree_stats: options.full_subtree_stats,
+        })
+    }
+
+    pub(crate) fn prunes(&self, path: &Path) -> bool {
+        if path == self.root {
+            return false;
+        }
+
+        if !self.show_hidden && is_dotfile(path) {
+            return true;
+        }
+
+        self.exclude
+            .as_ref()
+            .is_some_and(|set| self.glob_matches(set, path))
+    }
+
+    pub(crate) fn accepts(&self, info: &FileInfo) -> bool {
+        if info.path == self.root {
+            return true;
+        }
+
+        // Kept as containers; `retain_matching` drops the ones left empty
+        if info.is_directory {
+            return true;
+        }
+
+        let modified = info
+            .modified
+            .as_deref()
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .map(|value| value.with_timezone(&Utc));
+        self.accepts_file(&info.path, info.size, modified)
+    }
+
+    pub(crate) fn accepts_file(
+        &self,
+        path: &Path,
+        size: u64,
+        modified: Option<DateTime<Utc>>,
+    ) -> bool {
+        if self.entry_kind == EntryKindFilter::DirectoriesOnly {
+            return false;
+        }
+
+        if let Some(include) = self.include.as_ref() {
+            if !self.glob_matches(include, path) {
+                return false;
+            }
+        }
+
+        if self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max)
+        {
+            return false;
+        }
+
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let Some(modified) = modified else {
+                return false;
+            };
+
+            if self.modified_after.is_some_and(|after| modified < after)
+                || self.modified_before.is_some_and(|before| modified > before)
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Whether file-level filters are active, meaning directories only show up
+    // as parents of accepted files
+    fn filters_files(&self) -> bool {
+        self.entry_kind == EntryKindFilter::FilesOnly
+            || self.include.is_some()
+            || self.min_size.is_some()
+            || self.max_size.is_some()
+            || self.modified_after.is_some()
+            || self.modified_before.is_some()
+    }
+
+    pub(crate) fn retain_matching(&self, entries: &mut Vec<FileInfo>) {
+        if !self.filters_files() {
+            return;
+        }
+
+        let mut keep_dirs: HashSet<PathBuf> = HashSet::new();
+        keep_dirs.insert(self.root.clone());
+        for entry in entries.iter().filter(|entry| !entry.is_directory) {
+            for ancestor in entry.path.ancestors().skip(1) {
+                // The root is pre-seeded, so every walk stops there at the latest
+                if !keep_dirs.insert(ancestor.to_path_buf()) {
+                    break;
+                }
+            }
+        }
+
+        entries.retain(|entry| !entry.is_directory || keep_dirs.contains(&entry.path));
+    }
+
+    fn glob_matches(&self, set: &GlobSet, path: &Path) -> bool {
+        if path.file_name().is_some_and(|name| set.is_match(name)) {
+            return true;
+        }
+
+        path.strip_prefix(&self.root)
+            .map(|relative| set.is_match(relative))
+            .unwrap_or(false)
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<Option<GlobSet>, ScanError> {
+    let patterns: Vec<&str> = patterns
+        .iter()
+        .map(|pattern| pattern.trim())
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|err| ScanError::InvalidOptions(format!("Invalid glob {pattern:?}: {err}")))?;
+        builder.add(glob);
+    }
+
+    builder
+        .build()
+        .map(Some)
+        .map_err(|err| ScanError::InvalidOptions(err.to_string()))
+}
+
+pub(super) fn parse_timestamp(
+    field: &str,
+    value: &Option<String>,
+) -> Result<Option<DateTime<Utc>>, ScanError> {
+    value
+        .as_deref()
+        .map(|raw| {
+            DateTime::parse_from_rfc3339(raw)
+                .map(|parsed| parsed.with_timezone(&Utc))
+                .map_err(|err| {
+                    ScanError::InvalidOptions(format!("Invalid {field} timestamp {raw:?}: {err}"))
+                })
+        })
+        .transpose()
+}
+
+fn is_dotfile(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_info(path: &str, is_directory: bool, size: u64, modified: Option<&str>) -> FileInfo {
+        FileInfo {
+            size,
+            modified: modified.map(str::to_string),
+            ..FileInfo::new(Path::new(path), is_directory)
+        }
+    }
+
+    #[test]
+    fn prunes_hidden_and_excluded_entries() {
+        let options = ScanOptions {
+            show_hidden: false,
+            exclude: vec!["target".to_string(), "docs/*.md".to_string()],
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(Path::new("/root"), &options).unwrap();
+
+        assert!(filter.prunes(Path::new("/root/.git")));
+        assert!(filter.prunes(Path::new("/root/target")));
+        assert!(filter.prunes(Path::new("/root/docs/readme.md")));
+        assert!(!filter.prunes(Path::new("/root/src")));
+        assert!(!filter.prunes(Path::new("/root")));
+    }
+
+    #[test]
+    fn accepts_files_within_size_and_time_range() {
+        let options = ScanOptions {
+            min_size: Some(10),
+            max_size: Some(100),
+            modified_after: Some("2024-01-01T00:00:00+00:00".to_string()),
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(Path::new("/root"), &options).unwrap();
+
+        assert!(filter.accepts(&file_info(
+            "/root/a.txt",
+            false,
+            50,
+            Some("2024-06-01T00:00:00+00:00")
+        )));
+        assert!(!filter.accepts(&file_info(
+            "/root/small.txt",
+            false,
+            5,
+            Some("2024-06-01T00:00:00+00:00")
+        )));
+        assert!(!filter.accepts(&file_info(
+            "/root/old.txt",
+            false,
+            50,
+            Some("2023-06-01T00:00:00+00:00")
+        )));
+        assert!(!filter.accepts(&file_info("/root/unknown.txt", false, 50, None)));
+        assert!(filter.accepts(&file_info("/root/dir", true, 0, None)));
+    }
+
+    #[test]
+    fn include_globs_keep_only_parents_of_matches() {
+        let options = ScanOptions {
+            include: vec!["*.rs".to_string()],
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(Path::new("/root"), &options).unwrap();
+
+        let mut entries: Vec<FileInfo> = [
+            file_info("/root", true, 0, None),
+            file_info("/root/src", true, 0, None),
+            file_info("/root/src/main.rs", false, 1, None),
+            file_info("/root/assets", true, 0, None),
+            file_info("/root/readme.md", false, 1, None),
+        ]
+        .into_iter()
+        .filter(|entry| filter.accepts(entry))
+        .collect();
+        filter.retain_matching(&mut entries);
+
+        let paths: Vec<_> = entries.iter().map(|entry| entry.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("/root"),
+                PathBuf::from("/root/src"),
+                PathBuf::from("/root/src/main.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn directories_only_rejects_files() {
+        let options = ScanOptions {
+            entry_kind: EntryKindFilter::DirectoriesOnly,
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(Path::new("/root"), &options).unwrap();
+
+        assert!(filter.accepts(&file_info("/root/dir", true, 0, None)));
+        assert!(!filter.accepts(&file_info("/root/file.txt", false, 0, None)));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let bad_glob = ScanOptions {
+            include: vec!["[".to_string()],
+            ..ScanOptions::default()
+        };
+        assert!(matches!(
+            ScanFilter::new(Path::new("/root"), &bad_glob),
+            Err(ScanError::InvalidOptions(_))
+        ));
+
+        let bad_range = ScanOptions {
+            min_size: Some(10),
+            max_size: Some(1),
+            ..ScanOptions::default()
+        };
+        assert!(matches!(
+            ScanFilter::new(Path::new("/root"), &bad_range),
+            Err(ScanError::InvalidOptions(_))
+        ));
+
+        let bad_time = ScanOptions {
+            modified_before: Some("yesterday".to_string()),
+            ..ScanOptions::default()
+        };
+        assert!(matches!(
+            ScanFilter::new(Path::new("/root"), &bad_time),
+            Err(ScanError::InvalidOptions(_))
+        ));
+    }
+}
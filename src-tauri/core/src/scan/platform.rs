@@ -2,10 +2,10 @@
 mod windows;
 
 #[cfg(target_os = "windows")]
-pub(crate) use windows::collect_entries;
+pub(crate) use windows::{collect_entries, read_entry};
 
 #[cfg(not(target_os = "windows"))]
 mod portable;
 
 #[cfg(not(target_os = "windows"))]
-pub(crate) use portable::collect_entries;
+pub(crate) use portable::{collect_entries, read_entry};
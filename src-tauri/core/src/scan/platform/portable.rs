@@ -0,0 +1,272 @@
+use super::super::budget::ScanBudget;
+use super::super::cache::DirectoryCache;
+use super::super::helpers::{read_symlink_details, system_time_to_rfc3339};
+use super::super::ignore_rules::IgnoreRules;
+use super::super::metadata::ExtendedMetadata;
+use super::super::options::ScanFilter;
+use super::super::stats::{apply_seeded_stats, seed_stats, DirectoryStats};
+use super::super::{FileInfo, Freshness, ScanError, SkippedEntry};
+use crate::xmp_sidecar::{self, SharedStems};
+use crate::{tag_manifest, xdg_tags};
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub(crate) fn collect_entries(
+    root: &Path,
+    max_depth: usize,
+    filter: &ScanFilter,
+    cache: &mut DirectoryCache,
+    skipped: &mut Vec<SkippedEntry>,
+    budget: &mut ScanBudget,
+) -> Result<Vec<FileInfo>, ScanError> {
+    let root_metadata = fs::symlink_metadata(root)
+        .map_err(|e| ScanError::Io(format!("Failed to read metadata for {:?}: {}", root, e)))?;
+    let mut root_info = build_file_info(root, &root_metadata, root_metadata.is_symlink());
+    root_info.sidecar_tags = read_sidecar_tags(&root_info, &SharedStems::next_to(root));
+    root_info.manifest_tags = tag_manifest::read_tags_lenient(root);
+    if root_info.is_broken_symlink {
+        return Err(ScanError::Io(format!(
+            "Scan root is a broken symlink: {:?}",
+            root
+        )));
+    }
+
+    let mut ignore_rules = filter.respect_ignore_files.then(IgnoreRules::new);
+    let mut visited_dirs: HashSet<PathBuf> = HashSet::new();
+    let mut seeded_stats: HashMap<PathBuf, DirectoryStats> = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    if root_info.is_directory {
+        visited_dirs.insert(visited_key(root));
+        queue.push_back((root.to_path_buf(), 0));
+    }
+    let mut entries = vec![root_info];
+
+    while let Some((directory, current_depth)) = queue.pop_front() {
+        // The queue is still drained so every directory left unlisted gets marked
+        if !budget.can_continue(entries.len()) {
+            if current_depth < max_depth {
+                budget.mark_truncated(&directory);
+            }
+            seeded_stats.insert(directory, DirectoryStats::partial());
+            continue;
+        }
+
+        if current_depth >= max_depth {
+            let stats = seed_stats(&directory, filter, ignore_rules.as_mut(), budget);
+            seeded_stats.insert(directory, stats);
+            continue;
+        }
+
+        if let Some(rules) = ignore_rules.as_mut() {
+            rules.load_directory(&directory);
+        }
+
+        let mut unreadable = Vec::new();
+        let mut timed_out = false;
+        let listing = cache.list_with(&directory, || {
+            list_directory(&directory, &mut unreadable, budget, &mut timed_out)
+        });
+        if !unreadable.is_empty() {
+            cache.discard(&directory);
+            skipped.append(&mut unreadable);
+            seeded_stats.insert(directory.clone(), DirectoryStats::partial());
+        }
+        if timed_out {
+            cache.discard(&directory);
+            budget.mark_truncated(&directory);
+            seeded_stats.insert(directory.clone(), DirectoryStats::partial());
+        }
+        let mut children = match listing {
+            Ok(children) => children,
+            Err(e) => {
+                warn!("Skipping unreadable directory {:?}: {}", directory, e);
+                skipped.push(SkippedEntry::from_io(&directory, &e));
+                seeded_stats.insert(directory, DirectoryStats::partial());
+                continue;
+            }
+        };
+
+        refresh_cached_tags(&directory, &mut children);
+
+        // A stable order decides which children survive a per-directory cap
+        children.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut reported = 0;
+        for child in children {
+            if filter.prunes(&child.path) {
+                continue;
+            }
+            if ignore_rules
+                .as_mut()
+                .is_some_and(|rules| rules.filter_out(&child.path, child.is_directory))
+            {
+                continue;
+            }
+
+            let accepted = filter.accepts(&child);
+            if accepted && !budget.admits(entries.len(), reported) {
+                budget.mark_truncated(&directory);
+                seeded_stats.insert(directory.clone(), DirectoryStats::partial());
+                break;
+            }
+
+            if child.is_directory
+                && should_descend(&child, filter.follow_symlinks, &mut visited_dirs)
+            {
+                queue.push_back((child.path.clone(), current_depth + 1));
+            }
+
+            if accepted {
+                reported += 1;
+                entries.push(child);
+            }
+        }
+    }
+
+    if let Some(rules) = ignore_rules.as_ref() {
+        rules.apply_ignored_counts(&mut entries);
+    }
+    apply_seeded_stats(&mut entries, &seeded_stats);
+
+    Ok(entries)
+}
+
+// Links are only entered when following is enabled. Every directory is then
+// tracked by its canonical path so cycles and repeated targets are walked once.
+fn should_descend(
+    info: &FileInfo,
+    follow_symlinks: bool,
+    visited_dirs: &mut HashSet<PathBuf>,
+) -> bool {
+    if info.is_symlink && !follow_symlinks {
+        return false;
+    }
+    if !follow_symlinks {
+        return true;
+    }
+
+    if visited_dirs.insert(visited_key(&info.path)) {
+        return true;
+    }
+
+    debug!(
+        "Not descending into already visited directory: {:?}",
+        info.path
+    );
+    false
+}
+
+fn visited_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Entries that cannot be read are reported through `unreadable`; only a
+// failure to open the directory itself is returned as an error. Running out of
+// scan time ends the listing early and sets `timed_out`.
+fn list_directory(
+    directory: &Path,
+    unreadable: &mut Vec<SkippedEntry>,
+    budget: &ScanBudget,
+    timed_out: &mut bool,
+) -> io::Result<Vec<FileInfo>> {
+    let mut children = Vec::new();
+
+    for entry in fs::read_dir(directory)? {
+        if budget.out_of_time() {
+            *timed_out = true;
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Skipping unreadable entry in {:?}: {}", directory, e);
+                unreadable.push(SkippedEntry::from_io(directory, &e));
+                continue;
+            }
+        };
+
+        let path = entry.path();
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) => {
+                children.push(build_file_info(&path, &metadata, metadata.is_symlink()));
+            }
+            Err(e) => {
+                warn!("Skipping entry with unreadable metadata {:?}: {}", path, e);
+                unreadable.push(SkippedEntry::from_io(&path, &e));
+            }
+        }
+    }
+
+    let shared = SharedStems::from_listing(children.iter().map(|child| child.path.as_path()));
+    for child in children.iter_mut() {
+        child.sidecar_tags = read_sidecar_tags(child, &shared);
+    }
+    tag_manifest::apply_manifest(directory, &mut children);
+    Ok(children)
+}
+
+// Tags kept in xattrs, sidecars and manifests change without touching the
+// directory's mtime, so entries served from the scan cache read them again
+fn refresh_cached_tags(directory: &Path, children: &mut [FileInfo]) {
+    let shared = SharedStems::from_listing(children.iter().map(|child| child.path.as_path()));
+    let mut refreshed = false;
+    for child in children
+        .iter_mut()
+        .filter(|child| child.freshness == Freshness::Cached)
+    {
+        child.platform_tags = xdg_tags::read_tags_lenient(&child.path);
+        child.sidecar_tags = read_sidecar_tags(child, &shared);
+        child.manifest_tags = Vec::new();
+        refreshed = true;
+    }
+    if refreshed {
+        tag_manifest::apply_manifest(directory, children);
+    }
+}
+
+fn read_sidecar_tags(info: &FileInfo, shared: &SharedStems) -> Vec<String> {
+    if info.is_directory {
+        Vec::new()
+    } else {
+        xmp_sidecar::read_tags_lenient(&info.path, shared)
+    }
+}
+
+// Reads one entry outside a directory walk, e.g. a search result the scan
+// cache has not seen yet
+pub(crate) fn read_entry(path: &Path) -> io::Result<FileInfo> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut info = build_file_info(path, &metadata, metadata.is_symlink());
+    info.sidecar_tags = read_sidecar_tags(&info, &SharedStems::next_to(path));
+    info.manifest_tags = tag_manifest::read_tags_lenient(path);
+    Ok(info)
+}
+
+fn build_file_info(path: &Path, metadata: &fs::Metadata, is_symlink: bool) -> FileInfo {
+    let modified = metadata.modified().ok().map(system_time_to_rfc3339);
+    let (symlink_target, is_broken_symlink) = if is_symlink {
+        read_symlink_details(path)
+    } else {
+        (None, false)
+    };
+    // Links report the kind of their target, matching the Windows collector
+    let is_directory = if is_symlink {
+        fs::metadata(path).is_ok_and(|target| target.is_dir())
+    } else {
+        metadata.is_dir()
+    };
+
+    FileInfo {
+        is_symlink,
+        symlink_target,
+        is_broken_symlink,
+        size: metadata.len(),
+        modified,
+        platform_tags: xdg_tags::read_tags_lenient(path),
+        extended: ExtendedMetadata::from_metadata(metadata),
+        ..FileInfo::new(path, is_directory)
+    }
+}
@@ -0,0 +1,510 @@
+use super::super::budget::ScanBudget;
+use super::super::cache::DirectoryCache;
+use super::super::helpers::{read_symlink_details, system_time_to_rfc3339};
+use super::super::ignore_rules::IgnoreRules;
+use super::super::metadata::ExtendedMetadata;
+use super::super::options::ScanFilter;
+use super::super::stats::{apply_seeded_stats, seed_stats, DirectoryStats};
+use super::super::{FileInfo, Freshness, ScanError, SkipKind, SkippedEntry};
+use crate::path_codec::encode_path;
+use crate::tag_manifest;
+use crate::xmp_sidecar::{self, SharedStems};
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use windows::{
+    core::{
+        Array, Error as WinError, IInspectable, Interface, Result as WinResult, HRESULT, HSTRING,
+    },
+    Foundation::{DateTime, IPropertyValue, PropertyType},
+    Storage::{
+        FileProperties::{BasicProperties, PropertyPrefetchOptions},
+        Search::{CommonFileQuery, CommonFolderQuery, FolderDepth, IndexerOption, QueryOptions},
+        StorageFile, StorageFolder, SystemProperties,
+    },
+};
+use windows_collections::{IIterable, IVectorView};
+
+const WINDOWS_TO_UNIX_EPOCH_DIFF_SECS: i64 = 11_644_473_600;
+const HUNDRED_NANOS_PER_SEC: i64 = 10_000_000;
+
+const E_ACCESSDENIED: HRESULT = HRESULT(0x8007_0005_u32 as i32);
+
+type NormalizedPath = String;
+
+fn skipped_entry(path: &Path, err: &WinError) -> SkippedEntry {
+    SkippedEntry {
+        path: path.to_path_buf(),
+        kind: if err.code() == E_ACCESSDENIED {
+            SkipKind::PermissionDenied
+        } else {
+            SkipKind::Other
+        },
+        message: err.message(),
+    }
+}
+
+fn to_keywords(value: &IInspectable) -> WinResult<Vec<String>> {
+    if let Ok(pv) = value.cast::<IPropertyValue>() {
+        match pv.Type()? {
+            PropertyType::StringArray => {
+                let mut arr: Array<HSTRING> = Array::new();
+                pv.GetStringArray(&mut arr)?;
+                let v = arr.iter().map(|s| s.to_string()).collect();
+                return Ok(v);
+            }
+            PropertyType::String => {
+                let s: HSTRING = pv.GetString()?;
+                return Ok(vec![s.to_string()]);
+            }
+            PropertyType::Empty => {
+                // No keywords set
+                return Ok(Vec::new());
+            }
+            _ => {
+                // Try fallback for other types
+            }
+        }
+    }
+
+    if let Ok(view) = value.cast::<IVectorView<HSTRING>>() {
+        let mut out = Vec::with_capacity(view.Size()? as usize);
+        for i in 0..view.Size()? {
+            out.push(view.GetAt(i)?.to_string());
+        }
+        return Ok(out);
+    }
+
+    Ok(Vec::new())
+}
+
+fn build_file_query_options() -> WinResult<QueryOptions> {
+    let qo = QueryOptions::CreateCommonFileQuery(
+        CommonFileQuery::DefaultQuery,
+        &IIterable::<HSTRING>::from(vec![]),
+    )?;
+    qo.SetFolderDepth(FolderDepth::Shallow)?;
+    qo.SetIndexerOption(IndexerOption::UseIndexerWhenAvailable)?;
+
+    let keyword = SystemProperties::Keywords()?;
+    let props = IIterable::<HSTRING>::from(vec![keyword]);
+    qo.SetPropertyPrefetch(PropertyPrefetchOptions::BasicProperties, &props)?;
+
+    Ok(qo)
+}
+
+fn build_folder_query_options() -> WinResult<QueryOptions> {
+    let qo = QueryOptions::CreateCommonFolderQuery(CommonFolderQuery::DefaultQuery)?;
+    qo.SetFolderDepth(FolderDepth::Shallow)?;
+    qo.SetIndexerOption(IndexerOption::UseIndexerWhenAvailable)?;
+    Ok(qo)
+}
+
+fn classify_entry(path: &Path, fallback_is_directory: bool) -> (bool, bool) {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => {
+            if metadata.file_type().is_symlink() {
+                let target_is_directory = fs::metadata(path)
+                    .map(|meta| meta.is_dir())
+                    .unwrap_or(fallback_is_directory);
+                (target_is_directory, true)
+            } else {
+                (metadata.is_dir(), false)
+            }
+        }
+        Err(_) => (fallback_is_directory, false),
+    }
+}
+
+fn is_symlink_path(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_symlink())
+        .unwrap_or(false)
+}
+
+fn canonical_lowercase(path: &Path) -> Option<String> {
+    fs::canonicalize(path)
+        .ok()
+        .map(|p| encode_path(&p).to_lowercase())
+}
+
+fn normalized_key(path: &Path) -> String {
+    canonical_lowercase(path).unwrap_or_else(|| encode_path(path).to_lowercase())
+}
+
+fn to_modified_timestamp(date_modified: DateTime) -> Option<String> {
+    let date_modified_i64 = date_modified.UniversalTime;
+    if date_modified_i64 <= 0 {
+        return None;
+    }
+
+    let unix_timestamp_secs =
+        (date_modified_i64 / HUNDRED_NANOS_PER_SEC) - WINDOWS_TO_UNIX_EPOCH_DIFF_SECS;
+    let unix_timestamp_nanos = ((date_modified_i64 % HUNDRED_NANOS_PER_SEC) * 100) as u32;
+
+    if unix_timestamp_secs >= 0 {
+        Some(system_time_to_rfc3339(
+            SystemTime::UNIX_EPOCH
+                + Duration::new(unix_timestamp_secs as u64, unix_timestamp_nanos),
+        ))
+    } else {
+        None
+    }
+}
+
+fn collect_windows_tags(basic_props: &BasicProperties) -> WinResult<Vec<String>> {
+    let keyword = SystemProperties::Keywords()?;
+    let props = IIterable::<HSTRING>::from(vec![keyword.clone()]);
+
+    if let Ok(map) = basic_props.RetrievePropertiesAsync(&props)?.join() {
+        if let Ok(keywords_value) = map.Lookup(&keyword) {
+            return Ok(to_keywords(&keywords_value).unwrap_or_default());
+        }
+    }
+
+    Ok(Vec::new())
+}
+
+fn build_file_info_from_properties(
+    path: PathBuf,
+    basic_props: &BasicProperties,
+    fallback_is_directory: bool,
+) -> WinResult<FileInfo> {
+    let size = basic_props.Size()?;
+    let date_modified = basic_props.DateModified()?;
+    let windows_tags = collect_windows_tags(basic_props)?;
+    let modified = to_modified_timestamp(date_modified);
+    let (is_directory, is_symlink) = classify_entry(&path, fallback_is_directory);
+    let (symlink_target, is_broken_symlink) = if is_symlink {
+        read_symlink_details(&path)
+    } else {
+        (None, false)
+    };
+    // The storage APIs only expose basic properties, so the rest comes from std
+    let extended = fs::symlink_metadata(&path)
+        .map(|metadata| ExtendedMetadata::from_metadata(&metadata))
+        .unwrap_or_default();
+
+    Ok(FileInfo {
+        is_symlink,
+        symlink_target,
+        is_broken_symlink,
+        size,
+        modified,
+        platform_tags: windows_tags.clone(),
+        windows_tags,
+        extended,
+        ..FileInfo::new(&path, is_directory)
+    })
+}
+
+fn folder_to_file_info(folder: &StorageFolder) -> WinResult<FileInfo> {
+    let basic_props = folder.GetBasicPropertiesAsync()?.join()?;
+    let path = PathBuf::from(folder.Path()?.to_os_string());
+    build_file_info_from_properties(path, &basic_props, true)
+}
+
+// Reads one entry outside a directory walk, e.g. a search result the scan
+// cache has not seen yet
+pub(crate) fn read_entry(path: &Path) -> io::Result<FileInfo> {
+    let is_directory = fs::metadata(path)?.is_dir();
+    let path_hstring = HSTRING::from(path.as_os_str());
+    let basic_props = if is_directory {
+        StorageFolder::GetFolderFromPathAsync(&path_hstring)
+            .and_then(|operation| operation.join())
+            .and_then(|folder| folder.GetBasicPropertiesAsync())
+    } else {
+        StorageFile::GetFileFromPathAsync(&path_hstring)
+            .and_then(|operation| operation.join())
+            .and_then(|file| file.GetBasicPropertiesAsync())
+    }
+    .and_then(|operation| operation.join())
+    .map_err(|err| io::Error::other(err.message()))?;
+
+    let mut info = build_file_info_from_properties(path.to_path_buf(), &basic_props, is_directory)
+        .map_err(|err| io::Error::other(err.message()))?;
+    info.sidecar_tags = read_sidecar_tags(&info, &SharedStems::next_to(path));
+    info.manifest_tags = tag_manifest::read_tags_lenient(path);
+    Ok(info)
+}
+
+// Items whose properties cannot be read are reported through `unreadable`;
+// only a failed query for the folder itself is returned as an error
+fn list_file(
+    folder: &StorageFolder,
+    folder_path: &Path,
+    unreadable: &mut Vec<SkippedEntry>,
+) -> WinResult<Vec<FileInfo>> {
+    let query_options = build_file_query_options()?;
+    let query = folder.CreateItemQueryWithOptions(&query_options)?;
+    let items = query.GetItemsAsyncDefaultStartAndCount()?.join()?;
+
+    let mut results = Vec::new();
+
+    for item in &items {
+        let path = item
+            .Path()
+            .map(|path| PathBuf::from(path.to_os_string()))
+            .unwrap_or_else(|_| folder_path.to_path_buf());
+        let info = item
+            .GetBasicPropertiesAsync()
+            .and_then(|operation| operation.join())
+            .and_then(|basic_props| {
+                let fallback_is_directory = item.cast::<StorageFolder>().is_ok();
+                build_file_info_from_properties(path.clone(), &basic_props, fallback_is_directory)
+            });
+
+        match info {
+            Ok(info) => results.push(info),
+            Err(e) => {
+                warn!("Skipping unreadable item {:?}: {}", path, e);
+                unreadable.push(skipped_entry(&path, &e));
+            }
+        }
+    }
+
+    let shared = SharedStems::from_listing(results.iter().map(|info| info.path.as_path()));
+    for info in results.iter_mut() {
+        info.sidecar_tags = read_sidecar_tags(info, &shared);
+    }
+    tag_manifest::apply_manifest(folder_path, &mut results);
+    Ok(results)
+}
+
+// Sidecars and manifests change without touching the folder's mtime, so
+// items served from the scan cache read them again
+fn refresh_cached_tags(folder_path: &Path, files: &mut [FileInfo]) {
+    let shared = SharedStems::from_listing(files.iter().map(|info| info.path.as_path()));
+    let mut refreshed = false;
+    for info in files
+        .iter_mut()
+        .filter(|info| info.freshness == Freshness::Cached)
+    {
+        info.sidecar_tags = read_sidecar_tags(info, &shared);
+        info.manifest_tags = Vec::new();
+        refreshed = true;
+    }
+    if refreshed {
+        tag_manifest::apply_manifest(folder_path, files);
+    }
+}
+
+fn read_sidecar_tags(info: &FileInfo, shared: &SharedStems) -> Vec<String> {
+    if info.is_directory {
+        Vec::new()
+    } else {
+        xmp_sidecar::read_tags_lenient(&info.path, shared)
+    }
+}
+
+fn list_folder(folder: &StorageFolder) -> WinResult<Vec<StorageFolder>> {
+    let query_options = build_folder_query_options()?;
+    let query = folder.CreateFolderQueryWithOptions(&query_options)?;
+    let folders = query.GetFoldersAsyncDefaultStartAndCount()?.join()?;
+
+    let mut results = Vec::new();
+    for folder in &folders {
+        results.push(folder.clone());
+    }
+
+    Ok(results)
+}
+
+pub(crate) fn collect_entries(
+    root: &Path,
+    max_depth: usize,
+    filter: &ScanFilter,
+    cache: &mut DirectoryCache,
+    skipped: &mut Vec<SkippedEntry>,
+    budget: &mut ScanBudget,
+) -> Result<Vec<FileInfo>, ScanError> {
+    debug!("Scanning {:?} with depth {}", root, max_depth);
+
+    let root_hstring = HSTRING::from(root.as_os_str());
+
+    let root_folder = match StorageFolder::GetFolderFromPathAsync(&root_hstring) {
+        Ok(async_op) => match async_op.join() {
+            Ok(folder) => folder,
+            Err(e) => {
+                return Err(ScanError::Io(format!(
+                    "Failed to access folder {:?}: {}",
+                    root, e
+                )));
+            }
+        },
+        Err(e) => {
+            return Err(ScanError::Io(format!(
+                "Failed to create async operation for {:?}: {}",
+                root, e
+            )));
+        }
+    };
+
+    let mut all_entries = Vec::new();
+    let mut queue = VecDeque::new();
+    let mut visited_dirs: HashSet<NormalizedPath> = HashSet::new();
+    let mut ignore_rules = filter.respect_ignore_files.then(IgnoreRules::new);
+    let mut seeded_stats: HashMap<PathBuf, DirectoryStats> = HashMap::new();
+
+    let mut root_info = folder_to_file_info(&root_folder)
+        .map_err(|e| ScanError::Io(format!("Failed to retrieve metadata for {:?}: {}", root, e)))?;
+    root_info.manifest_tags = tag_manifest::read_tags_lenient(root);
+    all_entries.push(root_info);
+
+    visited_dirs.insert(normalized_key(root));
+    queue.push_back((root_folder, root.to_path_buf(), 0));
+
+    while let Some((folder, folder_path, current_depth)) = queue.pop_front() {
+        let can_descend = current_depth < max_depth;
+
+        // The queue is still drained so every folder left unlisted gets marked
+        if !budget.can_continue(all_entries.len()) {
+            if can_descend {
+                budget.mark_truncated(&folder_path);
+            }
+            seeded_stats.insert(folder_path, DirectoryStats::partial());
+            continue;
+        }
+
+        // Folders cut from the listing by the budget are not descended into
+        let mut dropped_folders: HashSet<PathBuf> = HashSet::new();
+
+        if can_descend {
+            if let Some(rules) = ignore_rules.as_mut() {
+                rules.load_directory(&folder_path);
+            }
+
+            // Item listings carry the expensive keyword lookups, so only they are
+            // cached; the folder query below stays live to obtain StorageFolder handles
+            let mut unreadable = Vec::new();
+            let listing = cache.list_with(&folder_path, || {
+                list_file(&folder, &folder_path, &mut unreadable)
+            });
+            if !unreadable.is_empty() {
+                cache.discard(&folder_path);
+                skipped.append(&mut unreadable);
+                seeded_stats.insert(folder_path.clone(), DirectoryStats::partial());
+            }
+
+            match listing {
+                Ok(mut files) => {
+                    refresh_cached_tags(&folder_path, &mut files);
+                    debug!(
+                        "Found {} files in {:?} at depth {}",
+                        files.len(),
+                        folder_path,
+                        current_depth
+                    );
+                    let mut files: Vec<FileInfo> = files
+                        .into_iter()
+                        .filter(|info| {
+                            !filter.prunes(&info.path)
+                                && ignore_rules.as_mut().is_none_or(|rules| {
+                                    !rules.filter_out(&info.path, info.is_directory)
+                                })
+                                && filter.accepts(info)
+                        })
+                        .collect();
+                    // A stable order decides which items survive a per-folder cap
+                    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+                    let mut reported = 0;
+                    for info in files {
+                        if !budget.admits(all_entries.len(), reported) {
+                            budget.mark_truncated(&folder_path);
+                            seeded_stats.insert(folder_path.clone(), DirectoryStats::partial());
+                            if info.is_directory {
+                                dropped_folders.insert(info.path);
+                            }
+                            continue;
+                        }
+                        reported += 1;
+                        all_entries.push(info);
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to list files in {:?}: {}. Skipping.",
+                        folder_path, e
+                    );
+                    skipped.push(skipped_entry(&folder_path, &e));
+                    seeded_stats.insert(folder_path.clone(), DirectoryStats::partial());
+                }
+            }
+        } else {
+            debug!(
+                "Skipping file enumeration for {:?} at depth {} (max depth {})",
+                folder_path, current_depth, max_depth
+            );
+            let stats = seed_stats(&folder_path, filter, ignore_rules.as_mut(), budget);
+            seeded_stats.insert(folder_path.clone(), stats);
+        }
+
+        if can_descend {
+            match list_folder(&folder) {
+                Ok(subfolders) => {
+                    debug!(
+                        "Found {} subfolders in {:?} at depth {}",
+                        subfolders.len(),
+                        folder_path,
+                        current_depth
+                    );
+
+                    for subfolder in subfolders {
+                        if let Ok(subfolder_path_hstring) = subfolder.Path() {
+                            let subfolder_path =
+                                PathBuf::from(subfolder_path_hstring.to_os_string());
+                            if dropped_folders.contains(&subfolder_path) {
+                                continue;
+                            }
+                            // Already counted when the item query listed this folder
+                            if filter.prunes(&subfolder_path)
+                                || ignore_rules
+                                    .as_ref()
+                                    .is_some_and(|rules| rules.is_ignored(&subfolder_path, true))
+                            {
+                                debug!("Skipping ignored folder during scan: {:?}", subfolder_path);
+                                continue;
+                            }
+                            if !filter.follow_symlinks && is_symlink_path(&subfolder_path) {
+                                debug!(
+                                    "Not following symlinked folder during scan: {:?}",
+                                    subfolder_path
+                                );
+                                continue;
+                            }
+                            let key = normalized_key(&subfolder_path);
+                            if !visited_dirs.insert(key) {
+                                debug!(
+                                    "Skipping already visited folder during scan: {:?}",
+                                    subfolder_path
+                                );
+                                continue;
+                            }
+                            queue.push_back((subfolder, subfolder_path, current_depth + 1));
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to list subfolders in {:?}: {}. Skipping.",
+                        folder_path, e
+                    );
+                    skipped.push(skipped_entry(&folder_path, &e));
+                    seeded_stats.insert(folder_path.clone(), DirectoryStats::partial());
+                }
+            }
+        }
+    }
+
+    if let Some(rules) = ignore_rules.as_ref() {
+        rules.apply_ignored_counts(&mut all_entries);
+    }
+    apply_seeded_stats(&mut all_entries, &seeded_stats);
+
+    debug!("Collected {} total entries", all_entries.len());
+    Ok(all_entries)
+}
@@ -0,0 +1,585 @@
+use super::cache::{cached_file_info, file_info_columns, listing_only, name_grams};
+use super::{normalize_path_buf, platform, FileInfo, ScanError};
+use crate::path_codec::{decode_path, encode_os_str, encode_path};
+use crate::tagging::{escape_for_like, get_tags_for_paths};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_LIMIT: usize = 100;
+
+// Substring tiers, best first
+const SCORE_EXACT: i64 = 400;
+const SCORE_PREFIX: i64 = 300;
+const SCORE_WORD_START: i64 = 200;
+const SCORE_CONTAINED: i64 = 100;
+
+// fzf's scoring constants
+const SCORE_MATCH: i64 = 16;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = PENALTY_GAP_START + PENALTY_GAP_EXTENSION;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    // Case-insensitive substring
+    #[default]
+    Substring,
+    // Case-insensitive glob; `*` stops at path separators, `**` does not
+    Glob,
+    // The query's characters in order, ranked like fzf
+    Fuzzy,
+}
+
+// Options accepted by `search_files`; every field is optional on the wire
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub mode: SearchMode,
+    // Match against the whole path instead of just the entry's name
+    pub full_path: bool,
+    pub limit: Option<usize>,
+}
+
+// A query compiled for one mode
+enum Matcher {
+    Substring(String),
+    Glob(GlobMatcher),
+    Fuzzy(Vec<char>),
+}
+
+impl Matcher {
+    fn new(query: &str, mode: SearchMode) -> Result<Self, ScanError> {
+        Ok(match mode {
+            SearchMode::Substring => Self::Substring(query.to_lowercase()),
+            SearchMode::Glob => Self::Glob(
+                GlobBuilder::new(query)
+                    .case_insensitive(true)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|err| {
+                        ScanError::InvalidOptions(format!("Invalid glob {query:?}: {err}"))
+                    })?
+                    .compile_matcher(),
+            ),
+            SearchMode::Fuzzy => Self::Fuzzy(
+                query
+                    .chars()
+                    .filter(|ch| !ch.is_whitespace())
+                    .map(fold_char)
+                    .collect(),
+            ),
+        })
+    }
+
+    // ILIKE pattern every match satisfies, so the database can discard most rows
+    fn like_pattern(&self, query: &str) -> String {
+        let fragments = match self {
+            Self::Substring(needle) => vec![needle.clone()],
+            Self::Glob(_) => glob_literals(query),
+            Self::Fuzzy(chars) => chars.iter().map(char::to_string).collect(),
+        };
+        let mut pattern = String::from("%");
+        for fragment in fragments {
+            pattern.push_str(&escape_for_like(&fragment));
+            pattern.push('%');
+        }
+        pattern
+    }
+
+    // Name trigrams every match contains, when there are any to look up.
+    // Fuzzy queries may skip any character, so nothing is certain to appear.
+    fn required_grams(&self, query: &str) -> BTreeSet<String> {
+        match self {
+            Self::Substring(needle) => name_grams(needle),
+            Self::Glob(_) => glob_literals(query)
+                .iter()
+                .flat_map(|literal| name_grams(literal))
+                .collect(),
+            Self::Fuzzy(_) => BTreeSet::new(),
+        }
+    }
+
+    fn score(&self, text: &str) -> Option<i64> {
+        match self {
+            Self::Substring(needle) => substring_score(text, needle),
+            Self::Glob(glob) => glob.is_match(text).then_some(0),
+            Self::Fuzzy(chars) => fuzzy_score(text, chars),
+        }
+    }
+}
+
+struct Ranked {
+    path: PathBuf,
+    score: i64,
+    length: usize,
+}
+
+pub(super) fn search(
+    connection: &duckdb::Connection,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<FileInfo>, ScanError> {
+    let limit = options.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 {
+        return Err(ScanError::InvalidOptions(
+            "limit must be greater than zero".into(),
+        ));
+    }
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let matcher = Matcher::new(query, options.mode)?;
+    let candidates = find_candidates(connection, &matcher, query, options.full_path)?;
+
+    // Ranking only needs the path, so entries are read once they make the cut
+    let mut ranked: Vec<Ranked> = candidates
+        .into_iter()
+        .filter_map(|path| rank(&matcher, path, options.full_path))
+        .collect();
+    ranked.sort_by(|a, b| {
+        (Reverse(a.score), a.length, &a.path).cmp(&(Reverse(b.score), b.length, &b.path))
+    });
+
+    let mut results = Vec::new();
+    let mut lookup = connection
+        .prepare(&format!(
+            "SELECT {listing_only}, {columns} FROM files f WHERE f.path = ?1",
+            listing_only = listing_only("f"),
+            columns = file_info_columns("f"),
+        ))
+        .map_err(database_error)?;
+    for hit in ranked {
+        if results.len() == limit {
+            break;
+        }
+        let mut rows = lookup
+            .query(duckdb::params![encode_path(&hit.path)])
+            .map_err(database_error)?;
+        let cached = match rows.next().map_err(database_error)? {
+            // A scan root's own row only holds listing state
+            Some(row) if !row.get::<_, bool>(0).map_err(database_error)? => {
+                Some(cached_file_info(row, 1)?)
+            }
+            _ => None,
+        };
+        // Tags can outlive the files they were put on
+        if let Some(info) = cached.or_else(|| platform::read_entry(&hit.path).ok()) {
+            results.push(info);
+        }
+    }
+
+    apply_tags(connection, &mut results)?;
+    Ok(results)
+}
+
+fn rank(matcher: &Matcher, path: PathBuf, full_path: bool) -> Option<Ranked> {
+    let text = search_text(&path, full_path);
+    let mut score = matcher.score(&text)?;
+    // In full-path mode, hits on the name itself outrank hits on its directories
+    if full_path {
+        if let Some(name_score) = matcher.score(&search_text(&path, false)) {
+            score += name_score;
+        }
+    }
+
+    Some(Ranked {
+        length: text.chars().count(),
+        path,
+        score,
+    })
+}
+
+fn search_text(path: &Path, full_path: bool) -> String {
+    match path.file_name() {
+        Some(name) if !full_path => encode_os_str(name),
+        _ => encode_path(path),
+    }
+}
+
+// Paths of indexed and tagged entries that may match. Name searches whose
+// query holds a trigram look the names up in `file_name_grams`; full-path,
+// fuzzy and shorter queries have nothing an index can serve, so they read
+// every name or path in the scan cache. Tagged paths are always read in full
+// from the tag table, which holds no names to index.
+fn find_candidates(
+    connection: &duckdb::Connection,
+    matcher: &Matcher,
+    query: &str,
+    full_path: bool,
+) -> Result<BTreeSet<PathBuf>, ScanError> {
+    let pattern = matcher.like_pattern(query);
+    let grams: Vec<String> = if full_path {
+        Vec::new()
+    } else {
+        matcher.required_grams(query).into_iter().collect()
+    };
+
+    let (sql, params) = if grams.is_empty() {
+        let column = if full_path { "path" } else { "name" };
+        (
+            format!("SELECT path FROM files WHERE {column} ILIKE ?1 ESCAPE '\\'"),
+            vec![pattern.clone()],
+        )
+    } else {
+        // Each lookup is an index scan unless the trigram is too common to
+        // be worth one
+        let lookups: Vec<String> = (1..=grams.len())
+            .map(|index| format!("SELECT path FROM file_name_grams WHERE gram = ?{index}"))
+            .collect();
+        (lookups.join(" INTERSECT "), grams)
+    };
+    let mut candidates = BTreeSet::new();
+    collect_paths(connection, &sql, params, &mut candidates)?;
+
+    // A path's name is part of the path, so this prefilter is only looser
+    collect_paths(
+        connection,
+        "SELECT DISTINCT path FROM path_tags WHERE path ILIKE ?1 ESCAPE '\\'",
+        vec![pattern],
+        &mut candidates,
+    )?;
+    Ok(candidates)
+}
+
+fn collect_paths(
+    connection: &duckdb::Connection,
+    sql: &str,
+    params: Vec<String>,
+    paths: &mut BTreeSet<PathBuf>,
+) -> Result<(), ScanError> {
+    let mut statement = connection.prepare(sql).map_err(database_error)?;
+    let mut rows = statement
+        .query(duckdb::params_from_iter(params))
+        .map_err(database_error)?;
+    while let Some(row) = rows.next().map_err(database_error)? {
+        let path: String = row.get(0).map_err(database_error)?;
+        paths.insert(decode_path(&path));
+    }
+    Ok(())
+}
+
+// Results come from anywhere, so tags are looked up per result along the
+// normalized path, the way a scan rooted at the result's parent would see them
+pub(super) fn apply_tags(
+    connection: &duckdb::Connection,
+    results: &mut [FileInfo],
+) -> Result<(), ScanError> {
+    let keys: Vec<(PathBuf, Option<PathBuf>)> = results.iter().map(tag_keys).collect();
+    let mut lookup: BTreeSet<PathBuf> = BTreeSet::new();
+    for (display, target) in &keys {
+        lookup.extend(display.ancestors().map(Path::to_path_buf));
+        lookup.extend(target.clone());
+    }
+
+    let lookup: Vec<PathBuf> = lookup.into_iter().collect();
+    let tags = get_tags_for_paths(connection, &lookup)
+        .map_err(|err| ScanError::Database(err.to_string()))?;
+
+    for (info, (display, target)) in results.iter_mut().zip(&keys) {
+        let mut own: BTreeSet<String> = BTreeSet::new();
+        for path in std::iter::once(display).chain(target) {
+            own.extend(tags.get(path).into_iter().flatten().cloned());
+        }
+        let inherited: BTreeSet<String> = display
+            .ancestors()
+            .skip(1)
+            .flat_map(|ancestor| tags.get(ancestor).into_iter().flatten().cloned())
+            .collect();
+
+        info.own_tags = own.into_iter().collect();
+        info.inherited_tags = inherited.into_iter().collect();
+    }
+
+    Ok(())
+}
+
+// The normalized path a result is displayed under, plus its resolved
+// location when it sits behind a symlink
+fn tag_keys(info: &FileInfo) -> (PathBuf, Option<PathBuf>) {
+    let display = match (info.path.parent(), info.path.file_name()) {
+        (Some(parent), Some(name)) => normalize_path_buf(parent).join(name),
+        _ => normalize_path_buf(&info.path),
+    };
+    let target = fs::canonicalize(&info.path)
+        .ok()
+        .filter(|resolved| *resolved != display);
+    (display, target)
+}
+
+// Literal runs of a glob, in order. Classes and alternations are skipped
+// since any one of their branches may be the one that matches.
+fn glob_literals(glob: &str) -> Vec<String> {
+    let mut literals = Vec::new();
+    let mut current = String::new();
+    let mut nesting = 0usize;
+    let mut chars = glob.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '[' | '{' => nesting += 1,
+            ']' | '}' => nesting = nesting.saturating_sub(1),
+            _ if nesting > 0 => continue,
+            '*' | '?' => {}
+            '\\' => {
+                current.extend(chars.next());
+                continue;
+            }
+            _ => {
+                current.push(ch);
+                continue;
+            }
+        }
+        if !current.is_empty() {
+            literals.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        literals.push(current);
+    }
+    literals
+}
+
+fn substring_score(text: &str, needle: &str) -> Option<i64> {
+    let text = text.to_lowercase();
+    let position = text.find(needle)?;
+    let score = if text.len() == needle.len() {
+        SCORE_EXACT
+    } else if position == 0 {
+        SCORE_PREFIX
+    } else if text[..position]
+        .chars()
+        .next_back()
+        .is_some_and(|before| !before.is_alphanumeric())
+    {
+        SCORE_WORD_START
+    } else {
+        SCORE_CONTAINED
+    };
+    Some(score)
+}
+
+// fzf's v1 algorithm: the first forward match is narrowed to the shortest
+// window ending at the same place, then scored with bonuses for matches at
+// word boundaries and in runs, and penalties for gaps
+fn fuzzy_score(text: &str, query: &[char]) -> Option<i64> {
+    let original: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = original.iter().copied().map(fold_char).collect();
+    let (&first, _) = query.split_first()?;
+
+    let mut remaining = query.iter().peekable();
+    let end = folded.iter().position(|&ch| {
+        if remaining.peek() == Some(&&ch) {
+            remaining.next();
+        }
+        remaining.peek().is_none()
+    })?;
+
+    let mut remaining = query.iter().rev().peekable();
+    let start = (0..=end).rev().find(|&index| {
+        if remaining.peek() == Some(&&folded[index]) {
+            remaining.next();
+        }
+        remaining.peek().is_none()
+    })?;
+    debug_assert_eq!(folded[start], first);
+
+    let mut score = 0;
+    let mut pending = query.iter().peekable();
+    let mut consecutive = false;
+    let mut in_gap = false;
+    for (index, ch) in folded.iter().enumerate().take(end + 1).skip(start) {
+        if pending.peek() == Some(&ch) {
+            let bonus = boundary_bonus(&original, index);
+            score += SCORE_MATCH;
+            score += if index == start {
+                bonus * BONUS_FIRST_CHAR_MULTIPLIER
+            } else if consecutive {
+                bonus.max(BONUS_CONSECUTIVE)
+            } else {
+                bonus
+            };
+            pending.next();
+            consecutive = true;
+            in_gap = false;
+        } else {
+            score -= if in_gap {
+                PENALTY_GAP_EXTENSION
+            } else {
+                PENALTY_GAP_START
+            };
+            consecutive = false;
+            in_gap = true;
+        }
+    }
+    Some(score)
+}
+
+fn boundary_bonus(text: &[char], index: usize) -> i64 {
+    let current = text[index];
+    let Some(&previous) = index.checked_sub(1).and_then(|before| text.get(before)) else {
+        return BONUS_BOUNDARY;
+    };
+
+    if !previous.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if (previous.is_lowercase() && current.is_uppercase())
+        || (!previous.is_numeric() && current.is_numeric())
+    {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+fn fold_char(ch: char) -> char {
+    ch.to_lowercase().next().unwrap_or(ch)
+}
+
+fn database_error(err: duckdb::Error) -> ScanError {
+    ScanError::Database(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::budget::ScanBudget;
+    use super::super::cache::{ensure_cache_schema, DirectoryCache};
+    use super::super::options::{ScanFilter, ScanOptions};
+    use super::*;
+
+    fn fuzzy(query: &str) -> Vec<char> {
+        query.chars().map(fold_char).collect()
+    }
+
+    #[test]
+    fn ranks_substring_and_fuzzy_matches() {
+        assert_eq!(substring_score("Notes.md", "notes.md"), Some(SCORE_EXACT));
+        assert_eq!(substring_score("notes.md", "note"), Some(SCORE_PREFIX));
+        assert_eq!(
+            substring_score("my-notes.md", "note"),
+            Some(SCORE_WORD_START)
+        );
+        assert_eq!(
+            substring_score("keynotes.md", "note"),
+            Some(SCORE_CONTAINED)
+        );
+        assert_eq!(substring_score("readme.md", "note"), None);
+
+        let query = fuzzy("fb");
+        let boundary = fuzzy_score("foo_bar.rs", &query).unwrap();
+        let camel = fuzzy_score("fooBar.rs", &query).unwrap();
+        let buried = fuzzy_score("fooobar.rs", &query).unwrap();
+        assert!(boundary > buried && camel > buried);
+        assert!(fuzzy_score("fab.rs", &query).unwrap() > buried);
+        assert_eq!(fuzzy_score("bf.rs", &query), None);
+    }
+
+    #[test]
+    fn prefilters_globs_on_their_literal_runs() {
+        assert_eq!(glob_literals("*.tar.gz"), vec![".tar.gz"]);
+        assert_eq!(glob_literals("img_??.[jp]ng"), vec!["img_", ".", "ng"]);
+        assert_eq!(glob_literals("{a,b}/\\*x"), vec!["/*x"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn searches_indexed_and_tagged_entries_across_roots() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = fs::canonicalize(temp.path()).expect("canonical temp dir");
+        let indexed = root.join("indexed");
+        let elsewhere = root.join("elsewhere");
+        fs::create_dir_all(indexed.join("reports")).expect("create indexed");
+        fs::create_dir_all(&elsewhere).expect("create elsewhere");
+        fs::write(indexed.join("reports").join("Quarterly_Report.pdf"), "").expect("write");
+        fs::write(indexed.join("report.txt"), "").expect("write");
+        fs::write(elsewhere.join("tagged-report.md"), "").expect("write");
+
+        let mut connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_cache_schema(&connection).expect("cache schema");
+        crate::tagging::ensure_schema(&connection).expect("tag schema");
+        let filter = ScanFilter::new(&indexed, &ScanOptions::default()).unwrap();
+        let mut cache = DirectoryCache::load(&connection, &indexed, 2, true).expect("load");
+        platform::collect_entries(
+            &indexed,
+            2,
+            &filter,
+            &mut cache,
+            &mut Vec::new(),
+            &mut ScanBudget::unlimited(),
+        )
+        .expect("scan");
+        cache.persist(&mut connection).expect("persist");
+        for (path, tag) in [
+            (&indexed, "work"),
+            (&elsewhere.join("tagged-report.md"), "draft"),
+        ] {
+            connection
+                .execute(
+                    "INSERT INTO path_tags (path, tag, path_depth) VALUES (?1, ?2, 0)",
+                    duckdb::params![encode_path(path), tag],
+                )
+                .expect("tag");
+        }
+
+        let names = |results: Vec<FileInfo>| -> Vec<String> {
+            results
+                .iter()
+                .map(|info| search_text(&info.path, false))
+                .collect()
+        };
+        let results = search(&connection, "REPORT", &SearchOptions::default()).expect("search");
+        assert_eq!(
+            names(results.clone()),
+            vec![
+                "reports",
+                "report.txt",
+                "tagged-report.md",
+                "Quarterly_Report.pdf"
+            ]
+        );
+        assert_eq!(results[0].inherited_tags, vec!["work"]);
+        assert_eq!(results[2].own_tags, vec!["draft"]);
+
+        // The scan root's own row only holds listing state, so it is read from disk
+        let results = search(&connection, "indexed", &SearchOptions::default()).expect("search");
+        assert_eq!(names(results.clone()), vec!["indexed"]);
+        assert!(results[0].is_directory && results[0].modified.is_some());
+        assert_eq!(results[0].own_tags, vec!["work"]);
+
+        let glob = SearchOptions {
+            mode: SearchMode::Glob,
+            ..SearchOptions::default()
+        };
+        let results = search(&connection, "*report*.{md,pdf}", &glob).expect("glob");
+        assert_eq!(
+            names(results),
+            vec!["tagged-report.md", "Quarterly_Report.pdf"]
+        );
+
+        let fuzzy = SearchOptions {
+            mode: SearchMode::Fuzzy,
+            full_path: true,
+            limit: Some(1),
+        };
+        let results = search(&connection, "rep/qrpdf", &fuzzy).expect("fuzzy");
+        assert_eq!(names(results), vec!["Quarterly_Report.pdf"]);
+
+        let zero = SearchOptions {
+            limit: Some(0),
+            ..SearchOptions::default()
+        };
+        assert!(matches!(
+            search(&connection, "report", &zero),
+            Err(ScanError::InvalidOptions(_))
+        ));
+    }
+}
@@ -0,0 +1,198 @@
+use super::DirectoryNode;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Name,
+    // Recursive size for directories, file size otherwise
+    Size,
+    Modified,
+    OwnTagCount,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+// How names are compared, both for `SortKey::Name` and as the tie-breaker
+// for the other keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameComparison {
+    // Byte-wise, so "B" sorts before "a" and "file10" before "file2"
+    #[default]
+    Ordinal,
+    // Unicode lowercase comparison
+    CaseInsensitive,
+    // Case-insensitive with digit runs compared by value, so "file2" < "file10"
+    Natural,
+}
+
+// Sort order applied to the children of every node in a scan tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SortOptions {
+    pub key: SortKey,
+    pub direction: SortDirection,
+    pub name_comparison: NameComparison,
+    pub directories_first: bool,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        Self {
+            key: SortKey::Name,
+            direction: SortDirection::Ascending,
+            name_comparison: NameComparison::Ordinal,
+            directories_first: true,
+        }
+    }
+}
+
+impl SortOptions {
+    pub(crate) fn compare(&self, a: &DirectoryNode, b: &DirectoryNode) -> Ordering {
+        if self.directories_first {
+            match (a.info.is_directory, b.info.is_directory) {
+                (true, false) => return Ordering::Less,
+                (false, true) => return Ordering::Greater,
+                _ => {}
+            }
+        }
+
+        let by_name = || compare_names(&a.name, &b.name, self.name_comparison);
+        let ordering = match self.key {
+            SortKey::Name => by_name(),
+            SortKey::Size => node_size(a).cmp(&node_size(b)).then_with(by_name),
+            SortKey::Modified => node_modified(a).cmp(&node_modified(b)).then_with(by_name),
+            SortKey::OwnTagCount => a
+                .info
+                .own_tags
+                .len()
+                .cmp(&b.info.own_tags.len())
+                .then_with(by_name),
+        };
+
+        match self.direction {
+            SortDirection::Ascending => ordering,
+            SortDirection::Descending => ordering.reverse(),
+        }
+    }
+}
+
+fn node_size(node: &DirectoryNode) -> u64 {
+    node.info
+        .stats
+        .map(|stats| stats.total_size)
+        .unwrap_or(node.info.size)
+}
+
+// Entries without a readable timestamp sort as the oldest
+fn node_modified(node: &DirectoryNode) -> Option<DateTime<Utc>> {
+    node.info
+        .modified
+        .as_deref()
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+        .map(|value| value.with_timezone(&Utc))
+}
+
+fn compare_names(a: &str, b: &str, comparison: NameComparison) -> Ordering {
+    match comparison {
+        NameComparison::Ordinal => a.cmp(b),
+        // Falling back to the ordinal order keeps names differing only in case stable
+        NameComparison::CaseInsensitive => a
+            .to_lowercase()
+            .cmp(&b.to_lowercase())
+            .then_with(|| a.cmp(b)),
+        NameComparison::Natural => natural_cmp(a, b).then_with(|| a.cmp(b)),
+    }
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut left = a.chars().peekable();
+    let mut right = b.chars().peekable();
+
+    loop {
+        match (left.peek().copied(), right.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let left_digits = take_digits(&mut left);
+                let right_digits = take_digits(&mut right);
+                let ordering = compare_digit_runs(&left_digits, &right_digits);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(l), Some(r)) => {
+                let ordering = l.to_lowercase().cmp(r.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                left.next();
+                right.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+// Compares digit runs by value without parsing, so arbitrarily long runs work;
+// "007" and "7" are equal here and left to the ordinal tie-breaker
+fn compare_digit_runs(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str], comparison: NameComparison) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| compare_names(a, b, comparison));
+        names
+    }
+
+    #[test]
+    fn natural_order_compares_numbers_by_value() {
+        assert_eq!(
+            sorted(
+                &["file10.txt", "File2.txt", "file1.txt", "file02.txt"],
+                NameComparison::Natural
+            ),
+            vec!["file1.txt", "File2.txt", "file02.txt", "file10.txt"]
+        );
+    }
+
+    #[test]
+    fn case_insensitive_order_ignores_case() {
+        assert_eq!(
+            sorted(
+                &["beta", "Alpha", "alpha", "Gamma"],
+                NameComparison::CaseInsensitive
+            ),
+            vec!["Alpha", "alpha", "beta", "Gamma"]
+        );
+        assert_eq!(
+            sorted(&["beta", "Alpha", "Gamma"], NameComparison::Ordinal),
+            vec!["Alpha", "Gamma", "beta"]
+        );
+    }
+}
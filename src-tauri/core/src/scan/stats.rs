@@ -0,0 +1,198 @@
+use super::budget::ScanBudget;
+use super::ignore_rules::IgnoreRules;
+use super::options::ScanFilter;
+use super::FileInfo;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Recursive totals for a directory, counted over the entries a scan would show
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryStats {
+    pub total_size: u64,
+    pub file_count: u64,
+    pub directory_count: u64,
+    // Set when part of the subtree was not counted, because of the depth limit
+    // or an unreadable directory
+    pub is_partial: bool,
+}
+
+impl DirectoryStats {
+    pub(crate) fn partial() -> Self {
+        Self {
+            is_partial: true,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn add_file(&mut self, size: u64) {
+        self.total_size = self.total_size.saturating_add(size);
+        self.file_count += 1;
+    }
+
+    pub(crate) fn add_directory(&mut self, child: &DirectoryStats) {
+        self.total_size = self.total_size.saturating_add(child.total_size);
+        self.file_count += child.file_count;
+        self.directory_count += 1 + child.directory_count;
+        self.is_partial |= child.is_partial;
+    }
+}
+
+// Stats for a directory the scan stopped at: walked in full when requested,
+// otherwise an empty partial marker
+pub(crate) fn seed_stats(
+    dir: &Path,
+    filter: &ScanFilter,
+    ignore_rules: Option<&mut IgnoreRules>,
+    budget: &ScanBudget,
+) -> DirectoryStats {
+    if filter.full_subtree_stats {
+        measure_subtree(dir, filter, ignore_rules, budget)
+    } else {
+        DirectoryStats::partial()
+    }
+}
+
+// Walks everything below `dir` without building entries, applying the same
+// pruning, ignore and file filters as the collectors. Running out of scan time
+// stops the walk and leaves the stats partial.
+pub(crate) fn measure_subtree(
+    dir: &Path,
+    filter: &ScanFilter,
+    mut ignore_rules: Option<&mut IgnoreRules>,
+    budget: &ScanBudget,
+) -> DirectoryStats {
+    let mut stats = DirectoryStats::default();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    visited.insert(fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()));
+
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(directory) = pending.pop() {
+        if let Some(rules) = ignore_rules.as_deref_mut() {
+            rules.load_directory(&directory);
+        }
+
+        let read_dir = match fs::read_dir(&directory) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                warn!("Failed to read {:?} while measuring: {}", directory, e);
+                stats.is_partial = true;
+                continue;
+            }
+        };
+
+        for entry in read_dir {
+            if budget.out_of_time() {
+                stats.is_partial = true;
+                return stats;
+            }
+            let Ok(entry) = entry else {
+                stats.is_partial = true;
+                continue;
+            };
+            let path = entry.path();
+            let Ok(link_metadata) = fs::symlink_metadata(&path) else {
+                stats.is_partial = true;
+                continue;
+            };
+            let is_symlink = link_metadata.is_symlink();
+            let metadata = if is_symlink {
+                fs::metadata(&path).unwrap_or(link_metadata)
+            } else {
+                link_metadata
+            };
+            let is_directory = metadata.is_dir();
+
+            if filter.prunes(&path)
+                || ignore_rules
+                    .as_deref()
+                    .is_some_and(|rules| rules.is_ignored(&path, is_directory))
+            {
+                continue;
+            }
+
+            if is_directory {
+                stats.directory_count += 1;
+                if is_symlink && !filter.follow_symlinks {
+                    continue;
+                }
+                let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                if visited.insert(key) {
+                    pending.push(path);
+                }
+                continue;
+            }
+
+            let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+            if filter.accepts_file(&path, metadata.len(), modified) {
+                stats.add_file(metadata.len());
+            }
+        }
+    }
+
+    stats
+}
+
+pub(crate) fn apply_seeded_stats(
+    entries: &mut [FileInfo],
+    seeded: &HashMap<PathBuf, DirectoryStats>,
+) {
+    for entry in entries.iter_mut() {
+        if let Some(stats) = seeded.get(&entry.path) {
+            entry.stats = Some(*stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::options::ScanOptions;
+    use super::*;
+
+    #[test]
+    fn measures_nested_files_and_directories() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let nested = dir.path().join("a").join("b");
+        fs::create_dir_all(&nested).expect("create nested");
+        fs::write(dir.path().join("top.txt"), b"12345").expect("write");
+        fs::write(nested.join("deep.txt"), b"123").expect("write");
+        fs::write(dir.path().join(".hidden"), b"1").expect("write");
+
+        let options = ScanOptions {
+            show_hidden: false,
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(dir.path(), &options).unwrap();
+        let stats = measure_subtree(dir.path(), &filter, None, &ScanBudget::unlimited());
+
+        assert_eq!(
+            stats,
+            DirectoryStats {
+                total_size: 8,
+                file_count: 2,
+                directory_count: 2,
+                is_partial: false,
+            }
+        );
+    }
+
+    #[test]
+    fn stops_measuring_once_the_deadline_passes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        fs::write(dir.path().join("file.txt"), b"123").expect("write");
+
+        let options = ScanOptions {
+            full_subtree_stats: true,
+            max_duration_ms: Some(0),
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(dir.path(), &options).unwrap();
+        let budget = ScanBudget::new(&options).unwrap();
+        let stats = seed_stats(dir.path(), &filter, None, &budget);
+
+        assert_eq!(stats, DirectoryStats::partial());
+    }
+}
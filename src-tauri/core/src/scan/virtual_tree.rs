@@ -0,0 +1,76 @@
+use super::search::apply_tags;
+use super::sort::SortOptions;
+use super::{platform, DirectoryNode, FileInfo, ScanError};
+use crate::path_codec::{encode_os_str, encode_path};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+// A tree that only exists in the app: a top node called `name` with one node
+// per real parent directory, each holding the given entries found there.
+// Entries are read from disk, so ones that no longer exist are left out.
+pub(crate) fn build_virtual_tree(
+    connection: &duckdb::Connection,
+    name: &str,
+    paths: &[PathBuf],
+    sort: &SortOptions,
+) -> Result<DirectoryNode, ScanError> {
+    let unique: BTreeSet<&PathBuf> = paths.iter().collect();
+    let mut entries: Vec<FileInfo> = unique
+        .into_iter()
+        .filter_map(|path| platform::read_entry(path).ok())
+        .collect();
+    apply_tags(connection, &mut entries)?;
+
+    let mut grouped: BTreeMap<PathBuf, Vec<FileInfo>> = BTreeMap::new();
+    for entry in entries {
+        let parent = entry.path.parent().unwrap_or(&entry.path).to_path_buf();
+        grouped.entry(parent).or_default().push(entry);
+    }
+
+    let mut parents: Vec<FileInfo> = grouped
+        .keys()
+        .map(|parent| platform::read_entry(parent).unwrap_or_else(|_| virtual_directory(parent)))
+        .collect();
+    apply_tags(connection, &mut parents)?;
+
+    let mut groups: Vec<DirectoryNode> = parents
+        .into_iter()
+        .zip(grouped.into_values())
+        .map(|(info, entries)| {
+            let mut children: Vec<DirectoryNode> = entries
+                .into_iter()
+                .map(|entry| DirectoryNode {
+                    name: entry
+                        .path
+                        .file_name()
+                        .map(encode_os_str)
+                        .unwrap_or_else(|| encode_path(&entry.path)),
+                    info: entry,
+                    children: Vec::new(),
+                    truncated: false,
+                })
+                .collect();
+            children.sort_by(|a, b| sort.compare(a, b));
+
+            DirectoryNode {
+                name: encode_path(&info.path),
+                info,
+                children,
+                truncated: false,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| sort.compare(a, b));
+
+    Ok(DirectoryNode {
+        name: name.to_string(),
+        info: virtual_directory(Path::new("")),
+        children: groups,
+        truncated: false,
+    })
+}
+
+// Stand-in for a directory with nothing on disk to describe
+fn virtual_directory(path: &Path) -> FileInfo {
+    FileInfo::new(path, true)
+}
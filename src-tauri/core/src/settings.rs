@@ -0,0 +1,210 @@
+use crate::atomic_file::write_atomically;
+use crate::storage::{BackendFeature, Database, StorageBackend};
+use crate::{tag_manifest, AccessError};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "type", content = "message")]
+pub enum SettingsError {
+    #[error("Failed to acquire settings lock: {0}")]
+    Lock(String),
+
+    #[error("Settings file is not available")]
+    Unavailable,
+
+    #[error("Failed to read settings: {0}")]
+    Read(String),
+
+    #[error("Failed to write settings: {0}")]
+    Write(String),
+
+    #[error(transparent)]
+    Access(#[from] AccessError),
+
+    #[error("Failed to copy tags to the new storage backend: {0}")]
+    SwitchBackend(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    // Which database the app uses. Changing it copies the tags over and
+    // switches at once.
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+}
+
+// A backend the settings can choose, with what the app cannot do on it
+#[derive(Debug, Serialize)]
+pub struct StorageBackendInfo {
+    backend: StorageBackend,
+    file_name: &'static str,
+    missing_features: &'static [BackendFeature],
+}
+
+pub fn list_storage_backends() -> Vec<StorageBackendInfo> {
+    [StorageBackend::DuckDb, StorageBackend::Sqlite]
+        .into_iter()
+        .map(|backend| StorageBackendInfo {
+            backend,
+            file_name: backend.file_name(),
+            missing_features: backend.missing_features(),
+        })
+        .collect()
+}
+
+// Where the desktop app keeps its settings. It is empty until the app data
+// directory is known.
+#[derive(Default)]
+pub struct SettingsFile {
+    pub path: Mutex<Option<PathBuf>>,
+}
+
+// A missing file yields the defaults
+pub fn load_settings(path: &Path) -> Result<Settings, SettingsError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            serde_json::from_str(&contents).map_err(|err| SettingsError::Read(err.to_string()))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+        Err(err) => Err(SettingsError::Read(err.to_string())),
+    }
+}
+
+pub fn save_settings(path: &Path, settings: &Settings) -> Result<(), SettingsError> {
+    let contents = serde_json::to_string_pretty(settings)
+        .map_err(|err| SettingsError::Write(err.to_string()))?;
+    write_atomically(path, contents).map_err(|err| SettingsError::Write(err.to_string()))
+}
+
+fn settings_path(file: &SettingsFile) -> Result<PathBuf, SettingsError> {
+    file.path
+        .lock()
+        .map_err(|err| SettingsError::Lock(err.to_string()))?
+        .clone()
+        .ok_or(SettingsError::Unavailable)
+}
+
+pub fn get_settings(file: &SettingsFile) -> Result<Settings, SettingsError> {
+    load_settings(&settings_path(file)?)
+}
+
+// Saves `settings`. A new storage backend gets a copy of the open
+// database's tags, in the file next to the settings, and replaces it.
+pub fn update_settings(
+    file: &SettingsFile,
+    database: &mut Database,
+    settings: &Settings,
+) -> Result<(), SettingsError> {
+    let path = settings_path(file)?;
+    let backend = settings.storage_backend;
+    if database.backend() == backend {
+        return save_settings(&path, settings);
+    }
+
+    let target = path.with_file_name(backend.file_name());
+    let switched = database
+        .copy_tags_into(backend, &target)
+        .map_err(|err| SettingsError::SwitchBackend(err.to_string()))?;
+    if backend
+        .missing_features()
+        .contains(&BackendFeature::TagManifests)
+    {
+        warn_about_manifest_roots(database.duckdb());
+    }
+    save_settings(&path, settings)?;
+    *database = switched;
+    info!("Switched to the {:?} database at {:?}", backend, target);
+    Ok(())
+}
+
+// Manifests stop following tag changes once the database cannot keep their roots
+fn warn_about_manifest_roots(connection: Option<&mut duckdb::Connection>) {
+    let Some(connection) = connection else {
+        return;
+    };
+    match tag_manifest::list_tag_manifest_roots(connection) {
+        Ok(roots) if roots.is_empty() => {}
+        Ok(roots) => warn!(
+            "Tag manifests under {} roots will no longer be updated",
+            roots.len()
+        ),
+        Err(err) => warn!("Could not load tag manifest roots: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip_and_default_when_missing() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let file = SettingsFile::default();
+        assert!(matches!(
+            get_settings(&file),
+            Err(SettingsError::Unavailable)
+        ));
+
+        *file.path.lock().unwrap() = Some(temp.path().join("settings.json"));
+        assert_eq!(get_settings(&file).expect("defaults"), Settings::default());
+
+        let settings = Settings {
+            storage_backend: StorageBackend::Sqlite,
+        };
+        let mut database = Database::open_in_memory(StorageBackend::Sqlite).expect("in memory db");
+        update_settings(&file, &mut database, &settings).expect("save");
+        assert_eq!(get_settings(&file).expect("load"), settings);
+
+        let written = fs::read_to_string(temp.path().join("settings.json")).expect("read");
+        assert!(written.contains("\"storage_backend\": \"sqlite\""));
+    }
+
+    #[test]
+    fn switching_backends_carries_the_tags_over() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let file = SettingsFile {
+            path: Mutex::new(Some(temp.path().join("settings.json"))),
+        };
+        let photos = Path::new("/photos");
+        let mut database = Database::open_in_memory(StorageBackend::DuckDb).expect("in memory db");
+        crate::tagging::assign_tag_to_paths(&mut database, &[photos.to_path_buf()], "holiday")
+            .expect("tag");
+        crate::path_matching::set_path_matching(
+            database.storage(),
+            photos,
+            crate::path_matching::PathMatching {
+                case_insensitive: true,
+                unicode_normalized: false,
+            },
+        )
+        .expect("path matching");
+
+        for backend in [StorageBackend::Sqlite, StorageBackend::DuckDb] {
+            let settings = Settings {
+                storage_backend: backend,
+            };
+            update_settings(&file, &mut database, &settings).expect("switch");
+
+            assert_eq!(database.backend(), backend);
+            // Found through the case-insensitive root, so the rule came along too
+            let tags =
+                crate::tagging::get_tags_for_paths(database.storage(), &[PathBuf::from("/PHOTOS")])
+                    .expect("tags");
+            assert_eq!(tags.values().next(), Some(&vec!["holiday".to_string()]));
+        }
+        assert!(temp
+            .path()
+            .join(StorageBackend::Sqlite.file_name())
+            .is_file());
+        assert!(temp
+            .path()
+            .join(StorageBackend::DuckDb.file_name())
+            .is_file());
+    }
+}
@@ -0,0 +1,368 @@
+use crate::tagging::TaggingError;
+use duckdb::types::Value;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_ROWS: usize = 1_000;
+const MAX_ROWS_CAP: usize = 100_000;
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+const TIMEOUT_CAP_MS: u64 = 120_000;
+
+// Leading keywords of statements that only read. Anything else is rejected
+// before it reaches DuckDB, which also runs the query in a read-only
+// transaction as a second line of defence.
+const READ_ONLY_KEYWORDS: [&str; 10] = [
+    "SELECT",
+    "WITH",
+    "FROM",
+    "VALUES",
+    "TABLE",
+    "DESCRIBE",
+    "SHOW",
+    "SUMMARIZE",
+    "PIVOT",
+    "UNPIVOT",
+];
+
+// Column types returned as JSON scalars; everything else is rendered as text
+const NATIVE_TYPES: [&str; 12] = [
+    "BOOLEAN",
+    "TINYINT",
+    "SMALLINT",
+    "INTEGER",
+    "BIGINT",
+    "UTINYINT",
+    "USMALLINT",
+    "UINTEGER",
+    "UBIGINT",
+    "FLOAT",
+    "DOUBLE",
+    "VARCHAR",
+];
+
+// Limits for `run_readonly_query`; values above the caps are lowered to them
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryLimits {
+    pub max_rows: Option<usize>,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryColumn {
+    name: String,
+    // DuckDB's name for the type, e.g. `VARCHAR` or `INTEGER[]`
+    #[serde(rename = "type")]
+    column_type: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryResult {
+    columns: Vec<QueryColumn>,
+    rows: Vec<Vec<serde_json::Value>>,
+    // Set when the query produced more than `max_rows` rows
+    truncated: bool,
+    elapsed_ms: u64,
+}
+
+// Runs one ad-hoc query against the tag database for analytics. Only
+// queries are accepted, and nothing they do outlives the call.
+pub fn run_readonly_query(
+    connection: &duckdb::Connection,
+    sql: &str,
+    limits: QueryLimits,
+) -> Result<QueryResult, TaggingError> {
+    let sql = sql.trim().trim_end_matches(';').trim_end();
+    let keyword = leading_keyword(sql);
+    if keyword.is_empty() {
+        return Err(TaggingError::EmptyQuery);
+    }
+    if !READ_ONLY_KEYWORDS.contains(&keyword.as_str()) {
+        return Err(TaggingError::ReadOnlyQuery(keyword));
+    }
+
+    let max_rows = limits
+        .max_rows
+        .unwrap_or(DEFAULT_MAX_ROWS)
+        .clamp(1, MAX_ROWS_CAP);
+    let timeout_ms = limits
+        .timeout_ms
+        .unwrap_or(DEFAULT_TIMEOUT_MS)
+        .clamp(1, TIMEOUT_CAP_MS);
+
+    connection
+        .execute_batch("BEGIN TRANSACTION READ ONLY")
+        .map_err(|err| TaggingError::Database(err.to_string()))?;
+    let result = with_timeout(connection, timeout_ms, || {
+        fetch_rows(connection, sql, max_rows)
+    });
+    // The transaction never wrote anything, so rolling back only releases it
+    let _ = connection.execute_batch("ROLLBACK");
+
+    result
+}
+
+// Runs `operation`, interrupting DuckDB once `timeout_ms` has passed
+fn with_timeout<T>(
+    connection: &duckdb::Connection,
+    timeout_ms: u64,
+    operation: impl FnOnce() -> Result<T, TaggingError>,
+) -> Result<T, TaggingError> {
+    let interrupt = connection.interrupt_handle();
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let (finished, wait) = mpsc::channel::<()>();
+    let watchdog = {
+        let timed_out = Arc::clone(&timed_out);
+        thread::spawn(move || {
+            if let Err(mpsc::RecvTimeoutError::Timeout) =
+                wait.recv_timeout(Duration::from_millis(timeout_ms))
+            {
+                timed_out.store(true, Ordering::SeqCst);
+                interrupt.interrupt();
+            }
+        })
+    };
+
+    let result = operation();
+    let _ = finished.send(());
+    let _ = watchdog.join();
+
+    if timed_out.load(Ordering::SeqCst) {
+        return Err(TaggingError::QueryTimeout(timeout_ms));
+    }
+    result
+}
+
+fn fetch_rows(
+    connection: &duckdb::Connection,
+    sql: &str,
+    max_rows: usize,
+) -> Result<QueryResult, TaggingError> {
+    let started = Instant::now();
+
+    // Wrapping the text as a subquery means only a single query parses
+    let columns = {
+        let mut statement = connection
+            .prepare(&format!("DESCRIBE SELECT * FROM ({sql}) AS q"))
+            .map_err(query_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(QueryColumn {
+                    name: row.get(0)?,
+                    column_type: row.get(1)?,
+                })
+            })
+            .map_err(query_error)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(query_error)?
+    };
+
+    // Positional aliases sidestep duplicate or awkward column names
+    let aliases: Vec<String> = (1..=columns.len())
+        .map(|index| format!("c{index}"))
+        .collect();
+    let selected: Vec<String> = columns
+        .iter()
+        .zip(&aliases)
+        .map(|(column, alias)| {
+            if NATIVE_TYPES.contains(&column.column_type.as_str()) {
+                format!("q.{alias}")
+            } else {
+                format!("CAST(q.{alias} AS VARCHAR)")
+            }
+        })
+        .collect();
+    let wrapped = format!(
+        "SELECT {} FROM ({sql}) AS q({}) LIMIT {}",
+        selected.join(", "),
+        aliases.join(", "),
+        max_rows + 1
+    );
+
+    let mut statement = connection.prepare(&wrapped).map_err(query_error)?;
+    let mut rows = statement.query([]).map_err(query_error)?;
+    let mut values = Vec::new();
+    while let Some(row) = rows.next().map_err(query_error)? {
+        let mut record = Vec::with_capacity(columns.len());
+        for index in 0..columns.len() {
+            let value: Value = row.get(index).map_err(query_error)?;
+            record.push(to_json(value));
+        }
+        values.push(record);
+    }
+
+    let truncated = values.len() > max_rows;
+    values.truncate(max_rows);
+    Ok(QueryResult {
+        columns,
+        rows: values,
+        truncated,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+// First keyword of the statement, skipping comments and opening parentheses
+fn leading_keyword(sql: &str) -> String {
+    let mut rest = sql;
+    loop {
+        rest = rest.trim_start().trim_start_matches('(');
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, after)| after);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        } else if rest.starts_with(char::is_whitespace) || rest.starts_with('(') {
+            continue;
+        } else {
+            break;
+        }
+    }
+
+    rest.chars()
+        .take_while(|ch| ch.is_ascii_alphabetic())
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+fn to_json(value: Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Boolean(value) => value.into(),
+        Value::TinyInt(value) => value.into(),
+        Value::SmallInt(value) => value.into(),
+        Value::Int(value) => value.into(),
+        Value::BigInt(value) => value.into(),
+        Value::UTinyInt(value) => value.into(),
+        Value::USmallInt(value) => value.into(),
+        Value::UInt(value) => value.into(),
+        Value::UBigInt(value) => value.into(),
+        // NaN and infinities have no JSON form and become null
+        Value::Float(value) => serde_json::Number::from_f64(value.into()).into(),
+        Value::Double(value) => serde_json::Number::from_f64(value).into(),
+        Value::Text(value) => value.into(),
+        other => format!("{other:?}").into(),
+    }
+}
+
+// The read-only transaction is the last guard, so its refusal is reported
+// the same way as a rejected keyword
+fn query_error(err: duckdb::Error) -> TaggingError {
+    let message = err.to_string();
+    if message.contains("read-only mode") {
+        TaggingError::ReadOnlyQuery(message)
+    } else {
+        TaggingError::Database(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_connection() -> duckdb::Connection {
+        let connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        crate::tagging::ensure_schema(&connection).expect("tag schema");
+        for (path, tag) in [("/a", "red"), ("/a/b", "red"), ("/c", "blue")] {
+            connection
+                .execute(
+                    "INSERT INTO path_tags (path, tag, path_depth) VALUES (?1, ?2, 1)",
+                    duckdb::params![path, tag],
+                )
+                .expect("insert");
+        }
+        connection
+    }
+
+    #[test]
+    fn returns_typed_columns_and_truncates_rows() {
+        let connection = test_connection();
+        let result = run_readonly_query(
+            &connection,
+            "-- tag usage\nSELECT tag, count(*) AS uses, list(path ORDER BY path) AS paths
+             FROM path_tags GROUP BY tag ORDER BY uses DESC;",
+            QueryLimits {
+                max_rows: Some(1),
+                ..QueryLimits::default()
+            },
+        )
+        .expect("query");
+
+        let types: Vec<(&str, &str)> = result
+            .columns
+            .iter()
+            .map(|column| (column.name.as_str(), column.column_type.as_str()))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                ("tag", "VARCHAR"),
+                ("uses", "BIGINT"),
+                ("paths", "VARCHAR[]")
+            ]
+        );
+        assert!(result.truncated);
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                serde_json::json!("red"),
+                serde_json::json!(2),
+                serde_json::json!("[/a, /a/b]")
+            ]]
+        );
+    }
+
+    #[test]
+    fn rejects_statements_that_could_write() {
+        let connection = test_connection();
+        for sql in [
+            "DELETE FROM path_tags",
+            "/* sneaky */ DROP TABLE path_tags",
+            "ATTACH 'other.db'",
+            "COPY path_tags TO 'tags.csv'",
+        ] {
+            assert!(
+                matches!(
+                    run_readonly_query(&connection, sql, QueryLimits::default()),
+                    Err(TaggingError::ReadOnlyQuery(_))
+                ),
+                "{sql:?} should be rejected"
+            );
+        }
+        assert!(matches!(
+            run_readonly_query(&connection, " ; ", QueryLimits::default()),
+            Err(TaggingError::EmptyQuery)
+        ));
+        assert!(run_readonly_query(
+            &connection,
+            "SELECT 1); DROP TABLE path_tags; (SELECT 1",
+            QueryLimits::default()
+        )
+        .is_err());
+
+        let count = run_readonly_query(
+            &connection,
+            "SELECT count(*) FROM path_tags",
+            QueryLimits::default(),
+        )
+        .expect("table survives");
+        assert_eq!(count.rows, vec![vec![serde_json::json!(3)]]);
+    }
+
+    #[test]
+    fn interrupts_queries_that_run_too_long() {
+        let connection = test_connection();
+        let result = run_readonly_query(
+            &connection,
+            "SELECT sum(i * i) FROM range(1000000000000) t(i)",
+            QueryLimits {
+                timeout_ms: Some(50),
+                ..QueryLimits::default()
+            },
+        );
+        assert!(matches!(result, Err(TaggingError::QueryTimeout(50))));
+
+        // The connection is usable again afterwards
+        run_readonly_query(&connection, "SELECT 1", QueryLimits::default()).expect("query");
+    }
+}
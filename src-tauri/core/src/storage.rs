@@ -0,0 +1,348 @@
+mod duckdb_backend;
+mod sqlite_backend;
+
+use crate::path_codec::encode_path;
+use crate::path_matching::{PathMatching, PathMatchingRules};
+use crate::tagging::{self, TaggingError};
+use crate::{saved_searches, scan, tag_manifest};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Where `path_tags` and `path_matching` live. Backends only run the queries;
+// normalization and path matching stay in `tagging`, so every backend
+// answers lookups the same way.
+pub trait TagStorage {
+    fn ensure_schema(&self) -> Result<(), TaggingError>;
+
+    fn path_matching_rules(&self) -> Result<PathMatchingRules, TaggingError>;
+
+    // Exact matching removes the entry for `root`
+    fn set_path_matching(&self, root: &str, matching: PathMatching) -> Result<(), TaggingError>;
+
+    // `(path, tag)` rows of `root` and of the paths below it no deeper than
+    // `max_depth`. Once `rules` fold, candidates are compared loosely and
+    // callers narrow them down.
+    fn descendant_rows(
+        &self,
+        rules: &PathMatchingRules,
+        root: &str,
+        root_depth: i64,
+        max_depth: i64,
+    ) -> Result<Vec<(String, String)>, TaggingError>;
+
+    // `(path, tag)` rows stored under any of `paths`, compared like `descendant_rows`
+    fn rows_for_paths(
+        &self,
+        rules: &PathMatchingRules,
+        paths: &[String],
+    ) -> Result<Vec<(String, String)>, TaggingError>;
+
+    // Distinct stored paths that `rules` might consider equal to `path`, sorted
+    fn candidate_spellings(
+        &self,
+        rules: &PathMatchingRules,
+        path: &str,
+    ) -> Result<Vec<String>, TaggingError>;
+
+    // Adds `tag` to every `(path, depth)` in one transaction
+    fn insert_tag(&mut self, paths: &[(String, i64)], tag: &str) -> Result<(), TaggingError>;
+
+    // Deletes every tag stored under `removed`, then stores `tags` on `path`
+    fn replace_tags(
+        &self,
+        removed: &[String],
+        path: &str,
+        depth: i64,
+        tags: &[String],
+    ) -> Result<(), TaggingError>;
+
+    // `(path, tag)` rows the last xattr sync agreed on for `root` and the paths below it
+    fn sync_baseline_rows(&self, root: &str) -> Result<Vec<(String, String)>, TaggingError>;
+
+    // Records `tags` as what both sides of an xattr sync hold for `path`
+    fn set_sync_baseline(&self, path: &str, tags: &[String]) -> Result<(), TaggingError>;
+
+    // Every stored tag and sync baseline row, for copying to another backend
+    fn tag_tables(&self) -> Result<TagTables, TaggingError>;
+
+    // Replaces every stored tag and sync baseline row with `tables`
+    fn replace_tag_tables(&self, tables: &TagTables) -> Result<(), TaggingError>;
+}
+
+// The rows a backend switch carries over; see `Database::copy_tags_into`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TagTables {
+    pub(crate) tags: Vec<StoredTag>,
+    // `(path, tag)` rows of `xattr_sync_baseline`
+    pub(crate) sync_baseline: Vec<(String, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct StoredTag {
+    pub(crate) path: String,
+    pub(crate) tag: String,
+    pub(crate) path_depth: Option<i64>,
+    // As text, which both backends read back as a timestamp
+    pub(crate) created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageBackend {
+    #[default]
+    #[serde(rename = "duckdb")]
+    DuckDb,
+    // Lighter, and leaves the file open to other processes, but without the
+    // features built on DuckDB; see `missing_features`
+    #[serde(rename = "sqlite")]
+    Sqlite,
+}
+
+// App features that need tables or functions only DuckDB has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendFeature {
+    ScanCache,
+    Search,
+    FacetFilters,
+    SavedSearches,
+    SqlConsole,
+    TagImportExport,
+    // `.tags` files are neither written nor updated as tags change
+    TagManifests,
+}
+
+impl StorageBackend {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::DuckDb => "data.duckdb",
+            Self::Sqlite => "data.sqlite",
+        }
+    }
+
+    pub fn missing_features(self) -> &'static [BackendFeature] {
+        match self {
+            Self::DuckDb => &[],
+            Self::Sqlite => &[
+                BackendFeature::ScanCache,
+                BackendFeature::Search,
+                BackendFeature::FacetFilters,
+                BackendFeature::SavedSearches,
+                BackendFeature::SqlConsole,
+                BackendFeature::TagImportExport,
+                BackendFeature::TagManifests,
+            ],
+        }
+    }
+}
+
+// An open tag database with every table its backend supports
+pub enum Database {
+    DuckDb(duckdb::Connection),
+    Sqlite(rusqlite::Connection),
+}
+
+impl Database {
+    pub fn open(backend: StorageBackend, path: &Path) -> Result<Self, TaggingError> {
+        let database = match backend {
+            StorageBackend::DuckDb => Self::DuckDb(
+                duckdb::Connection::open(path)
+                    .map_err(|err| TaggingError::Database(err.to_string()))?,
+            ),
+            StorageBackend::Sqlite => Self::Sqlite(sqlite_backend::open(path)?),
+        };
+        database.ensure_schema()?;
+        Ok(database)
+    }
+
+    pub fn open_in_memory(backend: StorageBackend) -> Result<Self, TaggingError> {
+        let database = match backend {
+            StorageBackend::DuckDb => Self::DuckDb(
+                duckdb::Connection::open_in_memory()
+                    .map_err(|err| TaggingError::Database(err.to_string()))?,
+            ),
+            StorageBackend::Sqlite => Self::Sqlite(sqlite_backend::open_in_memory()?),
+        };
+        database.ensure_schema()?;
+        Ok(database)
+    }
+
+    fn ensure_schema(&self) -> Result<(), TaggingError> {
+        tagging::ensure_schema(self.storage())?;
+        if let Self::DuckDb(connection) = self {
+            let database = |err: &dyn std::fmt::Display| TaggingError::Database(err.to_string());
+            scan::ensure_cache_schema(connection).map_err(|err| database(&err))?;
+            saved_searches::ensure_schema(connection).map_err(|err| database(&err))?;
+            tag_manifest::ensure_schema(connection).map_err(|err| database(&err))?;
+        }
+        Ok(())
+    }
+
+    pub fn backend(&self) -> StorageBackend {
+        match self {
+            Self::DuckDb(_) => StorageBackend::DuckDb,
+            Self::Sqlite(_) => StorageBackend::Sqlite,
+        }
+    }
+
+    pub fn storage(&self) -> &dyn TagStorage {
+        match self {
+            Self::DuckDb(connection) => connection,
+            Self::Sqlite(connection) => connection,
+        }
+    }
+
+    pub fn storage_mut(&mut self) -> &mut dyn TagStorage {
+        match self {
+            Self::DuckDb(connection) => connection,
+            Self::Sqlite(connection) => connection,
+        }
+    }
+
+    // Runs `operation` in one transaction, committed only if it succeeds
+    pub(crate) fn in_transaction<T>(
+        &mut self,
+        operation: impl FnOnce(&dyn TagStorage) -> Result<T, TaggingError>,
+    ) -> Result<T, TaggingError> {
+        let database = |err: &dyn std::fmt::Display| TaggingError::Database(err.to_string());
+        match self {
+            Self::DuckDb(connection) => {
+                let transaction = connection.transaction().map_err(|err| database(&err))?;
+                let result = operation(&*transaction)?;
+                transaction.commit().map_err(|err| database(&err))?;
+                Ok(result)
+            }
+            Self::Sqlite(connection) => {
+                let transaction = connection.transaction().map_err(|err| database(&err))?;
+                let result = operation(&*transaction)?;
+                transaction.commit().map_err(|err| database(&err))?;
+                Ok(result)
+            }
+        }
+    }
+
+    // Opens the `backend` database at `path` holding this database's tags,
+    // path matching rules and sync baselines in place of its own, so switching
+    // backends loses no tags. Tables only DuckDB has, such as the scan cache
+    // and saved searches, are not carried over.
+    pub fn copy_tags_into(
+        &self,
+        backend: StorageBackend,
+        path: &Path,
+    ) -> Result<Database, TaggingError> {
+        let tables = self.storage().tag_tables()?;
+        let rules = self.storage().path_matching_rules()?;
+        let mut target = Database::open(backend, path)?;
+        target.in_transaction(|storage| {
+            storage.replace_tag_tables(&tables)?;
+            for (root, _) in storage.path_matching_rules()?.roots() {
+                storage.set_path_matching(&encode_path(root), PathMatching::EXACT)?;
+            }
+            for (root, matching) in rules.roots() {
+                storage.set_path_matching(&encode_path(root), *matching)?;
+            }
+            Ok(())
+        })?;
+        Ok(target)
+    }
+
+    // The connection behind the features that only DuckDB supports
+    pub fn duckdb(&mut self) -> Option<&mut duckdb::Connection> {
+        match self {
+            Self::DuckDb(connection) => Some(connection),
+            Self::Sqlite(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // One empty database per backend, for tests that must pass on all of them
+    pub(crate) fn every_backend() -> Vec<Database> {
+        [StorageBackend::DuckDb, StorageBackend::Sqlite]
+            .into_iter()
+            .map(|backend| Database::open_in_memory(backend).expect("in memory db"))
+            .collect()
+    }
+
+    #[test]
+    fn sqlite_files_stay_open_to_other_connections() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let path = temp.path().join(StorageBackend::Sqlite.file_name());
+        let mut database = Database::open(StorageBackend::Sqlite, &path).expect("open");
+        database
+            .storage_mut()
+            .insert_tag(&[("/photos".to_string(), 1)], "holiday")
+            .expect("insert");
+
+        let reader = Database::open(StorageBackend::Sqlite, &path).expect("second open");
+        let rows = reader
+            .storage()
+            .rows_for_paths(&PathMatchingRules::default(), &["/photos".to_string()])
+            .expect("rows");
+        assert_eq!(rows, vec![("/photos".to_string(), "holiday".to_string())]);
+        assert!(database.duckdb().is_none());
+    }
+
+    #[test]
+    fn reencodes_replacement_characters_stored_before_escaping_once() {
+        let connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        connection
+            .execute_batch(
+                "
+                CREATE TABLE path_tags (
+                    path TEXT NOT NULL,
+                    tag  TEXT NOT NULL,
+                    path_depth INTEGER,
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (path, tag)
+                );
+                INSERT INTO path_tags (path, tag, path_depth) VALUES ('/data/\u{FFFD}E9', 'legacy', 2);
+                ",
+            )
+            .expect("legacy table");
+        let path = std::path::Path::new("/data/\u{FFFD}E9");
+
+        for _ in 0..2 {
+            connection.ensure_schema().expect("schema");
+            let tags = crate::tagging::get_tags_for_paths(&connection, &[path.to_path_buf()])
+                .expect("fetch tags");
+            assert_eq!(tags.get(path), Some(&vec!["legacy".to_string()]));
+        }
+    }
+
+    #[test]
+    fn folds_paths_tagged_before_folded_keys_existed() {
+        let connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        connection
+            .execute_batch(
+                "
+                CREATE TABLE path_tags (
+                    path TEXT NOT NULL,
+                    tag  TEXT NOT NULL,
+                    path_depth INTEGER,
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (path, tag)
+                );
+                INSERT INTO path_tags (path, tag, path_depth) VALUES ('/data/Photo.JPG', 'legacy', 2);
+                ",
+            )
+            .expect("legacy table");
+        connection.ensure_schema().expect("schema");
+        connection
+            .set_path_matching(
+                "/data",
+                PathMatching {
+                    case_insensitive: true,
+                    unicode_normalized: false,
+                },
+            )
+            .expect("matching");
+
+        let path = std::path::PathBuf::from("/data/photo.jpg");
+        let tags = crate::tagging::get_tags_for_paths(&connection, std::slice::from_ref(&path))
+            .expect("fetch tags");
+        assert_eq!(tags.get(&path), Some(&vec!["legacy".to_string()]));
+    }
+}
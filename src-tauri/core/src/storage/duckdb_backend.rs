@@ -0,0 +1,308 @@
+use super::{StoredTag, TagStorage, TagTables};
+use crate::path_matching::{self, PathMatching, PathMatchingRules};
+use crate::tagging::{descendant_like_pattern, TaggingError};
+
+fn database_error(err: duckdb::Error) -> TaggingError {
+    TaggingError::Database(err.to_string())
+}
+
+fn collect_rows(
+    connection: &duckdb::Connection,
+    sql: &str,
+    params: impl duckdb::Params,
+) -> Result<Vec<(String, String)>, TaggingError> {
+    let mut statement = connection.prepare(sql).map_err(database_error)?;
+    let rows = statement
+        .query_map(params, |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(database_error)?;
+    rows.collect::<Result<_, _>>().map_err(database_error)
+}
+
+// Tags stored before `path_codec` escaped non-UTF-8 names hold U+FFFD
+// literally, where the codec now doubles it. They are re-encoded once; the
+// SQLite backend came later and always held encoded paths.
+fn reencode_literal_replacement_characters(
+    connection: &duckdb::Connection,
+) -> Result<(), TaggingError> {
+    connection
+        .execute(
+            "CREATE TABLE IF NOT EXISTS path_encoding (version INTEGER NOT NULL)",
+            [],
+        )
+        .map_err(database_error)?;
+    let version: Option<i64> = connection
+        .query_row("SELECT max(version) FROM path_encoding", [], |row| {
+            row.get(0)
+        })
+        .map_err(database_error)?;
+    if version.is_some() {
+        return Ok(());
+    }
+
+    connection
+        .execute_batch(
+            "
+            BEGIN TRANSACTION;
+            UPDATE path_tags SET path = replace(path, '\u{FFFD}', '\u{FFFD}\u{FFFD}')
+            WHERE contains(path, '\u{FFFD}');
+            INSERT INTO path_encoding VALUES (1);
+            COMMIT;
+            ",
+        )
+        .map_err(database_error)
+}
+
+impl TagStorage for duckdb::Connection {
+    fn ensure_schema(&self) -> Result<(), TaggingError> {
+        self.execute(
+            "
+            CREATE TABLE IF NOT EXISTS path_tags (
+                path TEXT NOT NULL,
+                tag  TEXT NOT NULL,
+                path_depth INTEGER,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                folded_path TEXT,
+                PRIMARY KEY (path, tag)
+            )
+            ",
+            [],
+        )
+        .map_err(database_error)?;
+
+        self.execute(
+            "
+            CREATE TABLE IF NOT EXISTS xattr_sync_baseline (
+                path TEXT NOT NULL,
+                tag  TEXT NOT NULL,
+                PRIMARY KEY (path, tag)
+            )
+            ",
+            [],
+        )
+        .map_err(database_error)?;
+
+        reencode_literal_replacement_characters(self)?;
+
+        // `path` as folding roots compare it, kept on every row so lookups
+        // under those roots can use an index
+        self.execute_batch(
+            "
+            ALTER TABLE path_tags ADD COLUMN IF NOT EXISTS folded_path TEXT;
+            UPDATE path_tags SET folded_path = nfc_normalize(lower(path)) WHERE folded_path IS NULL;
+            CREATE INDEX IF NOT EXISTS path_tags_folded_path_idx ON path_tags (folded_path);
+            ",
+        )
+        .map_err(database_error)?;
+
+        path_matching::ensure_schema(self)
+    }
+
+    fn path_matching_rules(&self) -> Result<PathMatchingRules, TaggingError> {
+        PathMatchingRules::load(self)
+    }
+
+    fn set_path_matching(&self, root: &str, matching: PathMatching) -> Result<(), TaggingError> {
+        path_matching::store_path_matching(self, root, matching)
+    }
+
+    fn descendant_rows(
+        &self,
+        rules: &PathMatchingRules,
+        root: &str,
+        root_depth: i64,
+        max_depth: i64,
+    ) -> Result<Vec<(String, String)>, TaggingError> {
+        let sql = format!(
+            "
+            SELECT path, tag
+            FROM path_tags
+            WHERE {column} = {root}
+               OR (path_depth > ?2 AND path_depth <= ?3 AND {column} LIKE {pattern} ESCAPE '\\')
+            ",
+            column = rules.column(),
+            root = rules.parameter("?1"),
+            pattern = rules.parameter("?4"),
+        );
+        collect_rows(
+            self,
+            &sql,
+            duckdb::params![root, root_depth, max_depth, descendant_like_pattern(root)],
+        )
+    }
+
+    fn rows_for_paths(
+        &self,
+        rules: &PathMatchingRules,
+        paths: &[String],
+    ) -> Result<Vec<(String, String)>, TaggingError> {
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholder_list = std::iter::repeat_n(rules.parameter("?"), paths.len())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "SELECT path, tag FROM path_tags WHERE {} IN ({})",
+            rules.column(),
+            placeholder_list
+        );
+        collect_rows(
+            self,
+            &sql,
+            duckdb::params_from_iter(paths.iter().map(String::as_str)),
+        )
+    }
+
+    fn candidate_spellings(
+        &self,
+        rules: &PathMatchingRules,
+        path: &str,
+    ) -> Result<Vec<String>, TaggingError> {
+        let sql = format!(
+            "SELECT DISTINCT path FROM path_tags WHERE {} = {} ORDER BY path",
+            rules.column(),
+            rules.parameter("?1")
+        );
+        let mut statement = self.prepare(&sql).map_err(database_error)?;
+        let rows = statement
+            .query_map(duckdb::params![path], |row| row.get(0))
+            .map_err(database_error)?;
+        rows.collect::<Result<_, _>>().map_err(database_error)
+    }
+
+    fn insert_tag(&mut self, paths: &[(String, i64)], tag: &str) -> Result<(), TaggingError> {
+        let transaction = self.transaction().map_err(database_error)?;
+        {
+            let mut statement = transaction
+                .prepare(
+                    "INSERT OR REPLACE INTO path_tags (path, tag, path_depth, folded_path)
+                     VALUES (?1, ?2, ?3, nfc_normalize(lower(?1)))",
+                )
+                .map_err(database_error)?;
+            for (path, depth) in paths {
+                statement
+                    .execute(duckdb::params![path, tag, depth])
+                    .map_err(database_error)?;
+            }
+        }
+        transaction.commit().map_err(database_error)
+    }
+
+    fn replace_tags(
+        &self,
+        removed: &[String],
+        path: &str,
+        depth: i64,
+        tags: &[String],
+    ) -> Result<(), TaggingError> {
+        for removed_path in removed {
+            self.execute(
+                "DELETE FROM path_tags WHERE path = ?1",
+                duckdb::params![removed_path],
+            )
+            .map_err(database_error)?;
+        }
+
+        let mut statement = self
+            .prepare(
+                "INSERT OR REPLACE INTO path_tags (path, tag, path_depth, folded_path)
+                 VALUES (?1, ?2, ?3, nfc_normalize(lower(?1)))",
+            )
+            .map_err(database_error)?;
+        for tag in tags {
+            statement
+                .execute(duckdb::params![path, tag, depth])
+                .map_err(database_error)?;
+        }
+        Ok(())
+    }
+
+    fn sync_baseline_rows(&self, root: &str) -> Result<Vec<(String, String)>, TaggingError> {
+        collect_rows(
+            self,
+            "SELECT path, tag FROM xattr_sync_baseline WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'",
+            duckdb::params![root, descendant_like_pattern(root)],
+        )
+    }
+
+    fn set_sync_baseline(&self, path: &str, tags: &[String]) -> Result<(), TaggingError> {
+        self.execute(
+            "DELETE FROM xattr_sync_baseline WHERE path = ?1",
+            duckdb::params![path],
+        )
+        .map_err(database_error)?;
+
+        let mut statement = self
+            .prepare("INSERT OR REPLACE INTO xattr_sync_baseline (path, tag) VALUES (?1, ?2)")
+            .map_err(database_error)?;
+        for tag in tags {
+            statement
+                .execute(duckdb::params![path, tag])
+                .map_err(database_error)?;
+        }
+        Ok(())
+    }
+
+    fn tag_tables(&self) -> Result<TagTables, TaggingError> {
+        let mut statement = self
+            .prepare(
+                "SELECT path, tag, path_depth, CAST(created_at AS VARCHAR)
+                 FROM path_tags ORDER BY path, tag",
+            )
+            .map_err(database_error)?;
+        let tags = statement
+            .query_map([], |row| {
+                Ok(StoredTag {
+                    path: row.get(0)?,
+                    tag: row.get(1)?,
+                    path_depth: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })
+            .map_err(database_error)?
+            .collect::<Result<_, _>>()
+            .map_err(database_error)?;
+
+        Ok(TagTables {
+            tags,
+            sync_baseline: collect_rows(
+                self,
+                "SELECT path, tag FROM xattr_sync_baseline ORDER BY path, tag",
+                [],
+            )?,
+        })
+    }
+
+    fn replace_tag_tables(&self, tables: &TagTables) -> Result<(), TaggingError> {
+        self.execute_batch("DELETE FROM path_tags; DELETE FROM xattr_sync_baseline;")
+            .map_err(database_error)?;
+
+        let mut statement = self
+            .prepare(
+                "INSERT INTO path_tags (path, tag, path_depth, created_at, folded_path)
+                 VALUES (?1, ?2, ?3, COALESCE(CAST(?4 AS TIMESTAMP), current_timestamp),
+                         nfc_normalize(lower(?1)))",
+            )
+            .map_err(database_error)?;
+        for row in &tables.tags {
+            statement
+                .execute(duckdb::params![
+                    row.path,
+                    row.tag,
+                    row.path_depth,
+                    row.created_at
+                ])
+                .map_err(database_error)?;
+        }
+
+        let mut statement = self
+            .prepare("INSERT INTO xattr_sync_baseline (path, tag) VALUES (?1, ?2)")
+            .map_err(database_error)?;
+        for (path, tag) in &tables.sync_baseline {
+            statement
+                .execute(duckdb::params![path, tag])
+                .map_err(database_error)?;
+        }
+        Ok(())
+    }
+}
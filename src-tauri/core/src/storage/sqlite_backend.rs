@@ -0,0 +1,350 @@
+use super::{StoredTag, TagStorage, TagTables};
+use crate::path_codec::decode_path;
+use crate::path_matching::{loose_fold, PathMatching, PathMatchingRules};
+use crate::tagging::{descendant_like_pattern, TaggingError};
+use rusqlite::functions::FunctionFlags;
+use std::path::Path;
+use std::time::Duration;
+
+// How long a write waits for another process holding the file before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn database_error(err: rusqlite::Error) -> TaggingError {
+    TaggingError::Database(err.to_string())
+}
+
+pub(super) fn open(path: &Path) -> Result<rusqlite::Connection, TaggingError> {
+    let connection = rusqlite::Connection::open(path).map_err(database_error)?;
+    connection
+        .busy_timeout(BUSY_TIMEOUT)
+        .map_err(database_error)?;
+    // Readers in other processes do not block writers, nor the other way round
+    connection
+        .pragma_update(None, "journal_mode", "WAL")
+        .map_err(database_error)?;
+    prepare(connection)
+}
+
+pub(super) fn open_in_memory() -> Result<rusqlite::Connection, TaggingError> {
+    prepare(rusqlite::Connection::open_in_memory().map_err(database_error)?)
+}
+
+// SQLite's `lower` only folds ASCII and it has no Unicode normalization, so
+// loose comparisons go through the same folding the matching rules use
+fn prepare(connection: rusqlite::Connection) -> Result<rusqlite::Connection, TaggingError> {
+    connection
+        .create_scalar_function(
+            "fold_path",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |context| Ok(loose_fold(&context.get::<String>(0)?)),
+        )
+        .map_err(database_error)?;
+    Ok(connection)
+}
+
+// SQL operand for a bound parameter, folded like `folded_path` once any root folds
+fn operand(rules: &PathMatchingRules, expression: &str) -> String {
+    if rules.is_empty() {
+        expression.to_string()
+    } else {
+        format!("fold_path({expression})")
+    }
+}
+
+fn collect_rows(
+    connection: &rusqlite::Connection,
+    sql: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<(String, String)>, TaggingError> {
+    let mut statement = connection.prepare(sql).map_err(database_error)?;
+    let rows = statement
+        .query_map(params, |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(database_error)?;
+    rows.collect::<Result<_, _>>().map_err(database_error)
+}
+
+impl TagStorage for rusqlite::Connection {
+    fn ensure_schema(&self) -> Result<(), TaggingError> {
+        self.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS path_tags (
+                path TEXT NOT NULL,
+                tag  TEXT NOT NULL,
+                path_depth INTEGER,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                folded_path TEXT,
+                PRIMARY KEY (path, tag)
+            );
+            CREATE TABLE IF NOT EXISTS path_matching (
+                root TEXT PRIMARY KEY,
+                case_insensitive BOOLEAN NOT NULL,
+                unicode_normalized BOOLEAN NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS xattr_sync_baseline (
+                path TEXT NOT NULL,
+                tag  TEXT NOT NULL,
+                PRIMARY KEY (path, tag)
+            );
+            ",
+        )
+        .map_err(database_error)?;
+
+        // `path` as folding roots compare it, kept on every row so lookups
+        // under those roots can use an index
+        let has_folded_path: bool = self
+            .query_row(
+                "SELECT count(*) > 0 FROM pragma_table_info('path_tags') WHERE name = 'folded_path'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(database_error)?;
+        if !has_folded_path {
+            self.execute("ALTER TABLE path_tags ADD COLUMN folded_path TEXT", [])
+                .map_err(database_error)?;
+        }
+        self.execute_batch(
+            "
+            UPDATE path_tags SET folded_path = fold_path(path) WHERE folded_path IS NULL;
+            CREATE INDEX IF NOT EXISTS path_tags_folded_path_idx ON path_tags (folded_path);
+            ",
+        )
+        .map_err(database_error)
+    }
+
+    fn path_matching_rules(&self) -> Result<PathMatchingRules, TaggingError> {
+        let mut statement = self
+            .prepare("SELECT root, case_insensitive, unicode_normalized FROM path_matching")
+            .map_err(database_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    decode_path(&row.get::<_, String>(0)?),
+                    PathMatching {
+                        case_insensitive: row.get(1)?,
+                        unicode_normalized: row.get(2)?,
+                    },
+                ))
+            })
+            .map_err(database_error)?;
+
+        Ok(PathMatchingRules::from_roots(
+            rows.collect::<Result<_, _>>().map_err(database_error)?,
+        ))
+    }
+
+    fn set_path_matching(&self, root: &str, matching: PathMatching) -> Result<(), TaggingError> {
+        let result = if matching == PathMatching::EXACT {
+            self.execute(
+                "DELETE FROM path_matching WHERE root = ?1",
+                rusqlite::params![root],
+            )
+        } else {
+            self.execute(
+                "INSERT OR REPLACE INTO path_matching (root, case_insensitive, unicode_normalized)
+                 VALUES (?1, ?2, ?3)",
+                rusqlite::params![root, matching.case_insensitive, matching.unicode_normalized],
+            )
+        };
+
+        result.map(|_| ()).map_err(database_error)
+    }
+
+    fn descendant_rows(
+        &self,
+        rules: &PathMatchingRules,
+        root: &str,
+        root_depth: i64,
+        max_depth: i64,
+    ) -> Result<Vec<(String, String)>, TaggingError> {
+        // LIKE ignores ASCII case here; callers drop paths outside `root`
+        let sql = format!(
+            "
+            SELECT path, tag
+            FROM path_tags
+            WHERE {column} = {root}
+               OR (path_depth > ?2 AND path_depth <= ?3 AND {column} LIKE {pattern} ESCAPE '\\')
+            ",
+            column = rules.column(),
+            root = operand(rules, "?1"),
+            pattern = operand(rules, "?4"),
+        );
+        collect_rows(
+            self,
+            &sql,
+            rusqlite::params![root, root_depth, max_depth, descendant_like_pattern(root)],
+        )
+    }
+
+    fn rows_for_paths(
+        &self,
+        rules: &PathMatchingRules,
+        paths: &[String],
+    ) -> Result<Vec<(String, String)>, TaggingError> {
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholder_list = std::iter::repeat_n(operand(rules, "?"), paths.len())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "SELECT path, tag FROM path_tags WHERE {} IN ({})",
+            rules.column(),
+            placeholder_list
+        );
+        collect_rows(self, &sql, rusqlite::params_from_iter(paths))
+    }
+
+    fn candidate_spellings(
+        &self,
+        rules: &PathMatchingRules,
+        path: &str,
+    ) -> Result<Vec<String>, TaggingError> {
+        let sql = format!(
+            "SELECT DISTINCT path FROM path_tags WHERE {} = {} ORDER BY path",
+            rules.column(),
+            operand(rules, "?1")
+        );
+        let mut statement = self.prepare(&sql).map_err(database_error)?;
+        let rows = statement
+            .query_map(rusqlite::params![path], |row| row.get(0))
+            .map_err(database_error)?;
+        rows.collect::<Result<_, _>>().map_err(database_error)
+    }
+
+    fn insert_tag(&mut self, paths: &[(String, i64)], tag: &str) -> Result<(), TaggingError> {
+        let transaction = self.transaction().map_err(database_error)?;
+        {
+            let mut statement = transaction
+                .prepare(
+                    "INSERT OR REPLACE INTO path_tags (path, tag, path_depth, folded_path)
+                     VALUES (?1, ?2, ?3, fold_path(?1))",
+                )
+                .map_err(database_error)?;
+            for (path, depth) in paths {
+                statement
+                    .execute(rusqlite::params![path, tag, depth])
+                    .map_err(database_error)?;
+            }
+        }
+        transaction.commit().map_err(database_error)
+    }
+
+    fn replace_tags(
+        &self,
+        removed: &[String],
+        path: &str,
+        depth: i64,
+        tags: &[String],
+    ) -> Result<(), TaggingError> {
+        for removed_path in removed {
+            self.execute(
+                "DELETE FROM path_tags WHERE path = ?1",
+                rusqlite::params![removed_path],
+            )
+            .map_err(database_error)?;
+        }
+
+        let mut statement = self
+            .prepare(
+                "INSERT OR REPLACE INTO path_tags (path, tag, path_depth, folded_path)
+                 VALUES (?1, ?2, ?3, fold_path(?1))",
+            )
+            .map_err(database_error)?;
+        for tag in tags {
+            statement
+                .execute(rusqlite::params![path, tag, depth])
+                .map_err(database_error)?;
+        }
+        Ok(())
+    }
+
+    fn sync_baseline_rows(&self, root: &str) -> Result<Vec<(String, String)>, TaggingError> {
+        // LIKE ignores ASCII case here; callers drop paths outside `root`
+        collect_rows(
+            self,
+            "SELECT path, tag FROM xattr_sync_baseline WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'",
+            rusqlite::params![root, descendant_like_pattern(root)],
+        )
+    }
+
+    fn set_sync_baseline(&self, path: &str, tags: &[String]) -> Result<(), TaggingError> {
+        self.execute(
+            "DELETE FROM xattr_sync_baseline WHERE path = ?1",
+            rusqlite::params![path],
+        )
+        .map_err(database_error)?;
+
+        let mut statement = self
+            .prepare("INSERT OR REPLACE INTO xattr_sync_baseline (path, tag) VALUES (?1, ?2)")
+            .map_err(database_error)?;
+        for tag in tags {
+            statement
+                .execute(rusqlite::params![path, tag])
+                .map_err(database_error)?;
+        }
+        Ok(())
+    }
+
+    fn tag_tables(&self) -> Result<TagTables, TaggingError> {
+        let mut statement = self
+            .prepare(
+                "SELECT path, tag, path_depth, CAST(created_at AS TEXT)
+                 FROM path_tags ORDER BY path, tag",
+            )
+            .map_err(database_error)?;
+        let tags = statement
+            .query_map([], |row| {
+                Ok(StoredTag {
+                    path: row.get(0)?,
+                    tag: row.get(1)?,
+                    path_depth: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })
+            .map_err(database_error)?
+            .collect::<Result<_, _>>()
+            .map_err(database_error)?;
+
+        Ok(TagTables {
+            tags,
+            sync_baseline: collect_rows(
+                self,
+                "SELECT path, tag FROM xattr_sync_baseline ORDER BY path, tag",
+                [],
+            )?,
+        })
+    }
+
+    fn replace_tag_tables(&self, tables: &TagTables) -> Result<(), TaggingError> {
+        self.execute_batch("DELETE FROM path_tags; DELETE FROM xattr_sync_baseline;")
+            .map_err(database_error)?;
+
+        let mut statement = self
+            .prepare(
+                "INSERT INTO path_tags (path, tag, path_depth, created_at, folded_path)
+                 VALUES (?1, ?2, ?3, COALESCE(?4, CURRENT_TIMESTAMP), fold_path(?1))",
+            )
+            .map_err(database_error)?;
+        for row in &tables.tags {
+            statement
+                .execute(rusqlite::params![
+                    row.path,
+                    row.tag,
+                    row.path_depth,
+                    row.created_at
+                ])
+                .map_err(database_error)?;
+        }
+
+        let mut statement = self
+            .prepare("INSERT INTO xattr_sync_baseline (path, tag) VALUES (?1, ?2)")
+            .map_err(database_error)?;
+        for (path, tag) in &tables.sync_baseline {
+            statement
+                .execute(rusqlite::params![path, tag])
+                .map_err(database_error)?;
+        }
+        Ok(())
+    }
+}
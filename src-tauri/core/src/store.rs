@@ -0,0 +1,123 @@
+use crate::scan::{self, ScanError, ScanOptions, ScanResult, SortOptions};
+use crate::storage::{Database, StorageBackend};
+use crate::tagging::{
+    assign_tag_to_paths, get_tags_for_directory, get_tags_for_paths, mirror_to_manifests,
+    replace_tags_for_path, DirectoryTagSnapshot, TaggingError,
+};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// The tag database behind a plain connection, for scripts, command-line tools
+// and tests that run without the desktop app
+pub struct TagStore {
+    database: Database,
+}
+
+impl TagStore {
+    // Opens the database at `path`, creating it and any missing tables
+    pub fn open(backend: StorageBackend, path: impl AsRef<Path>) -> Result<Self, TaggingError> {
+        Ok(Self {
+            database: Database::open(backend, path.as_ref())?,
+        })
+    }
+
+    pub fn open_in_memory(backend: StorageBackend) -> Result<Self, TaggingError> {
+        Ok(Self {
+            database: Database::open_in_memory(backend)?,
+        })
+    }
+
+    // For the module functions that are not wrapped here, such as exports
+    pub fn database(&mut self) -> &mut Database {
+        &mut self.database
+    }
+
+    pub fn into_database(self) -> Database {
+        self.database
+    }
+
+    pub fn assign_tag(&mut self, paths: &[PathBuf], tag: &str) -> Result<(), TaggingError> {
+        assign_tag_to_paths(&mut self.database, paths, tag)
+    }
+
+    // Makes `tags` the complete set of direct tags on `path`
+    pub fn replace_tags(&mut self, path: &Path, tags: &[String]) -> Result<(), TaggingError> {
+        replace_tags_for_path(self.database.storage(), path, tags)?;
+        mirror_to_manifests(&mut self.database, &[path.to_path_buf()]);
+        Ok(())
+    }
+
+    // Direct tags of each path that has any
+    pub fn tags_for_paths(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<String>>, TaggingError> {
+        get_tags_for_paths(self.database.storage(), paths)
+    }
+
+    // Direct tags below `root`, down to `max_depth` levels, and the tags
+    // `root` inherits from its ancestors
+    pub fn tags_for_directory(
+        &self,
+        root: &Path,
+        max_depth: usize,
+    ) -> Result<DirectoryTagSnapshot, TaggingError> {
+        get_tags_for_directory(self.database.storage(), root, max_depth)
+    }
+
+    pub fn scan(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        options: &ScanOptions,
+        sort: &SortOptions,
+    ) -> Result<ScanResult, ScanError> {
+        scan::scan_directory(&mut self.database, path, depth, options, sort)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_and_scans_without_an_app() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = temp.path().canonicalize().expect("canonical temp dir");
+        std::fs::create_dir_all(root.join("photos")).expect("photos dir");
+        std::fs::write(root.join("photos/beach.jpg"), "jpg").expect("write");
+
+        for backend in [StorageBackend::DuckDb, StorageBackend::Sqlite] {
+            let mut store = TagStore::open_in_memory(backend).expect("store");
+            store
+                .assign_tag(&[root.join("photos")], "holiday")
+                .expect("assign");
+            store
+                .replace_tags(&root.join("photos/beach.jpg"), &["sea".to_string()])
+                .expect("replace");
+
+            let tags = store
+                .tags_for_paths(&[root.join("photos"), root.join("photos/beach.jpg")])
+                .expect("tags for paths");
+            assert_eq!(tags[&root.join("photos")], vec!["holiday".to_string()]);
+            assert_eq!(
+                tags[&root.join("photos/beach.jpg")],
+                vec!["sea".to_string()]
+            );
+
+            let snapshot = store
+                .tags_for_directory(&root.join("photos"), 1)
+                .expect("tags for directory");
+            assert_eq!(snapshot.direct_tags.len(), 2);
+
+            let result = store
+                .scan(&root, 2, &ScanOptions::default(), &SortOptions::default())
+                .expect("scan");
+            let photos = &result.tree().children()[0];
+            let beach = &photos.children()[0];
+            assert_eq!(beach.info().path(), root.join("photos/beach.jpg"));
+            assert_eq!(beach.info().own_tags(), ["sea".to_string()]);
+            assert_eq!(beach.info().inherited_tags(), ["holiday".to_string()]);
+        }
+    }
+}
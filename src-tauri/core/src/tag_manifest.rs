@@ -0,0 +1,511 @@
+use crate::atomic_file::write_atomically;
+use crate::path_codec::{decode_path, encode_os_str, encode_path, serialize_path};
+use crate::scan::{FileInfo, IgnoreRules, SkippedEntry};
+use crate::tag_transfer::{store_rows, ImportStrategy, ImportSummary, ImportedRow, TransferError};
+use crate::tagging::{get_tags_for_directory, normalize_path, TaggingError};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+pub(crate) const MANIFEST_FILE_NAME: &str = ".tags";
+
+const MANIFEST_VERSION: u32 = 1;
+
+// Contents of a `.tags` file: the direct tags of a directory's entries, keyed
+// by entry name so the folder can move between machines. Sorted maps keep
+// diffs small when the folder is under version control.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Manifest {
+    version: u32,
+    entries: BTreeMap<String, Vec<String>>,
+}
+
+impl Manifest {
+    fn from_tags(directory: &Path, direct_tags: BTreeMap<PathBuf, Vec<String>>) -> Self {
+        let entries = direct_tags
+            .into_iter()
+            .filter(|(path, tags)| path.parent() == Some(directory) && !tags.is_empty())
+            .filter_map(|(path, mut tags)| {
+                tags.sort();
+                tags.dedup();
+                Some((encode_os_str(path.file_name()?), tags))
+            })
+            .collect();
+        Self {
+            version: MANIFEST_VERSION,
+            entries,
+        }
+    }
+
+    fn tags_for(&self, path: &Path) -> Vec<String> {
+        path.file_name()
+            .and_then(|name| self.entries.get(&encode_os_str(name)))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Entries naming something directly inside the directory; anything else,
+    // such as `../elsewhere`, is ignored
+    fn entries_within<'a>(
+        &'a self,
+        directory: &'a Path,
+    ) -> impl Iterator<Item = (PathBuf, &'a Vec<String>)> + 'a {
+        self.entries.iter().filter_map(move |(name, tags)| {
+            let name = decode_path(name);
+            let mut components = name.components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) => Some((directory.join(&name), tags)),
+                _ => {
+                    warn!("Ignoring manifest entry {:?} in {:?}", name, directory);
+                    None
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestReport {
+    // Manifests created or rewritten
+    #[serde(serialize_with = "serialize_paths")]
+    written: Vec<PathBuf>,
+    // Manifests deleted because nothing in their directory is tagged anymore
+    #[serde(serialize_with = "serialize_paths")]
+    removed: Vec<PathBuf>,
+    skipped: Vec<SkippedEntry>,
+}
+
+fn serialize_paths<S: serde::Serializer>(
+    paths: &[PathBuf],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| encode_path(path)))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestRoot {
+    #[serde(serialize_with = "serialize_path")]
+    root: PathBuf,
+}
+
+pub(crate) fn read_manifest(directory: &Path) -> io::Result<Option<Manifest>> {
+    let raw = match fs::read_to_string(directory.join(MANIFEST_FILE_NAME)) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    serde_json::from_str(&raw)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn read_manifest_lenient(directory: &Path) -> Manifest {
+    read_manifest(directory)
+        .unwrap_or_else(|err| {
+            debug!("Could not read tag manifest in {:?}: {}", directory, err);
+            None
+        })
+        .unwrap_or_default()
+}
+
+// Sets `manifest_tags` on the entries of one directory listing
+pub(crate) fn apply_manifest(directory: &Path, entries: &mut [FileInfo]) {
+    let manifest = read_manifest_lenient(directory);
+    if manifest.entries.is_empty() {
+        return;
+    }
+    for entry in entries {
+        entry.manifest_tags = manifest.tags_for(&entry.path);
+    }
+}
+
+// Tags listed for a single entry in its parent directory's manifest
+pub(crate) fn read_tags_lenient(path: &Path) -> Vec<String> {
+    path.parent()
+        .map(|directory| read_manifest_lenient(directory).tags_for(path))
+        .unwrap_or_default()
+}
+
+// Writes the manifest, or deletes it once it lists nothing. Returns whether
+// the file changed.
+fn write_manifest(directory: &Path, manifest: &Manifest) -> io::Result<bool> {
+    let file = directory.join(MANIFEST_FILE_NAME);
+    if manifest.entries.is_empty() {
+        return match fs::remove_file(&file) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        };
+    }
+
+    let mut contents = serde_json::to_string_pretty(manifest).map_err(io::Error::other)?;
+    contents.push('\n');
+    if fs::read_to_string(&file).is_ok_and(|current| current == contents) {
+        return Ok(false);
+    }
+    write_atomically(&file, contents)?;
+    Ok(true)
+}
+
+fn invalid_root(err: TaggingError) -> TransferError {
+    TransferError::InvalidSource(err.to_string())
+}
+
+// Enables or disables mirroring tags into `.tags` manifests below `root`
+pub fn set_tag_manifests(
+    connection: &duckdb::Connection,
+    root: &Path,
+    enabled: bool,
+) -> Result<(), TransferError> {
+    let root = encode_path(&normalize_path(root).map_err(invalid_root)?);
+    let result = if enabled {
+        connection.execute(
+            "INSERT OR IGNORE INTO tag_manifest_roots (root) VALUES (?1)",
+            duckdb::params![root],
+        )
+    } else {
+        connection.execute(
+            "DELETE FROM tag_manifest_roots WHERE root = ?1",
+            duckdb::params![root],
+        )
+    };
+    result
+        .map(|_| ())
+        .map_err(|err| TransferError::Database(err.to_string()))
+}
+
+pub fn list_tag_manifest_roots(
+    connection: &duckdb::Connection,
+) -> Result<Vec<ManifestRoot>, TransferError> {
+    Ok(manifest_roots(connection)?
+        .into_iter()
+        .map(|root| ManifestRoot { root })
+        .collect())
+}
+
+// Writes a manifest for every directory below `root` whose entries carry
+// tags, and removes manifests that no longer list anything
+pub fn export_tag_manifests(
+    connection: &duckdb::Connection,
+    root: &Path,
+) -> Result<ManifestReport, TransferError> {
+    let root = normalize_path(root).map_err(invalid_root)?;
+    let mut skipped = Vec::new();
+    let existing = find_manifests(&root, &mut skipped);
+
+    let snapshot = get_tags_for_directory(connection, &root, usize::MAX)
+        .map_err(|err| TransferError::Database(err.to_string()))?;
+    let mut directories: BTreeMap<PathBuf, BTreeMap<PathBuf, Vec<String>>> = BTreeMap::new();
+    for (path, tags) in snapshot.direct_tags {
+        // The root's own tags belong in its parent's manifest, outside the export
+        match path.parent() {
+            Some(parent) if path != root => directories
+                .entry(parent.to_path_buf())
+                .or_default()
+                .insert(path, tags),
+            _ => continue,
+        };
+    }
+    for directory in existing {
+        directories.entry(directory).or_default();
+    }
+
+    let mut written = Vec::new();
+    let mut removed = Vec::new();
+    for (directory, direct_tags) in directories {
+        let manifest = Manifest::from_tags(&directory, direct_tags);
+        let file = directory.join(MANIFEST_FILE_NAME);
+        match write_manifest(&directory, &manifest) {
+            Ok(false) => {}
+            Ok(true) if manifest.entries.is_empty() => removed.push(file),
+            Ok(true) => written.push(file),
+            Err(err) => skipped.push(SkippedEntry::from_io(&file, &err)),
+        }
+    }
+
+    info!(
+        "Exported tag manifests under {:?}: {} written, {} removed",
+        root,
+        written.len(),
+        removed.len()
+    );
+    Ok(ManifestReport {
+        written,
+        removed,
+        skipped,
+    })
+}
+
+// Adds the tags listed in every manifest below `root` to the database
+pub fn import_tag_manifests(
+    connection: &mut duckdb::Connection,
+    root: &Path,
+    strategy: ImportStrategy,
+) -> Result<ImportSummary, TransferError> {
+    let root = normalize_path(root).map_err(invalid_root)?;
+    let mut skipped = Vec::new();
+    let mut rows = Vec::new();
+    for directory in find_manifests(&root, &mut skipped) {
+        let manifest = match read_manifest(&directory) {
+            Ok(manifest) => manifest.unwrap_or_default(),
+            Err(err) => {
+                return Err(TransferError::InvalidSource(format!(
+                    "{}: {err}",
+                    encode_path(&directory.join(MANIFEST_FILE_NAME))
+                )));
+            }
+        };
+        for (path, tags) in manifest.entries_within(&directory) {
+            rows.extend(tags.iter().map(|tag| ImportedRow::new(&path, tag.clone())));
+        }
+    }
+    for entry in &skipped {
+        warn!(
+            "Could not look for tag manifests in {:?}: {}",
+            entry.path, entry.message
+        );
+    }
+
+    store_rows(connection, rows, strategy, None)
+}
+
+// Directories at or below `root` that hold a manifest. Symlinks are not
+// followed, and directories `.gitignore` or `.tagignore` hide from scans are
+// not entered.
+fn find_manifests(root: &Path, skipped: &mut Vec<SkippedEntry>) -> Vec<PathBuf> {
+    let mut rules = IgnoreRules::new();
+    let mut directories = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(directory) = stack.pop() {
+        rules.load_directory(&directory);
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(err) => {
+                skipped.push(SkippedEntry::from_io(&directory, &err));
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            if entry.file_name() == MANIFEST_FILE_NAME {
+                directories.push(directory.clone());
+            } else if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                let path = entry.path();
+                if !rules.is_ignored(&path, true) {
+                    stack.push(path);
+                }
+            }
+        }
+    }
+    directories.sort();
+    directories
+}
+
+fn manifest_roots(connection: &duckdb::Connection) -> Result<Vec<PathBuf>, TransferError> {
+    let mut statement = connection
+        .prepare("SELECT root FROM tag_manifest_roots ORDER BY root")
+        .map_err(|err| TransferError::Database(err.to_string()))?;
+    let rows = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|err| TransferError::Database(err.to_string()))?;
+    rows.map(|row| {
+        row.map(|root| decode_path(&root))
+            .map_err(|err| TransferError::Database(err.to_string()))
+    })
+    .collect()
+}
+
+// Rewrites the manifests of the directories holding `paths`, where mirroring
+// is enabled. Failures are logged; the database stays the source of truth.
+pub(crate) fn mirror_directories(connection: &duckdb::Connection, paths: &[PathBuf]) {
+    let roots = match manifest_roots(connection) {
+        Ok(roots) if roots.is_empty() => return,
+        Ok(roots) => roots,
+        Err(err) => {
+            warn!("Could not load tag manifest roots: {}", err);
+            return;
+        }
+    };
+
+    let directories: BTreeSet<&Path> = paths
+        .iter()
+        .filter_map(|path| path.parent())
+        .filter(|directory| roots.iter().any(|root| directory.starts_with(root)))
+        .collect();
+    for directory in directories {
+        let result = get_tags_for_directory(connection, directory, 1)
+            .map_err(|err| io::Error::other(err.to_string()))
+            .and_then(|snapshot| {
+                write_manifest(
+                    directory,
+                    &Manifest::from_tags(directory, snapshot.direct_tags),
+                )
+            });
+        if let Err(err) = result {
+            warn!("Could not update tag manifest in {:?}: {}", directory, err);
+        }
+    }
+}
+
+pub(crate) fn ensure_schema(connection: &duckdb::Connection) -> Result<(), TransferError> {
+    connection
+        .execute(
+            "CREATE TABLE IF NOT EXISTS tag_manifest_roots (root TEXT PRIMARY KEY)",
+            [],
+        )
+        .map_err(|err| TransferError::Database(err.to_string()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_connection() -> duckdb::Connection {
+        let connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        crate::tagging::ensure_schema(&connection).expect("tag schema");
+        ensure_schema(&connection).expect("manifest schema");
+        connection
+    }
+
+    fn tag(connection: &duckdb::Connection, path: &Path, tag: &str) {
+        connection
+            .execute(
+                "INSERT INTO path_tags (path, tag, path_depth) VALUES (?1, ?2, ?3)",
+                duckdb::params![
+                    encode_path(path),
+                    tag,
+                    crate::tagging::calculate_path_depth(path)
+                ],
+            )
+            .expect("insert");
+    }
+
+    #[test]
+    fn exports_and_imports_relative_manifests() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path().canonicalize().expect("canonical temp dir");
+        fs::create_dir_all(root.join("album")).expect("album");
+        fs::write(root.join("album/a.jpg"), "a").expect("write");
+        fs::write(root.join("notes.txt"), "n").expect("write");
+        fs::create_dir_all(root.join("stale")).expect("stale");
+        // Ignored directories are left alone, as scans leave them out
+        fs::write(root.join(".tagignore"), "vendor/\n").expect("write");
+        fs::create_dir_all(root.join("vendor")).expect("vendor");
+        fs::write(
+            root.join("vendor").join(MANIFEST_FILE_NAME),
+            "{\"version\":1,\"entries\":{\"lib\":[\"third-party\"]}}",
+        )
+        .expect("write");
+        fs::write(
+            root.join("stale").join(MANIFEST_FILE_NAME),
+            "{\"version\":1,\"entries\":{\"x\":[\"old\"]}}",
+        )
+        .expect("write");
+
+        let connection = &mut test_connection();
+        tag(connection, &root, "project");
+        tag(connection, &root.join("album"), "photos");
+        tag(connection, &root.join("album/a.jpg"), "sea");
+        tag(connection, &root.join("album/a.jpg"), "beach");
+        tag(connection, &root.join("notes.txt"), "todo");
+
+        let report = export_tag_manifests(connection, &root).expect("export");
+        assert_eq!(
+            report.written,
+            vec![
+                root.join(MANIFEST_FILE_NAME),
+                root.join("album").join(MANIFEST_FILE_NAME)
+            ]
+        );
+        assert_eq!(
+            report.removed,
+            vec![root.join("stale").join(MANIFEST_FILE_NAME)]
+        );
+        assert!(root.join("vendor").join(MANIFEST_FILE_NAME).exists());
+
+        let manifest = read_manifest(&root.join("album")).unwrap().unwrap();
+        assert_eq!(
+            manifest.entries,
+            BTreeMap::from([(
+                "a.jpg".to_string(),
+                vec!["beach".to_string(), "sea".to_string()]
+            )])
+        );
+        assert_eq!(
+            read_tags_lenient(&root.join("notes.txt")),
+            vec!["todo".to_string()]
+        );
+        // Unchanged manifests are not rewritten
+        assert!(export_tag_manifests(connection, &root)
+            .expect("export")
+            .written
+            .is_empty());
+
+        // Importing into a fresh database restores every tag but the root's
+        let connection = &mut test_connection();
+        import_tag_manifests(connection, &root, ImportStrategy::Skip).expect("import");
+        let mut statement = connection
+            .prepare("SELECT count(*) FROM path_tags")
+            .expect("prepare");
+        let count: i64 = statement.query_row([], |row| row.get(0)).expect("count");
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn mirrors_only_below_enabled_roots() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path().canonicalize().expect("canonical temp dir");
+        for name in ["shared", "private"] {
+            fs::create_dir_all(root.join(name)).expect("dir");
+            fs::write(root.join(name).join("file.txt"), "data").expect("write");
+        }
+
+        let connection = test_connection();
+        connection
+            .execute(
+                "INSERT INTO tag_manifest_roots (root) VALUES (?1)",
+                duckdb::params![encode_path(&root.join("shared"))],
+            )
+            .expect("enable");
+        let paths = [root.join("shared/file.txt"), root.join("private/file.txt")];
+        for path in &paths {
+            tag(&connection, path, "red");
+        }
+
+        mirror_directories(&connection, &paths);
+        assert_eq!(read_tags_lenient(&paths[0]), vec!["red".to_string()]);
+        assert!(!root.join("private").join(MANIFEST_FILE_NAME).exists());
+
+        // Once nothing is tagged the manifest goes away
+        connection
+            .execute("DELETE FROM path_tags", [])
+            .expect("delete");
+        mirror_directories(&connection, &paths);
+        assert!(!root.join("shared").join(MANIFEST_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn mirrors_tags_replaced_outside_assignments() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path().canonicalize().expect("canonical temp dir");
+        let file = root.join("file.txt");
+        fs::write(&file, "data").expect("write");
+
+        let mut store =
+            crate::TagStore::open_in_memory(crate::StorageBackend::DuckDb).expect("store");
+        set_tag_manifests(store.database().duckdb().expect("duckdb"), &root, true).expect("enable");
+
+        store
+            .replace_tags(&file, &["red".to_string()])
+            .expect("replace");
+        assert_eq!(read_tags_lenient(&file), vec!["red".to_string()]);
+
+        store.replace_tags(&file, &[]).expect("clear");
+        assert!(!root.join(MANIFEST_FILE_NAME).exists());
+    }
+}
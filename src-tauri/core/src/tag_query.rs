@@ -0,0 +1,218 @@
+use std::collections::BTreeSet;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+// Boolean expression over tag names, e.g. `finance AND status=open AND NOT
+// archived`. Keywords are case-insensitive, adjacent terms are ANDed, and a
+// tag that looks like a keyword or holds spaces or parentheses can be quoted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TagQuery {
+    Tag(String),
+    Not(Box<TagQuery>),
+    And(Vec<TagQuery>),
+    Or(Vec<TagQuery>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Tag(String),
+}
+
+impl TagQuery {
+    pub(crate) fn parse(input: &str) -> Result<Self, String> {
+        let mut tokens = tokenize(input)?.into_iter().peekable();
+        if tokens.peek().is_none() {
+            return Err("Query must not be empty".into());
+        }
+
+        let query = parse_or(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(query),
+            Some(Token::Close) => Err("Unmatched ')'".into()),
+            Some(token) => Err(format!("Unexpected {}", describe(&token))),
+        }
+    }
+
+    pub(crate) fn matches(&self, tags: &BTreeSet<String>) -> bool {
+        match self {
+            Self::Tag(tag) => tags.contains(tag),
+            Self::Not(inner) => !inner.matches(tags),
+            Self::And(terms) => terms.iter().all(|term| term.matches(tags)),
+            Self::Or(terms) => terms.iter().any(|term| term.matches(tags)),
+        }
+    }
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<TagQuery, String> {
+    let mut terms = vec![parse_and(tokens)?];
+    while tokens.next_if_eq(&Token::Or).is_some() {
+        terms.push(parse_and(tokens)?);
+    }
+    Ok(flatten(terms, TagQuery::Or))
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<TagQuery, String> {
+    let mut terms = vec![parse_not(tokens)?];
+    loop {
+        // An explicit AND, or a term directly following the previous one
+        if tokens.next_if_eq(&Token::And).is_some()
+            || matches!(
+                tokens.peek(),
+                Some(Token::Tag(_) | Token::Not | Token::Open)
+            )
+        {
+            terms.push(parse_not(tokens)?);
+        } else {
+            return Ok(flatten(terms, TagQuery::And));
+        }
+    }
+}
+
+fn parse_not(tokens: &mut Tokens) -> Result<TagQuery, String> {
+    if tokens.next_if_eq(&Token::Not).is_some() {
+        return Ok(TagQuery::Not(Box::new(parse_not(tokens)?)));
+    }
+
+    match tokens.next() {
+        Some(Token::Tag(tag)) => Ok(TagQuery::Tag(tag)),
+        Some(Token::Open) => {
+            let inner = parse_or(tokens)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(inner),
+                _ => Err("Missing ')'".into()),
+            }
+        }
+        Some(token) => Err(format!("Expected a tag, found {}", describe(&token))),
+        None => Err("Expected a tag at the end of the query".into()),
+    }
+}
+
+fn flatten(mut terms: Vec<TagQuery>, combine: fn(Vec<TagQuery>) -> TagQuery) -> TagQuery {
+    if terms.len() == 1 {
+        terms.remove(0)
+    } else {
+        combine(terms)
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            _ if ch.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut tag = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => tag.extend(chars.next()),
+                        Some(ch) => tag.push(ch),
+                        None => return Err("Unclosed quote".into()),
+                    }
+                }
+                let tag = tag.trim();
+                if tag.is_empty() {
+                    return Err("Quoted tag must not be empty".into());
+                }
+                tokens.push(Token::Tag(tag.to_string()));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(ch) =
+                    chars.next_if(|ch| !ch.is_whitespace() && !matches!(ch, '(' | ')' | '"'))
+                {
+                    word.push(ch);
+                }
+                tokens.push(match word.to_ascii_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Tag(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Open => "'('".into(),
+        Token::Close => "')'".into(),
+        Token::And => "AND".into(),
+        Token::Or => "OR".into(),
+        Token::Not => "NOT".into(),
+        Token::Tag(tag) => format!("tag {tag:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_precedence_implicit_and_and_quoting() {
+        let query = TagQuery::parse("finance AND status=open AND NOT archived").unwrap();
+        assert!(query.matches(&tags(&["finance", "status=open"])));
+        assert!(!query.matches(&tags(&["finance", "status=open", "archived"])));
+        assert!(!query.matches(&tags(&["finance"])));
+
+        // NOT binds tighter than AND, which binds tighter than OR
+        let query = TagQuery::parse("a b or not c").unwrap();
+        assert_eq!(
+            query,
+            TagQuery::Or(vec![
+                TagQuery::And(vec![TagQuery::Tag("a".into()), TagQuery::Tag("b".into())]),
+                TagQuery::Not(Box::new(TagQuery::Tag("c".into()))),
+            ])
+        );
+
+        let query = TagQuery::parse("(\"to do\" OR \"and\") -draft").unwrap();
+        assert!(query.matches(&tags(&["and", "-draft"])));
+        assert!(!query.matches(&tags(&["to do"])));
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        for input in [
+            "",
+            "  ",
+            "a AND",
+            "(a OR b",
+            "a)",
+            "NOT",
+            "\"open",
+            "a OR OR b",
+        ] {
+            assert!(
+                TagQuery::parse(input).is_err(),
+                "{input:?} should not parse"
+            );
+        }
+    }
+}
@@ -0,0 +1,780 @@
+use crate::path_codec::{decode_path, encode_path, serialize_path};
+use crate::path_matching::PathMatchingRules;
+use crate::tag_manifest;
+use crate::tagging::{calculate_path_depth, descendant_like_pattern, normalize_path};
+use crate::AccessError;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Invalid rows listed individually in an import summary; the rest are only counted
+const MAX_REPORTED_INVALID_ROWS: usize = 100;
+
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "type", content = "message")]
+pub enum TransferError {
+    #[error("Invalid destination {0}")]
+    InvalidDestination(String),
+
+    #[error("Invalid source {0}")]
+    InvalidSource(String),
+
+    #[error("Conflicting row: {0}")]
+    Conflict(String),
+
+    #[error(transparent)]
+    Access(#[from] AccessError),
+
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+// File formats for moving `path_tags` rows in and out of the database
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagFileFormat {
+    #[default]
+    Csv,
+    // One JSON object per line
+    JsonLines,
+    Parquet,
+}
+
+impl TagFileFormat {
+    fn copy_options(self) -> &'static str {
+        match self {
+            Self::Csv => "FORMAT CSV, HEADER",
+            Self::JsonLines => "FORMAT JSON",
+            Self::Parquet => "FORMAT PARQUET",
+        }
+    }
+
+    // Table function reading `file`, given as a SQL literal. CSV columns are
+    // read as text so that tags such as `007` keep their spelling.
+    fn reader(self, file: &str) -> String {
+        match self {
+            Self::Csv => format!("read_csv({file}, header = true, all_varchar = true)"),
+            Self::JsonLines => format!("read_json({file}, format = 'newline_delimited')"),
+            Self::Parquet => format!("read_parquet({file})"),
+        }
+    }
+}
+
+// Narrows an export; both filters apply when both are set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportFilter {
+    // Only tags on this path and below it
+    pub root: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportSummary {
+    #[serde(serialize_with = "serialize_path")]
+    destination: PathBuf,
+    format: TagFileFormat,
+    rows: usize,
+}
+
+// Writes `path_tags` rows, with their depth and creation time, to a file.
+// Paths are written in their stored form, so they survive a round trip.
+pub fn export_tags(
+    connection: &duckdb::Connection,
+    destination: &Path,
+    format: TagFileFormat,
+    filter: &ExportFilter,
+) -> Result<ExportSummary, TransferError> {
+    let invalid = |reason: &str| {
+        TransferError::InvalidDestination(format!("{}: {reason}", encode_path(destination)))
+    };
+    if !destination.is_absolute() {
+        return Err(invalid("must be an absolute path"));
+    }
+    if destination.is_dir() {
+        return Err(invalid("is a directory"));
+    }
+    if !destination.parent().is_some_and(Path::is_dir) {
+        return Err(invalid("parent directory does not exist"));
+    }
+    // COPY takes a file name as SQL text, which cannot carry arbitrary bytes
+    let target = destination
+        .to_str()
+        .ok_or_else(|| invalid("is not valid Unicode"))?;
+
+    // COPY does not accept bound parameters, so values are inlined as literals
+    let mut conditions = Vec::new();
+    if let Some(root) = filter.root.as_deref() {
+        let root = normalize_path(&decode_path(root))
+            .map_err(|err| TransferError::InvalidSource(err.to_string()))?;
+        let root = encode_path(&root);
+        conditions.push(format!(
+            "(path = {} OR path LIKE {} ESCAPE '\\')",
+            sql_literal(&root),
+            sql_literal(&descendant_like_pattern(&root))
+        ));
+    }
+    if let Some(tag) = filter.tag.as_deref().map(str::trim) {
+        conditions.push(format!("tag = {}", sql_literal(tag)));
+    }
+    let condition = if conditions.is_empty() {
+        "TRUE".to_string()
+    } else {
+        conditions.join(" AND ")
+    };
+
+    let rows = connection
+        .execute(
+            &format!(
+                "
+                COPY (
+                    SELECT path, tag, path_depth, created_at
+                    FROM path_tags
+                    WHERE {condition}
+                    ORDER BY path, tag
+                ) TO {} ({})
+                ",
+                sql_literal(target),
+                format.copy_options()
+            ),
+            [],
+        )
+        .map_err(|err| TransferError::Database(err.to_string()))?;
+
+    Ok(ExportSummary {
+        destination: destination.to_path_buf(),
+        format,
+        rows,
+    })
+}
+
+// What to do with an imported row whose path already carries its tag
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+    #[default]
+    Skip,
+    // Replaces the stored row, taking the imported creation time
+    Overwrite,
+    // Aborts the import without writing anything
+    Fail,
+}
+
+// Moves paths captured under `from`, e.g. on another machine, to `to`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefixRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InvalidRow {
+    // 1-based position among the data rows
+    row: usize,
+    reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    inserted: usize,
+    // Existing rows replaced by the `overwrite` strategy
+    overwritten: usize,
+    skipped: usize,
+    invalid: usize,
+    // The first `MAX_REPORTED_INVALID_ROWS` rows counted in `invalid`
+    invalid_rows: Vec<InvalidRow>,
+}
+
+// Reads `path`/`tag` rows, plus `created_at` when present, from a file and
+// adds them to the database in one transaction
+pub fn import_tags(
+    connection: &mut duckdb::Connection,
+    source: &Path,
+    format: TagFileFormat,
+    strategy: ImportStrategy,
+    rewrite: Option<&PrefixRewrite>,
+) -> Result<ImportSummary, TransferError> {
+    let rows = read_rows(connection, source, format)?;
+    let summary = store_rows(connection, rows, strategy, rewrite)?;
+    info!(
+        "Imported tags from {:?}: {} inserted, {} overwritten, {} skipped, {} invalid",
+        source, summary.inserted, summary.overwritten, summary.skipped, summary.invalid
+    );
+    Ok(summary)
+}
+
+// Adds validated rows to `path_tags` in one transaction, which `strategy`
+// may abort. The rows go through a temporary table, so conflicts with stored
+// rows are found by one join instead of a lookup per row.
+pub(crate) fn store_rows(
+    connection: &mut duckdb::Connection,
+    rows: Vec<ImportedRow>,
+    strategy: ImportStrategy,
+    rewrite: Option<&PrefixRewrite>,
+) -> Result<ImportSummary, TransferError> {
+    let database_error = |err: duckdb::Error| TransferError::Database(err.to_string());
+    let transaction = connection.transaction().map_err(database_error)?;
+    let rules = PathMatchingRules::load(&transaction)
+        .map_err(|err| TransferError::Database(err.to_string()))?;
+
+    let mut summary = ImportSummary::default();
+    transaction
+        .execute_batch(
+            "
+            CREATE OR REPLACE TEMP TABLE imported_tags (
+                position INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                path_depth INTEGER NOT NULL,
+                created_at TEXT
+            )
+            ",
+        )
+        .map_err(database_error)?;
+    {
+        let mut appender = transaction
+            .appender_to_catalog_and_db("imported_tags", "temp", "main")
+            .map_err(database_error)?;
+        for (index, row) in rows.into_iter().enumerate() {
+            match row.resolve(rewrite) {
+                Ok((path, tag, created_at)) => appender
+                    .append_row(duckdb::params![
+                        index as i64,
+                        encode_path(&path),
+                        tag,
+                        calculate_path_depth(&path),
+                        created_at
+                    ])
+                    .map_err(database_error)?,
+                Err(reason) => summary.reject(index + 1, reason),
+            }
+        }
+    }
+
+    // Reuse the spelling an equivalent path was already tagged under
+    if !rules.is_empty() {
+        for (path, spelling) in stored_spellings_of_imports(&transaction, &rules)? {
+            transaction
+                .execute(
+                    "UPDATE imported_tags SET path = ?2 WHERE path = ?1",
+                    duckdb::params![path, spelling],
+                )
+                .map_err(database_error)?;
+        }
+    }
+
+    // A row repeated within the file is not a conflict with the database
+    summary.skipped += transaction
+        .execute(
+            "
+            DELETE FROM imported_tags
+            WHERE position NOT IN (SELECT min(position) FROM imported_tags GROUP BY path, tag)
+            ",
+            [],
+        )
+        .map_err(database_error)?;
+
+    let conflicts: Vec<(String, String)> = {
+        let mut statement = transaction
+            .prepare(
+                "
+                SELECT i.path, i.tag
+                FROM imported_tags i JOIN path_tags t ON t.path = i.path AND t.tag = i.tag
+                ORDER BY i.position
+                ",
+            )
+            .map_err(database_error)?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(database_error)?;
+        rows.collect::<Result<_, _>>().map_err(database_error)?
+    };
+    if let (Some((path, tag)), ImportStrategy::Fail) = (conflicts.first(), strategy) {
+        return Err(TransferError::Conflict(format!(
+            "{path} is already tagged {tag:?}"
+        )));
+    }
+
+    let (insert, only_new) = match strategy {
+        ImportStrategy::Overwrite => ("INSERT OR REPLACE", ""),
+        _ => (
+            "INSERT",
+            "WHERE NOT EXISTS (SELECT 1 FROM path_tags t WHERE t.path = i.path AND t.tag = i.tag)",
+        ),
+    };
+    let stored = transaction
+        .execute(
+            &format!(
+                "
+                {insert} INTO path_tags (path, tag, path_depth, created_at, folded_path)
+                SELECT i.path, i.tag, i.path_depth,
+                       COALESCE(CAST(i.created_at AS TIMESTAMP), CAST(now() AS TIMESTAMP)),
+                       nfc_normalize(lower(i.path))
+                FROM imported_tags i
+                {only_new}
+                "
+            ),
+            [],
+        )
+        .map_err(database_error)?;
+    if strategy == ImportStrategy::Overwrite {
+        summary.overwritten += conflicts.len();
+        summary.inserted += stored - conflicts.len();
+    } else {
+        summary.skipped += conflicts.len();
+        summary.inserted += stored;
+    }
+
+    let written: Vec<PathBuf> = {
+        let mut statement = transaction
+            .prepare("SELECT DISTINCT path FROM imported_tags ORDER BY path")
+            .map_err(database_error)?;
+        let rows = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(database_error)?;
+        rows.map(|path| path.map(|path| decode_path(&path)))
+            .collect::<Result<_, _>>()
+            .map_err(database_error)?
+    };
+    transaction
+        .execute("DROP TABLE imported_tags", [])
+        .map_err(database_error)?;
+    transaction.commit().map_err(database_error)?;
+
+    tag_manifest::mirror_directories(connection, &written);
+    Ok(summary)
+}
+
+// Imported paths that an equivalent, already tagged spelling should replace
+fn stored_spellings_of_imports(
+    connection: &duckdb::Connection,
+    rules: &PathMatchingRules,
+) -> Result<Vec<(String, String)>, TransferError> {
+    let database_error = |err: duckdb::Error| TransferError::Database(err.to_string());
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT DISTINCT i.path, t.path
+            FROM imported_tags i JOIN path_tags t ON t.folded_path = nfc_normalize(lower(i.path))
+            ORDER BY i.path, t.path
+            ",
+        )
+        .map_err(database_error)?;
+    let candidates = statement
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(database_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(database_error)?;
+
+    // The first matching spelling wins, as in `stored_spellings`
+    let mut resolved: Vec<(String, String)> = Vec::new();
+    for (path, spelling) in candidates {
+        if resolved.last().is_some_and(|(last, _)| *last == path) {
+            continue;
+        }
+        let imported = decode_path(&path);
+        if rules
+            .for_path(&imported)
+            .same_path(&decode_path(&spelling), &imported)
+        {
+            resolved.push((path, spelling));
+        }
+    }
+    resolved.retain(|(path, spelling)| path != spelling);
+    Ok(resolved)
+}
+
+impl ImportSummary {
+    fn reject(&mut self, row: usize, reason: String) {
+        self.invalid += 1;
+        if self.invalid_rows.len() < MAX_REPORTED_INVALID_ROWS {
+            self.invalid_rows.push(InvalidRow { row, reason });
+        }
+    }
+}
+
+// One data row as read from a source, before any validation
+pub(crate) struct ImportedRow {
+    path: Option<String>,
+    tag: Option<String>,
+    created_at: Option<String>,
+    created_at_valid: bool,
+    // Set when the source could not even read the row
+    unreadable: Option<String>,
+}
+
+impl ImportedRow {
+    // A row from a source without creation times
+    pub(crate) fn new(path: &Path, tag: String) -> Self {
+        Self {
+            path: Some(encode_path(path)),
+            tag: Some(tag),
+            created_at: None,
+            created_at_valid: true,
+            unreadable: None,
+        }
+    }
+
+    // A row the source could not read, reported with the invalid rows
+    pub(crate) fn unreadable(reason: String) -> Self {
+        Self {
+            path: None,
+            tag: None,
+            created_at: None,
+            created_at_valid: true,
+            unreadable: Some(reason),
+        }
+    }
+
+    // The normalized path, trimmed tag and creation time to store, or why
+    // the row cannot be imported
+    fn resolve(
+        self,
+        rewrite: Option<&PrefixRewrite>,
+    ) -> Result<(PathBuf, String, Option<String>), String> {
+        if let Some(reason) = self.unreadable {
+            return Err(reason);
+        }
+        let path = self
+            .path
+            .filter(|path| !path.is_empty())
+            .ok_or("missing path")?;
+        let tag = self
+            .tag
+            .as_deref()
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .ok_or("missing tag")?
+            .to_string();
+        if !self.created_at_valid {
+            return Err(format!(
+                "invalid created_at {:?}",
+                self.created_at.unwrap_or_default()
+            ));
+        }
+
+        let mut path = decode_path(&path);
+        if let Some(rewrite) = rewrite {
+            if let Ok(rest) = path.strip_prefix(decode_path(&rewrite.from)) {
+                path = decode_path(&rewrite.to).join(rest);
+            }
+        }
+        if !path.is_absolute() {
+            return Err(format!("{} is not an absolute path", encode_path(&path)));
+        }
+
+        let path = normalize_path(&path).map_err(|err| err.to_string())?;
+        Ok((path, tag, self.created_at))
+    }
+}
+
+fn read_rows(
+    connection: &duckdb::Connection,
+    source: &Path,
+    format: TagFileFormat,
+) -> Result<Vec<ImportedRow>, TransferError> {
+    let invalid =
+        |reason: &str| TransferError::InvalidSource(format!("{}: {reason}", encode_path(source)));
+    if !source.is_file() {
+        return Err(invalid("is not a file"));
+    }
+    // Table functions take the file name as SQL text, like COPY
+    let relation = format.reader(&sql_literal(
+        source
+            .to_str()
+            .ok_or_else(|| invalid("is not valid Unicode"))?,
+    ));
+
+    let columns: Vec<String> = {
+        let mut statement = connection
+            .prepare(&format!("DESCRIBE SELECT * FROM {relation}"))
+            .map_err(|err| invalid(&err.to_string()))?;
+        let rows = statement
+            .query_map([], |row| row.get(0))
+            .map_err(|err| invalid(&err.to_string()))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|err| invalid(&err.to_string()))?
+    };
+    for required in ["path", "tag"] {
+        if !columns.iter().any(|column| column == required) {
+            return Err(invalid(&format!("missing column {required:?}")));
+        }
+    }
+    let created_at = if columns.iter().any(|column| column == "created_at") {
+        "created_at"
+    } else {
+        "NULL"
+    };
+
+    let mut statement = connection
+        .prepare(&format!(
+            "
+            SELECT
+                CAST(path AS VARCHAR),
+                CAST(tag AS VARCHAR),
+                CAST({created_at} AS VARCHAR),
+                {created_at} IS NULL OR TRY_CAST({created_at} AS TIMESTAMP) IS NOT NULL
+            FROM {relation}
+            "
+        ))
+        .map_err(|err| invalid(&err.to_string()))?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(ImportedRow {
+                path: row.get(0)?,
+                tag: row.get(1)?,
+                created_at: row.get(2)?,
+                created_at_valid: row.get(3)?,
+                unreadable: None,
+            })
+        })
+        .map_err(|err| invalid(&err.to_string()))?;
+    rows.collect::<Result<_, _>>()
+        .map_err(|err| invalid(&err.to_string()))
+}
+
+fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_filtered_rows_in_every_format() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        crate::tagging::ensure_schema(&connection).expect("tag schema");
+        for (path, tag, depth) in [
+            ("/data/o'brien", "red", 2),
+            ("/data/o'brien/a.txt", "blue", 3),
+            ("/data/other", "red", 2),
+            ("/database", "red", 1),
+        ] {
+            connection
+                .execute(
+                    "INSERT INTO path_tags (path, tag, path_depth) VALUES (?1, ?2, ?3)",
+                    duckdb::params![path, tag, depth],
+                )
+                .expect("insert");
+        }
+
+        let filter = ExportFilter {
+            root: Some("/data".into()),
+            tag: Some("red".into()),
+        };
+        for (format, reader) in [
+            (TagFileFormat::Csv, "read_csv"),
+            (TagFileFormat::JsonLines, "read_json"),
+            (TagFileFormat::Parquet, "read_parquet"),
+        ] {
+            let destination = temp.path().join(format!("tags-{format:?}"));
+            let summary = export_tags(&connection, &destination, format, &filter).expect("export");
+            assert_eq!(summary.rows, 2);
+
+            let mut statement = connection
+                .prepare(&format!(
+                    "SELECT path, path_depth, created_at IS NOT NULL FROM {reader}(?1) ORDER BY path"
+                ))
+                .expect("prepare");
+            let rows: Vec<(String, i64, bool)> = statement
+                .query_map([destination.to_str().unwrap()], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })
+                .expect("read back")
+                .collect::<Result<_, _>>()
+                .expect("rows");
+            assert_eq!(
+                rows,
+                vec![
+                    ("/data/o'brien".to_string(), 2, true),
+                    ("/data/other".to_string(), 2, true)
+                ]
+            );
+        }
+
+        assert!(matches!(
+            export_tags(
+                &connection,
+                Path::new("relative.csv"),
+                TagFileFormat::Csv,
+                &ExportFilter::default()
+            ),
+            Err(TransferError::InvalidDestination(_))
+        ));
+    }
+
+    #[test]
+    fn imports_rows_with_each_conflict_strategy() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let connection = &mut duckdb::Connection::open_in_memory().expect("in memory db");
+        crate::tagging::ensure_schema(connection).expect("tag schema");
+        connection
+            .execute(
+                "INSERT INTO path_tags (path, tag, path_depth, created_at)
+                 VALUES ('/data/a.txt', 'red', 2, TIMESTAMP '2001-01-01 00:00:00')",
+                [],
+            )
+            .expect("insert");
+
+        let source = temp.path().join("tags.csv");
+        std::fs::write(
+            &source,
+            "path,tag,created_at\n\
+             /data/a.txt,red,2024-05-01 10:00:00\n\
+             /data/a.txt, 007 ,\n\
+             /data/a.txt,007,\n\
+             /old/home/b.txt,blue,\n\
+             relative.txt,red,\n\
+             /data/c.txt,,\n\
+             /data/d.txt,red,yesterday\n",
+        )
+        .expect("write csv");
+        let rewrite = PrefixRewrite {
+            from: "/old/home".into(),
+            to: "/new/home".into(),
+        };
+
+        let summary = import_tags(
+            connection,
+            &source,
+            TagFileFormat::Csv,
+            ImportStrategy::Skip,
+            Some(&rewrite),
+        )
+        .expect("import");
+        assert_eq!(
+            (
+                summary.inserted,
+                summary.overwritten,
+                summary.skipped,
+                summary.invalid
+            ),
+            (2, 0, 2, 3)
+        );
+        assert_eq!(
+            summary
+                .invalid_rows
+                .iter()
+                .map(|invalid| invalid.row)
+                .collect::<Vec<_>>(),
+            vec![5, 6, 7]
+        );
+
+        let rows = |connection: &duckdb::Connection| -> Vec<(String, String, i64, String)> {
+            let mut statement = connection
+                .prepare(
+                    "SELECT path, tag, path_depth, strftime(created_at, '%Y')
+                     FROM path_tags ORDER BY path, tag",
+                )
+                .expect("prepare");
+            statement
+                .query_map([], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })
+                .expect("query")
+                .collect::<Result<_, _>>()
+                .expect("rows")
+        };
+        let year = rows(connection)[0].3.clone();
+        assert_eq!(
+            rows(connection),
+            vec![
+                ("/data/a.txt".into(), "007".into(), 2, year.clone()),
+                ("/data/a.txt".into(), "red".into(), 2, "2001".into()),
+                ("/new/home/b.txt".into(), "blue".into(), 3, year),
+            ]
+        );
+
+        // Nothing is written when a conflict aborts the import
+        assert!(matches!(
+            import_tags(
+                connection,
+                &source,
+                TagFileFormat::Csv,
+                ImportStrategy::Fail,
+                None
+            ),
+            Err(TransferError::Conflict(_))
+        ));
+        assert_eq!(rows(connection).len(), 3);
+
+        let source = temp.path().join("tags.jsonl");
+        std::fs::write(
+            &source,
+            "{\"path\": \"/data/a.txt\", \"tag\": \"red\", \"created_at\": \"2024-05-01 10:00:00\"}\n",
+        )
+        .expect("write json");
+        let summary = import_tags(
+            connection,
+            &source,
+            TagFileFormat::JsonLines,
+            ImportStrategy::Overwrite,
+            None,
+        )
+        .expect("import");
+        assert_eq!((summary.inserted, summary.overwritten), (0, 1));
+        assert_eq!(rows(connection)[1].3, "2024");
+    }
+
+    #[test]
+    fn imports_onto_the_spelling_already_tagged_under_folding_roots() {
+        use crate::path_matching::PathMatching;
+        use crate::storage::TagStorage;
+
+        let temp = tempfile::tempdir().expect("temp dir");
+        let connection = &mut duckdb::Connection::open_in_memory().expect("in memory db");
+        crate::tagging::ensure_schema(connection).expect("tag schema");
+        connection
+            .insert_tag(&[("/data/Photo.JPG".to_string(), 2)], "red")
+            .expect("insert");
+        connection
+            .set_path_matching(
+                "/data",
+                PathMatching {
+                    case_insensitive: true,
+                    unicode_normalized: false,
+                },
+            )
+            .expect("matching");
+
+        let source = temp.path().join("tags.csv");
+        std::fs::write(
+            &source,
+            "path,tag\n\
+             /data/photo.jpg,blue\n\
+             /data/PHOTO.jpg,blue\n\
+             /data/photo.jpg,red\n",
+        )
+        .expect("write csv");
+        let summary = import_tags(
+            connection,
+            &source,
+            TagFileFormat::Csv,
+            ImportStrategy::Skip,
+            None,
+        )
+        .expect("import");
+        assert_eq!((summary.inserted, summary.skipped), (1, 2));
+
+        let mut statement = connection
+            .prepare("SELECT path, tag FROM path_tags ORDER BY path, tag")
+            .expect("prepare");
+        let rows: Vec<(String, String)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .expect("query")
+            .collect::<Result<_, _>>()
+            .expect("rows");
+        assert_eq!(
+            rows,
+            vec![
+                ("/data/Photo.JPG".into(), "blue".into()),
+                ("/data/Photo.JPG".into(), "red".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,778 @@
+use crate::path_codec::{decode_path, encode_path};
+use crate::path_matching::{loose_key, PathMatchingRules};
+use crate::storage::{Database, TagStorage};
+use crate::tag_manifest;
+use crate::AccessError;
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "type", content = "message")]
+pub enum TaggingError {
+    #[error("Tag must not be empty")]
+    EmptyTag,
+
+    #[error("Paths must not be empty")]
+    EmptyPaths,
+
+    #[error(transparent)]
+    Access(#[from] AccessError),
+
+    #[error("Database error: {0}")]
+    Database(String),
+
+    #[error("Query must not be empty")]
+    EmptyQuery,
+
+    #[error("Only read-only queries are allowed, not {0}")]
+    ReadOnlyQuery(String),
+
+    #[error("Query did not finish within {0} ms")]
+    QueryTimeout(u64),
+
+    #[error("Cannot resolve relative path without a current directory: {0}")]
+    RelativePath(String),
+}
+
+const PATH_LOOKUP_CHUNK_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct DirectoryTagSnapshot {
+    pub direct_tags: BTreeMap<PathBuf, Vec<String>>,
+    pub root_ancestor_tags: Vec<String>,
+}
+
+impl DirectoryTagSnapshot {
+    // Moves tags stored under another spelling of one of `paths` onto that
+    // path, wherever the path's matching mode treats both spellings as equal
+    pub(crate) fn align_to(&mut self, rules: &PathMatchingRules, paths: &[PathBuf]) {
+        if rules.is_empty() {
+            return;
+        }
+
+        let mut paths_by_key: HashMap<PathBuf, Vec<&PathBuf>> = HashMap::new();
+        for path in paths {
+            paths_by_key.entry(loose_key(path)).or_default().push(path);
+        }
+
+        let stored_paths: Vec<PathBuf> = self.direct_tags.keys().cloned().collect();
+        for stored_path in stored_paths {
+            let Some(candidates) = paths_by_key.get(&loose_key(&stored_path)) else {
+                continue;
+            };
+            if candidates.contains(&&stored_path) {
+                continue;
+            }
+            let Some(target) = candidates
+                .iter()
+                .find(|path| rules.for_path(path).same_path(path, &stored_path))
+            else {
+                continue;
+            };
+
+            let tags = self.direct_tags.remove(&stored_path).unwrap_or_default();
+            let merged = self.direct_tags.entry((*target).clone()).or_default();
+            merged.extend(tags);
+            merged.sort();
+            merged.dedup();
+        }
+    }
+}
+
+// Adds `tag` to every path, reusing the spelling an equivalent path was
+// already tagged under, and returns the paths as stored
+pub(crate) fn assign_tag(
+    storage: &mut (impl TagStorage + ?Sized),
+    paths: &[PathBuf],
+    tag: &str,
+) -> Result<Vec<PathBuf>, TaggingError> {
+    let normalized_tag = tag.trim();
+    if normalized_tag.is_empty() {
+        return Err(TaggingError::EmptyTag);
+    }
+
+    if paths.is_empty() {
+        return Err(TaggingError::EmptyPaths);
+    }
+
+    let rules = storage.path_matching_rules()?;
+    let mut unique_paths: BTreeMap<String, i64> = BTreeMap::new();
+    for path in paths {
+        let normalized = normalize_path(path)?;
+        let depth = calculate_path_depth(&normalized);
+        let spelling = stored_spellings(storage, &rules, &normalized)?
+            .into_iter()
+            .next()
+            .unwrap_or(normalized);
+        debug!("Assigning tag to path: {:?} -> {:?}", path, spelling);
+        unique_paths.insert(encode_path(&spelling), depth);
+    }
+
+    if unique_paths.is_empty() {
+        return Err(TaggingError::EmptyPaths);
+    }
+
+    let rows: Vec<(String, i64)> = unique_paths.into_iter().collect();
+    storage.insert_tag(&rows, normalized_tag)?;
+    Ok(rows.iter().map(|(path, _)| decode_path(path)).collect())
+}
+
+pub fn assign_tag_to_paths(
+    database: &mut Database,
+    paths: &[PathBuf],
+    tag: &str,
+) -> Result<(), TaggingError> {
+    let tagged = assign_tag(database.storage_mut(), paths, tag)?;
+    mirror_to_manifests(database, &tagged);
+    Ok(())
+}
+
+// Rewrites the `.tags` manifests next to `paths` after their tags changed
+pub(crate) fn mirror_to_manifests(database: &mut Database, paths: &[PathBuf]) {
+    // Manifest roots are only kept by DuckDB
+    if let Some(connection) = database.duckdb() {
+        tag_manifest::mirror_directories(connection, paths);
+    }
+}
+
+pub(crate) fn ensure_schema(storage: &(impl TagStorage + ?Sized)) -> Result<(), TaggingError> {
+    storage.ensure_schema()
+}
+
+fn collect_descendant_tags(
+    storage: &(impl TagStorage + ?Sized),
+    rules: &PathMatchingRules,
+    root_path: &Path,
+    normalized_root: &str,
+    root_depth: i64,
+    max_allowed_depth: i64,
+) -> Result<BTreeMap<PathBuf, BTreeSet<String>>, TaggingError> {
+    let mut tags_by_path: BTreeMap<PathBuf, BTreeSet<String>> = BTreeMap::new();
+    let rows = storage.descendant_rows(rules, normalized_root, root_depth, max_allowed_depth)?;
+    for (stored_path, tag) in rows {
+        let stored_path_buf = decode_path(&stored_path);
+
+        if rules
+            .for_path(&stored_path_buf)
+            .is_within(&stored_path_buf, root_path)
+        {
+            tags_by_path.entry(stored_path_buf).or_default().insert(tag);
+        }
+    }
+
+    Ok(tags_by_path)
+}
+
+fn collect_ancestor_tags(
+    storage: &(impl TagStorage + ?Sized),
+    rules: &PathMatchingRules,
+    root_path: &Path,
+) -> Result<BTreeSet<String>, TaggingError> {
+    let ancestor_paths: Vec<String> = root_path
+        .ancestors()
+        .skip(1)
+        .filter_map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                None
+            } else {
+                Some(encode_path(ancestor))
+            }
+        })
+        .collect();
+
+    if ancestor_paths.is_empty() {
+        return Ok(BTreeSet::new());
+    }
+
+    let mut tags = BTreeSet::new();
+    for (stored_path, tag) in storage.rows_for_paths(rules, &ancestor_paths)? {
+        let stored_path_buf = decode_path(&stored_path);
+
+        if rules
+            .for_path(&stored_path_buf)
+            .is_within(root_path, &stored_path_buf)
+        {
+            tags.insert(tag);
+        }
+    }
+
+    Ok(tags)
+}
+
+pub fn get_tags_for_directory<P: AsRef<Path>>(
+    storage: &(impl TagStorage + ?Sized),
+    root: P,
+    max_depth: usize,
+) -> Result<DirectoryTagSnapshot, TaggingError> {
+    let root = root.as_ref();
+    let root_path = normalize_path(root)?;
+    let normalized_root = encode_path(&root_path);
+    let root_depth = calculate_path_depth(&root_path);
+    let depth_offset = if max_depth > i64::MAX as usize {
+        i64::MAX
+    } else {
+        max_depth as i64
+    };
+    let max_allowed_depth = root_depth.saturating_add(depth_offset);
+
+    let rules = storage.path_matching_rules()?;
+    let tags_by_path = collect_descendant_tags(
+        storage,
+        &rules,
+        &root_path,
+        &normalized_root,
+        root_depth,
+        max_allowed_depth,
+    )?;
+
+    let ancestor_tags = collect_ancestor_tags(storage, &rules, &root_path)?;
+
+    let direct_tags = tags_by_path
+        .into_iter()
+        .filter_map(|(path, tags)| {
+            if tags.is_empty() {
+                None
+            } else {
+                Some((path, tags.into_iter().collect()))
+            }
+        })
+        .collect();
+
+    Ok(DirectoryTagSnapshot {
+        direct_tags,
+        root_ancestor_tags: ancestor_tags.into_iter().collect(),
+    })
+}
+
+// Looks up the tags stored on specific, already-normalized paths. Results are
+// keyed by the requested paths, whichever equivalent spelling the tags are
+// stored under.
+pub fn get_tags_for_paths(
+    storage: &(impl TagStorage + ?Sized),
+    paths: &[PathBuf],
+) -> Result<BTreeMap<PathBuf, Vec<String>>, TaggingError> {
+    let rules = storage.path_matching_rules()?;
+    let mut tags_by_path: BTreeMap<PathBuf, BTreeSet<String>> = BTreeMap::new();
+
+    for chunk in paths.chunks(PATH_LOOKUP_CHUNK_SIZE) {
+        let path_strings: Vec<String> = chunk.iter().map(|path| encode_path(path)).collect();
+        let mut requested_by_key: HashMap<PathBuf, Vec<&PathBuf>> = HashMap::new();
+        if !rules.is_empty() {
+            for path in chunk {
+                requested_by_key
+                    .entry(loose_key(path))
+                    .or_default()
+                    .push(path);
+            }
+        }
+
+        for (stored_path, tag) in storage.rows_for_paths(&rules, &path_strings)? {
+            let stored_path = decode_path(&stored_path);
+            if rules.is_empty() {
+                tags_by_path.entry(stored_path).or_default().insert(tag);
+                continue;
+            }
+
+            let requested = requested_by_key
+                .get(&loose_key(&stored_path))
+                .into_iter()
+                .flatten()
+                .filter(|path| rules.for_path(path).same_path(path, &stored_path));
+            for path in requested {
+                tags_by_path
+                    .entry((*path).clone())
+                    .or_default()
+                    .insert(tag.clone());
+            }
+        }
+    }
+
+    Ok(tags_by_path
+        .into_iter()
+        .map(|(path, tags)| (path, tags.into_iter().collect()))
+        .collect())
+}
+
+// Makes `tags` the complete set of direct tags of an already-normalized path
+pub(crate) fn replace_tags_for_path(
+    storage: &(impl TagStorage + ?Sized),
+    path: &Path,
+    tags: &[String],
+) -> Result<(), TaggingError> {
+    let rules = storage.path_matching_rules()?;
+    let spellings = stored_spellings(storage, &rules, path)?;
+    let removed: Vec<String> = spellings
+        .iter()
+        .map(|spelling| encode_path(spelling))
+        .collect();
+    let stored_path = encode_path(spellings.first().map_or(path, PathBuf::as_path));
+
+    storage.replace_tags(&removed, &stored_path, calculate_path_depth(path), tags)
+}
+
+// Spellings `path` is already stored under that its matching mode treats as
+// the same path, including `path` itself
+pub(crate) fn stored_spellings(
+    storage: &(impl TagStorage + ?Sized),
+    rules: &PathMatchingRules,
+    path: &Path,
+) -> Result<Vec<PathBuf>, TaggingError> {
+    let matching = rules.for_path(path);
+    Ok(storage
+        .candidate_spellings(rules, &encode_path(path))?
+        .iter()
+        .map(|spelling| decode_path(spelling))
+        .filter(|spelling| matching.same_path(spelling, path))
+        .collect())
+}
+
+// Relative paths are taken against the current directory, as the filesystem
+// calls that read them do
+pub(crate) fn normalize_path(path: &Path) -> Result<PathBuf, TaggingError> {
+    match fs::canonicalize(path) {
+        Ok(canonical) => Ok(canonical),
+        Err(err) => {
+            warn!(
+                "Failed to canonicalize path; normalizing lexically: {:?} ({})",
+                path, err
+            );
+            let base = relative_base(path)?;
+            Ok(normalize_lexically(path, &base))
+        }
+    }
+}
+
+// What `path` is relative to; absolute paths need no base
+pub(crate) fn relative_base(path: &Path) -> Result<PathBuf, TaggingError> {
+    if path.is_absolute() {
+        return Ok(PathBuf::new());
+    }
+    env::current_dir().map_err(|_| TaggingError::RelativePath(encode_path(path)))
+}
+
+// Fallback for paths that cannot be canonicalized, typically because they were
+// deleted or do not exist yet. The deepest existing ancestor is still
+// canonicalized so the result matches keys stored while the path existed.
+pub(crate) fn normalize_lexically(path: &Path, base: &Path) -> PathBuf {
+    let resolved = resolve_lexically(path, base);
+
+    let mut existing = resolved.as_path();
+    let mut missing = Vec::new();
+    while let Some(parent) = existing.parent() {
+        if let Some(name) = existing.file_name() {
+            missing.push(name);
+        }
+        existing = parent;
+        if let Ok(mut canonical) = fs::canonicalize(existing) {
+            canonical.extend(missing.iter().rev());
+            return canonical;
+        }
+    }
+
+    resolved
+}
+
+// Makes `path` absolute against `base` and drops `.`, `..`, repeated and
+// trailing separators without touching the filesystem
+fn resolve_lexically(path: &Path, base: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in base.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match resolved.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    resolved.pop();
+                }
+                Some(Component::ParentDir) | None => resolved.push(component),
+                // Nothing lies above the root
+                _ => {}
+            },
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+pub(crate) fn calculate_path_depth(path: &Path) -> i64 {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .count() as i64
+}
+
+pub(crate) fn descendant_like_pattern(root: &str) -> String {
+    let mut base = root.to_string();
+    let separator = std::path::MAIN_SEPARATOR_STR;
+
+    if !base.ends_with(separator) {
+        base.push_str(separator);
+    }
+
+    let escaped = escape_for_like(&base);
+    format!("{escaped}%")
+}
+
+pub(crate) fn escape_for_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for ch in input.chars() {
+        match ch {
+            '\\' | '%' | '_' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            _ => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path_matching::PathMatching;
+    use crate::storage::tests::every_backend;
+
+    struct TestPaths {
+        ancestor: PathBuf,
+        parent: PathBuf,
+        scan_root: PathBuf,
+        descendant: PathBuf,
+        deep_descendant: PathBuf,
+        unrelated: PathBuf,
+    }
+
+    #[cfg(windows)]
+    fn sample_paths() -> TestPaths {
+        let parent = PathBuf::from(r"C:\workspace");
+        let scan_root = parent.join("project");
+        let descendant = scan_root.join("notes.txt");
+        let deep_descendant = scan_root.join("folder").join("deep.txt");
+        let ancestor = PathBuf::from(r"C:\");
+        let unrelated = PathBuf::from(r"C:\other");
+
+        TestPaths {
+            ancestor,
+            parent,
+            scan_root,
+            descendant,
+            deep_descendant,
+            unrelated,
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn sample_paths() -> TestPaths {
+        let parent = PathBuf::from("/workspace");
+        let scan_root = parent.join("project");
+        let descendant = scan_root.join("notes.txt");
+        let deep_descendant = scan_root.join("folder").join("deep.txt");
+        let ancestor = PathBuf::from("/");
+        let unrelated = PathBuf::from("/other");
+
+        TestPaths {
+            ancestor,
+            parent,
+            scan_root,
+            descendant,
+            deep_descendant,
+            unrelated,
+        }
+    }
+
+    fn path_to_string(path: &Path) -> String {
+        encode_path(path)
+    }
+
+    fn insert(database: &mut Database, path: &Path, tag: &str) {
+        database
+            .storage_mut()
+            .insert_tag(&[(path_to_string(path), calculate_path_depth(path))], tag)
+            .expect("insert tag");
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_get_then_check)]
+    fn collects_tags_with_direct_and_inherited_entries() {
+        let paths = sample_paths();
+        for mut database in every_backend() {
+            insert(&mut database, &paths.parent, "parent-tag");
+            insert(&mut database, &paths.scan_root, "root-tag");
+            insert(&mut database, &paths.descendant, "desc-tag");
+            insert(&mut database, &paths.ancestor, "ancestor-tag");
+            insert(&mut database, &paths.unrelated, "other-tag");
+
+            let snapshot = get_tags_for_directory(database.storage(), &paths.scan_root, 5)
+                .expect("fetch tags");
+
+            assert_eq!(
+                snapshot.direct_tags.get(&paths.scan_root),
+                Some(&vec!["root-tag".to_string()])
+            );
+
+            assert_eq!(
+                snapshot.direct_tags.get(&paths.descendant),
+                Some(&vec!["desc-tag".to_string()])
+            );
+
+            assert!(snapshot.direct_tags.get(&paths.unrelated).is_none());
+            assert_eq!(
+                snapshot.root_ancestor_tags,
+                vec!["ancestor-tag".to_string(), "parent-tag".to_string()]
+            );
+        }
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_get_then_check)]
+    fn filters_descendants_by_scan_depth() {
+        let paths = sample_paths();
+        for mut database in every_backend() {
+            insert(&mut database, &paths.scan_root, "root-tag");
+            insert(&mut database, &paths.descendant, "desc-tag");
+            insert(&mut database, &paths.deep_descendant, "deep-tag");
+
+            let depth_one = get_tags_for_directory(database.storage(), &paths.scan_root, 1)
+                .expect("fetch depth 1 tags");
+            assert!(depth_one.direct_tags.get(&paths.scan_root).is_some());
+            assert!(depth_one.direct_tags.get(&paths.descendant).is_some());
+            assert!(depth_one.direct_tags.get(&paths.deep_descendant).is_none());
+
+            let depth_three = get_tags_for_directory(database.storage(), &paths.scan_root, 3)
+                .expect("fetch depth 3 tags");
+            assert!(depth_three
+                .direct_tags
+                .get(&paths.deep_descendant)
+                .is_some());
+        }
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_get_then_check)]
+    fn inherits_tags_from_all_ancestors() {
+        let paths = sample_paths();
+        for mut database in every_backend() {
+            insert(&mut database, &paths.ancestor, "ancestor-tag");
+            insert(&mut database, &paths.parent, "parent-tag");
+
+            let snapshot = get_tags_for_directory(database.storage(), &paths.scan_root, 5)
+                .expect("fetch tags");
+
+            assert!(snapshot.direct_tags.get(&paths.scan_root).is_none());
+            assert_eq!(
+                snapshot.root_ancestor_tags,
+                vec!["ancestor-tag".to_string(), "parent-tag".to_string()]
+            );
+        }
+    }
+
+    #[test]
+    fn fetches_tags_for_explicit_paths() {
+        let paths = sample_paths();
+        for mut database in every_backend() {
+            insert(&mut database, &paths.unrelated, "other-tag");
+            insert(&mut database, &paths.unrelated, "beta");
+            insert(&mut database, &paths.descendant, "desc-tag");
+
+            let tags =
+                get_tags_for_paths(database.storage(), std::slice::from_ref(&paths.unrelated))
+                    .expect("fetch tags");
+
+            assert_eq!(
+                tags.get(&paths.unrelated),
+                Some(&vec!["beta".to_string(), "other-tag".to_string()])
+            );
+            assert!(!tags.contains_key(&paths.descendant));
+        }
+    }
+
+    #[test]
+    fn assigns_tags_once_per_path() {
+        let paths = sample_paths();
+        for mut database in every_backend() {
+            assert!(matches!(
+                assign_tag_to_paths(&mut database, std::slice::from_ref(&paths.unrelated), " "),
+                Err(TaggingError::EmptyTag)
+            ));
+            assign_tag_to_paths(
+                &mut database,
+                &[paths.unrelated.clone(), paths.unrelated.join(".")],
+                " red ",
+            )
+            .expect("assign");
+            assign_tag_to_paths(&mut database, std::slice::from_ref(&paths.unrelated), "red")
+                .expect("assign again");
+
+            let storage = database.storage();
+            let rows = storage
+                .rows_for_paths(
+                    &PathMatchingRules::default(),
+                    &[path_to_string(&paths.unrelated)],
+                )
+                .expect("rows");
+            assert_eq!(
+                rows,
+                vec![(path_to_string(&paths.unrelated), "red".to_string())]
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stores_non_utf8_paths_without_loss() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let paths = sample_paths();
+        let odd = paths.scan_root.join(OsStr::from_bytes(b"caf\xe9.txt"));
+        let lookalike = paths.scan_root.join("caf\u{FFFD}.txt");
+        for database in every_backend() {
+            let storage = database.storage();
+            replace_tags_for_path(storage, &odd, &["odd".to_string()]).expect("tag odd");
+            replace_tags_for_path(storage, &lookalike, &["lookalike".to_string()])
+                .expect("tag lookalike");
+
+            let snapshot =
+                get_tags_for_directory(storage, &paths.scan_root, 1).expect("fetch tags");
+            assert_eq!(
+                snapshot.direct_tags.get(&odd),
+                Some(&vec!["odd".to_string()])
+            );
+            assert_eq!(
+                snapshot.direct_tags.get(&lookalike),
+                Some(&vec!["lookalike".to_string()])
+            );
+
+            let tags = get_tags_for_paths(storage, std::slice::from_ref(&odd)).expect("fetch");
+            assert_eq!(tags.keys().collect::<Vec<_>>(), vec![&odd]);
+        }
+    }
+
+    #[test]
+    fn resolves_dot_segments_and_separators_lexically() {
+        let base = sample_paths().parent;
+
+        let resolve = |path: &str| resolve_lexically(Path::new(path), &base);
+        assert_eq!(
+            resolve("project/./old/../notes.txt"),
+            base.join("project").join("notes.txt")
+        );
+        assert_eq!(
+            resolve("project//nested/"),
+            base.join("project").join("nested")
+        );
+        assert_eq!(resolve(".."), sample_paths().ancestor);
+        assert_eq!(resolve("../../../.."), sample_paths().ancestor);
+        assert_eq!(
+            resolve_lexically(&base.join("a").join("..").join("b"), Path::new("ignored")),
+            base.join("b")
+        );
+        assert_eq!(
+            resolve_lexically(Path::new("../a/./b"), Path::new("")),
+            PathBuf::from("..").join("a").join("b")
+        );
+    }
+
+    #[test]
+    fn normalizes_missing_paths_under_their_canonical_ancestor() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let canonical_root = fs::canonicalize(temp.path()).expect("canonical root");
+        let gone = temp
+            .path()
+            .join("gone")
+            .join(".")
+            .join("..")
+            .join("deleted.txt");
+
+        assert_eq!(
+            normalize_path(&gone).expect("absolute"),
+            canonical_root.join("deleted.txt")
+        );
+        assert_eq!(
+            normalize_path(&temp.path().join("deleted.txt")).expect("absolute"),
+            normalize_path(&gone).expect("absolute")
+        );
+        // Relative paths resolve against the current directory
+        let current =
+            fs::canonicalize(env::current_dir().expect("current dir")).expect("canonical");
+        assert_eq!(
+            normalize_path(Path::new("gone/../deleted.txt")).expect("relative"),
+            current.join("deleted.txt")
+        );
+    }
+
+    #[test]
+    fn matches_equivalent_spellings_under_folding_roots() {
+        let paths = sample_paths();
+        let stored_dir = paths.parent.join("Cafe\u{301}");
+        let stored_file = stored_dir.join("Notes.TXT");
+        let dir = paths.parent.join("caf\u{e9}");
+        let file = dir.join("notes.txt");
+        for mut database in every_backend() {
+            insert(&mut database, &stored_dir, "dir-tag");
+            insert(&mut database, &stored_file, "file-tag");
+            let storage = database.storage();
+
+            let exact = get_tags_for_directory(storage, &dir, 2).expect("fetch tags");
+            assert!(exact.direct_tags.is_empty());
+
+            storage
+                .set_path_matching(
+                    &path_to_string(&paths.parent),
+                    PathMatching {
+                        case_insensitive: true,
+                        unicode_normalized: true,
+                    },
+                )
+                .expect("configure matching");
+            let rules = storage.path_matching_rules().expect("rules");
+
+            let mut snapshot = get_tags_for_directory(storage, &dir, 2).expect("fetch tags");
+            assert_eq!(
+                snapshot.direct_tags.get(&stored_file),
+                Some(&vec!["file-tag".to_string()])
+            );
+            snapshot.align_to(&rules, &[dir.clone(), file.clone()]);
+            assert_eq!(
+                snapshot.direct_tags.get(&file),
+                Some(&vec!["file-tag".to_string()])
+            );
+            assert_eq!(
+                snapshot.direct_tags.get(&dir),
+                Some(&vec!["dir-tag".to_string()])
+            );
+
+            let nested = get_tags_for_directory(storage, dir.join("sub"), 1).expect("fetch");
+            assert_eq!(nested.root_ancestor_tags, vec!["dir-tag".to_string()]);
+
+            let by_path =
+                get_tags_for_paths(storage, std::slice::from_ref(&file)).expect("fetch tags");
+            assert_eq!(by_path.get(&file), Some(&vec!["file-tag".to_string()]));
+
+            // Retagging keeps the original spelling instead of adding a second key
+            replace_tags_for_path(storage, &file, &["renamed".to_string()]).expect("replace");
+            let stored = storage
+                .rows_for_paths(&rules, &[path_to_string(&file)])
+                .expect("rows");
+            assert_eq!(
+                stored,
+                vec![(path_to_string(&stored_file), "renamed".to_string())]
+            );
+        }
+    }
+
+    #[test]
+    fn returns_empty_when_no_relevant_tags() {
+        let paths = sample_paths();
+        for database in every_backend() {
+            let snapshot = get_tags_for_directory(database.storage(), &paths.scan_root, 3)
+                .expect("fetch tags");
+            assert!(snapshot.direct_tags.is_empty());
+            assert!(snapshot.root_ancestor_tags.is_empty());
+        }
+    }
+}
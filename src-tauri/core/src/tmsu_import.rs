@@ -0,0 +1,421 @@
+use crate::path_codec::{encode_path, serialize_path};
+use crate::tag_transfer::{store_rows, ImportStrategy, ImportSummary, ImportedRow, TransferError};
+use log::info;
+use rusqlite::types::ValueRef;
+use rusqlite::OpenFlags;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize)]
+pub struct MissingFile {
+    #[serde(serialize_with = "serialize_path")]
+    path: PathBuf,
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TmsuImportReport {
+    #[serde(flatten)]
+    summary: ImportSummary,
+    // Tagged files that no longer exist; their tags are not imported
+    missing: Vec<MissingFile>,
+}
+
+// Copies the tags of a TMSU database (usually `.tmsu/db`) into `path_tags`.
+// TMSU values have no counterpart here, so `year` with value `2017` becomes
+// the tag `year=2017`. Tags implied through TMSU implications are imported
+// as if they had been applied directly.
+pub fn import_tmsu_database(
+    connection: &mut duckdb::Connection,
+    database: &Path,
+    strategy: ImportStrategy,
+) -> Result<TmsuImportReport, TransferError> {
+    let (tagged_files, unreadable) = read_tagged_files(database)?;
+
+    let mut rows: Vec<_> = unreadable
+        .into_iter()
+        .map(ImportedRow::unreadable)
+        .collect();
+    let mut missing = Vec::new();
+    for (path, tags) in tagged_files {
+        if path.symlink_metadata().is_err() {
+            missing.push(MissingFile { path, tags });
+            continue;
+        }
+        rows.extend(tags.into_iter().map(|tag| ImportedRow::new(&path, tag)));
+    }
+
+    let summary = store_rows(connection, rows, strategy, None)?;
+    info!(
+        "Imported TMSU database {:?}; {} tagged files no longer exist",
+        database,
+        missing.len()
+    );
+    Ok(TmsuImportReport { summary, missing })
+}
+
+type TaggedFile = (PathBuf, Vec<String>);
+
+// A file row as TMSU stores it, with the tags applied to it
+struct RawFile {
+    id: i64,
+    directory: Vec<u8>,
+    name: Vec<u8>,
+    pairs: BTreeSet<TagPair>,
+}
+
+// A TMSU `(tag_id, value_id)` pair; value 0 means the tag has no value
+type TagPair = (i64, i64);
+
+// Tags of every tagged file, in file order, plus why any file or tag could
+// not be read. Relative directories, which TMSU stores for files below the
+// directory holding `.tmsu`, are resolved against it.
+fn read_tagged_files(database: &Path) -> Result<(Vec<TaggedFile>, Vec<String>), TransferError> {
+    let invalid = |reason: String| {
+        TransferError::InvalidSource(format!("{}: {reason}", encode_path(database)))
+    };
+    if !database.is_file() {
+        return Err(invalid("is not a file".into()));
+    }
+    let root = database
+        .parent()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new("/"));
+
+    let sqlite = rusqlite::Connection::open_with_flags(
+        database,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|err| invalid(err.to_string()))?;
+    let not_tmsu = |err: rusqlite::Error| invalid(format!("not a TMSU database ({err})"));
+    let tag_names = read_names(&sqlite, "tag").map_err(not_tmsu)?;
+    let value_names = read_names(&sqlite, "value").map_err(not_tmsu)?;
+    let implications = read_implications(&sqlite).map_err(not_tmsu)?;
+
+    // TMSU declares names TEXT but stores whatever bytes the file system
+    // gave it, so they are read as bytes rather than as UTF-8
+    let mut statement = sqlite
+        .prepare(
+            "
+            SELECT f.id, f.directory, f.name, ft.tag_id, ft.value_id
+            FROM file_tag ft
+            JOIN file f ON f.id = ft.file_id
+            ORDER BY f.id
+            ",
+        )
+        .map_err(not_tmsu)?;
+    let mut rows = statement
+        .query([])
+        .map_err(|err| invalid(err.to_string()))?;
+    let mut files: Vec<RawFile> = Vec::new();
+    while let Some(row) = rows.next().map_err(|err| invalid(err.to_string()))? {
+        let read = || -> rusqlite::Result<_> {
+            Ok((
+                row.get::<_, i64>(0)?,
+                text_bytes(row.get_ref(1)?),
+                text_bytes(row.get_ref(2)?),
+                (row.get::<_, i64>(3)?, row.get::<_, i64>(4)?),
+            ))
+        };
+        let (id, directory, name, pair) = read().map_err(|err| invalid(err.to_string()))?;
+        match files.last_mut() {
+            Some(file) if file.id == id => {
+                file.pairs.insert(pair);
+            }
+            _ => files.push(RawFile {
+                id,
+                directory,
+                name,
+                pairs: BTreeSet::from([pair]),
+            }),
+        }
+    }
+
+    let mut tagged_files = Vec::new();
+    let mut unreadable = Vec::new();
+    for RawFile {
+        directory,
+        name,
+        pairs,
+        ..
+    } in files
+    {
+        let (Some(directory), Some(name)) = (path_from_bytes(&directory), path_from_bytes(&name))
+        else {
+            unreadable.push(format!(
+                "{:?} in {:?} is not a valid path here",
+                String::from_utf8_lossy(&name),
+                String::from_utf8_lossy(&directory)
+            ));
+            continue;
+        };
+        let path = root.join(directory).join(name);
+
+        let mut tags = Vec::new();
+        for (tag, value) in with_implied(pairs, &implications) {
+            match tag_text(&tag_names, &value_names, tag, value) {
+                Ok(text) => tags.push(text),
+                Err(reason) => unreadable.push(format!("{}: {reason}", encode_path(&path))),
+            }
+        }
+        tags.sort();
+        tags.dedup();
+        if !tags.is_empty() {
+            tagged_files.push((path, tags));
+        }
+    }
+    Ok((tagged_files, unreadable))
+}
+
+fn text_bytes(value: ValueRef<'_>) -> Vec<u8> {
+    match value {
+        ValueRef::Text(bytes) | ValueRef::Blob(bytes) => bytes.to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    std::str::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+// Names by id; `None` for a name that is not UTF-8
+fn read_names(
+    sqlite: &rusqlite::Connection,
+    table: &str,
+) -> rusqlite::Result<HashMap<i64, Option<String>>> {
+    let mut statement = sqlite.prepare(&format!("SELECT id, name FROM {table}"))?;
+    let mut rows = statement.query([])?;
+    let mut names = HashMap::new();
+    while let Some(row) = rows.next()? {
+        names.insert(
+            row.get(0)?,
+            String::from_utf8(text_bytes(row.get_ref(1)?)).ok(),
+        );
+    }
+    Ok(names)
+}
+
+// Each implication as (implying pair, implied pair). An implying value of 0
+// matches the tag with any value, as in TMSU.
+fn read_implications(sqlite: &rusqlite::Connection) -> rusqlite::Result<Vec<(TagPair, TagPair)>> {
+    let mut statement = sqlite.prepare("SELECT name FROM pragma_table_info('implication')")?;
+    let columns = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if columns.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Implications only gained values in TMSU 0.7
+    let sql = if columns.iter().any(|column| column == "value_id") {
+        "SELECT tag_id, value_id, implied_tag_id, implied_value_id FROM implication"
+    } else {
+        "SELECT tag_id, 0, implied_tag_id, 0 FROM implication"
+    };
+    let mut statement = sqlite.prepare(sql)?;
+    let rows = statement.query_map([], |row| {
+        Ok(((row.get(0)?, row.get(1)?), (row.get(2)?, row.get(3)?)))
+    })?;
+    rows.collect()
+}
+
+// `pairs` plus everything they imply, directly or through other implications
+fn with_implied(
+    mut pairs: BTreeSet<TagPair>,
+    implications: &[(TagPair, TagPair)],
+) -> BTreeSet<TagPair> {
+    loop {
+        let implied: Vec<TagPair> = implications
+            .iter()
+            .filter(|(implying, implied)| {
+                !pairs.contains(implied)
+                    && pairs.iter().any(|&(tag, value)| {
+                        tag == implying.0 && (implying.1 == 0 || implying.1 == value)
+                    })
+            })
+            .map(|&(_, implied)| implied)
+            .collect();
+        if implied.is_empty() {
+            return pairs;
+        }
+        pairs.extend(implied);
+    }
+}
+
+fn tag_text(
+    tag_names: &HashMap<i64, Option<String>>,
+    value_names: &HashMap<i64, Option<String>>,
+    tag: i64,
+    value: i64,
+) -> Result<String, String> {
+    let name = |names: &HashMap<i64, Option<String>>, kind: &str, id: i64| match names.get(&id) {
+        Some(Some(name)) => Ok(name.clone()),
+        Some(None) => Err(format!("{kind} {id} is not valid UTF-8")),
+        None => Err(format!("unknown {kind} {id}")),
+    };
+    let tag = name(tag_names, "tag", tag)?;
+    if value == 0 {
+        return Ok(tag);
+    }
+    let value = name(value_names, "value", value)?;
+    Ok(if value.is_empty() {
+        tag
+    } else {
+        format!("{tag}={value}")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_tags_and_values_and_reports_missing_files() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = temp.path().canonicalize().expect("canonical temp dir");
+        std::fs::create_dir_all(root.join(".tmsu")).expect("tmsu dir");
+        std::fs::create_dir_all(root.join("photos")).expect("photos dir");
+        std::fs::write(root.join("photos/beach.jpg"), "jpg").expect("write");
+        std::fs::write(root.join("notes.txt"), "txt").expect("write");
+
+        let database = root.join(".tmsu/db");
+        let sqlite = rusqlite::Connection::open(&database).expect("sqlite");
+        sqlite
+            .execute_batch(&format!(
+                "
+                CREATE TABLE file (id INTEGER PRIMARY KEY, directory TEXT NOT NULL,
+                    name TEXT NOT NULL, fingerprint TEXT, mod_time DATETIME,
+                    size INTEGER, is_dir BOOLEAN);
+                CREATE TABLE tag (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                CREATE TABLE value (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                CREATE TABLE file_tag (file_id INTEGER NOT NULL, tag_id INTEGER NOT NULL,
+                    value_id INTEGER NOT NULL);
+                INSERT INTO file (id, directory, name) VALUES
+                    (1, 'photos', 'beach.jpg'),
+                    (2, '{}', 'notes.txt'),
+                    (3, 'photos', 'gone.jpg');
+                INSERT INTO tag VALUES (1, 'holiday'), (2, 'year'), (3, 'todo');
+                INSERT INTO value VALUES (1, '2017');
+                INSERT INTO file_tag VALUES (1, 1, 0), (1, 2, 1), (2, 3, 0), (3, 1, 0);
+                ",
+                root.display()
+            ))
+            .expect("tmsu schema");
+        drop(sqlite);
+
+        let connection = &mut duckdb::Connection::open_in_memory().expect("in memory db");
+        crate::tagging::ensure_schema(connection).expect("tag schema");
+        let report =
+            import_tmsu_database(connection, &database, ImportStrategy::Skip).expect("import");
+
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].path, root.join("photos/gone.jpg"));
+        assert_eq!(report.missing[0].tags, vec!["holiday".to_string()]);
+
+        let mut statement = connection
+            .prepare("SELECT path, tag FROM path_tags ORDER BY path, tag")
+            .expect("prepare");
+        let rows: Vec<(String, String)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .expect("query")
+            .collect::<Result<_, _>>()
+            .expect("rows");
+        let path = |relative: &str| root.join(relative).to_string_lossy().into_owned();
+        assert_eq!(
+            rows,
+            vec![
+                (path("notes.txt"), "todo".to_string()),
+                (path("photos/beach.jpg"), "holiday".to_string()),
+                (path("photos/beach.jpg"), "year=2017".to_string()),
+            ]
+        );
+
+        assert!(matches!(
+            import_tmsu_database(connection, &root.join("notes.txt"), ImportStrategy::Skip),
+            Err(TransferError::InvalidSource(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_non_utf8_names_and_applies_implications() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = temp.path().canonicalize().expect("canonical temp dir");
+        std::fs::create_dir_all(root.join(".tmsu")).expect("tmsu dir");
+        let latin1 = root.join(std::ffi::OsStr::from_bytes(b"caf\xe9.jpg"));
+        std::fs::write(&latin1, "jpg").expect("write");
+        std::fs::write(root.join("song.mp3"), "mp3").expect("write");
+
+        let database = root.join(".tmsu/db");
+        let sqlite = rusqlite::Connection::open(&database).expect("sqlite");
+        sqlite
+            .execute_batch(&format!(
+                "
+                CREATE TABLE file (id INTEGER PRIMARY KEY, directory TEXT NOT NULL,
+                    name TEXT NOT NULL);
+                CREATE TABLE tag (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                CREATE TABLE value (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                CREATE TABLE file_tag (file_id INTEGER NOT NULL, tag_id INTEGER NOT NULL,
+                    value_id INTEGER NOT NULL);
+                CREATE TABLE implication (tag_id INTEGER NOT NULL, value_id INTEGER NOT NULL,
+                    implied_tag_id INTEGER NOT NULL, implied_value_id INTEGER NOT NULL);
+                INSERT INTO file VALUES
+                    (1, '{root}', CAST(X'636166E92E6A7067' AS TEXT)),
+                    (2, '{root}', 'song.mp3');
+                INSERT INTO tag VALUES (1, 'holiday'), (2, 'travel'), (3, 'memories'),
+                    (4, CAST(X'FF' AS TEXT)), (5, 'music'), (6, 'year');
+                INSERT INTO value VALUES (1, '2017'), (2, '2016');
+                INSERT INTO file_tag VALUES (1, 1, 0), (1, 6, 2), (2, 5, 0), (2, 4, 0);
+                -- holiday => travel => memories, and year=2017 => music
+                INSERT INTO implication VALUES (1, 0, 2, 0), (2, 0, 3, 0), (6, 1, 5, 0);
+                ",
+                root = root.display()
+            ))
+            .expect("tmsu schema");
+        drop(sqlite);
+
+        let connection = &mut duckdb::Connection::open_in_memory().expect("in memory db");
+        crate::tagging::ensure_schema(connection).expect("tag schema");
+        let report =
+            import_tmsu_database(connection, &database, ImportStrategy::Skip).expect("import");
+
+        let json = serde_json::to_value(&report).expect("serializable report");
+        assert_eq!(json["inserted"], 5);
+        assert_eq!(json["invalid"], 1);
+        assert!(json["invalid_rows"][0]["reason"]
+            .as_str()
+            .expect("reason")
+            .contains("tag 4 is not valid UTF-8"));
+
+        let mut statement = connection
+            .prepare("SELECT path, tag FROM path_tags ORDER BY path, tag")
+            .expect("prepare");
+        let rows: Vec<(String, String)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .expect("query")
+            .collect::<Result<_, _>>()
+            .expect("rows");
+        let latin1 = encode_path(&latin1);
+        let song = encode_path(&root.join("song.mp3"));
+        assert_eq!(
+            rows,
+            vec![
+                (latin1.clone(), "holiday".to_string()),
+                (latin1.clone(), "memories".to_string()),
+                (latin1.clone(), "travel".to_string()),
+                (latin1, "year=2016".to_string()),
+                (song, "music".to_string()),
+            ]
+        );
+    }
+}
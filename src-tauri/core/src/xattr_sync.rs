@@ -0,0 +1,604 @@
+use crate::path_codec::{decode_path, encode_path, serialize_path};
+use crate::scan::{SkipKind, SkippedEntry};
+use crate::storage::{Database, TagStorage};
+use crate::tagging::{get_tags_for_directory, mirror_to_manifests, replace_tags_for_path};
+use crate::xdg_tags::{self, XATTRS_SUPPORTED};
+use crate::AccessError;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "type", content = "message")]
+pub enum SyncError {
+    #[error("Extended attributes are not supported on this platform")]
+    Unsupported,
+
+    #[error(transparent)]
+    Access(#[from] AccessError),
+
+    #[error("IO error: {0}")]
+    Io(String),
+
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+// Which side wins when a path's tags changed in both the database and
+// `user.xdg.tags` since the last sync. A side that still matches the last sync
+// takes the other side's tags, removals included; a path never synced before
+// counts as having had no tags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    #[default]
+    DatabaseWins,
+    XattrWins,
+    Union,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncDirection {
+    ToXattr,
+    ToDatabase,
+    Both,
+}
+
+impl SyncDirection {
+    fn writes_xattr(self) -> bool {
+        matches!(self, Self::ToXattr | Self::Both)
+    }
+
+    fn writes_database(self) -> bool {
+        matches!(self, Self::ToDatabase | Self::Both)
+    }
+}
+
+// One path whose two tag sets disagree, with what both sides hold afterwards
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncChange {
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) path: PathBuf,
+    pub(crate) database_tags: Vec<String>,
+    pub(crate) xattr_tags: Vec<String>,
+    pub(crate) resolved_tags: Vec<String>,
+    pub(crate) direction: SyncDirection,
+}
+
+// In a dry run `changes` lists what would be written; otherwise what was written
+#[derive(Debug, Serialize)]
+pub struct SyncReport {
+    dry_run: bool,
+    policy: ConflictPolicy,
+    changes: Vec<SyncChange>,
+    skipped: Vec<SkippedEntry>,
+}
+
+// Reconciles the direct tags of `root` and everything below it with their
+// `user.xdg.tags` attributes. Symlinks are not followed.
+pub fn sync_xattr_tags(
+    database: &mut Database,
+    root: &Path,
+    policy: ConflictPolicy,
+    dry_run: bool,
+) -> Result<SyncReport, SyncError> {
+    if !XATTRS_SUPPORTED {
+        return Err(SyncError::Unsupported);
+    }
+    let root = fs::canonicalize(root).map_err(|err| SyncError::Io(err.to_string()))?;
+
+    let mut skipped = Vec::new();
+    let mut unsupported = Vec::new();
+    let mut xattr_tags = collect_xattr_tags(&root, &mut skipped, &mut unsupported);
+
+    let storage = database.storage();
+    let mut snapshot = get_tags_for_directory(storage, &root, usize::MAX)
+        .map_err(|err| SyncError::Database(err.to_string()))?;
+    let rules = storage
+        .path_matching_rules()
+        .map_err(|err| SyncError::Database(err.to_string()))?;
+    snapshot.align_to(&rules, &xattr_tags.keys().cloned().collect::<Vec<_>>());
+    let mut database_tags = snapshot.direct_tags;
+    let mut baseline = load_baseline(storage, &root)?;
+    let candidates: BTreeSet<PathBuf> = database_tags
+        .keys()
+        .chain(baseline.keys())
+        .cloned()
+        .collect();
+    let readable = read_missing_xattrs(candidates, &mut xattr_tags, &mut skipped, &unsupported);
+    database_tags.retain(|path, _| readable.contains(path));
+    baseline.retain(|path, _| readable.contains(path));
+
+    let mut changes = plan_changes(&database_tags, &xattr_tags, &baseline, policy);
+    if !dry_run {
+        let planned: BTreeSet<PathBuf> = changes.iter().map(|change| change.path.clone()).collect();
+        changes = write_xattrs(changes, &mut skipped);
+        let settled = settled_tags(&database_tags, &xattr_tags, &baseline, &planned, &changes);
+
+        write_database(database, &changes, &baseline, &settled)?;
+        info!(
+            "Synced xattr tags for {} paths under {:?}",
+            changes.len(),
+            root
+        );
+    }
+
+    Ok(SyncReport {
+        dry_run,
+        policy,
+        changes,
+        skipped,
+    })
+}
+
+// Tags stored in xattrs on `root` and its descendants. Directories on
+// filesystems without xattr support are reported once and not descended into.
+fn collect_xattr_tags(
+    root: &Path,
+    skipped: &mut Vec<SkippedEntry>,
+    unsupported: &mut Vec<PathBuf>,
+) -> BTreeMap<PathBuf, Vec<String>> {
+    let mut tags_by_path = BTreeMap::new();
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(path) = stack.pop() {
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                skipped.push(SkippedEntry::from_io(&path, &err));
+                continue;
+            }
+        };
+        if metadata.file_type().is_symlink() {
+            continue;
+        }
+
+        match xdg_tags::read_tags(&path) {
+            Ok(tags) if tags.is_empty() => {}
+            Ok(tags) => {
+                tags_by_path.insert(path.clone(), tags);
+            }
+            Err(err) if is_unsupported(&err) => {
+                debug!("No xattr support at {:?}: {}", path, err);
+                skipped.push(unsupported_entry(&path, &err));
+                unsupported.push(path);
+                continue;
+            }
+            Err(err) => skipped.push(SkippedEntry::from_io(&path, &err)),
+        }
+
+        if !metadata.is_dir() {
+            continue;
+        }
+        match fs::read_dir(&path) {
+            Ok(entries) => {
+                for entry in entries {
+                    match entry {
+                        Ok(entry) => stack.push(entry.path()),
+                        Err(err) => skipped.push(SkippedEntry::from_io(&path, &err)),
+                    }
+                }
+            }
+            Err(err) => skipped.push(SkippedEntry::from_io(&path, &err)),
+        }
+    }
+
+    tags_by_path
+}
+
+// Tags each path under `root` held on both sides after its last sync
+fn load_baseline(
+    storage: &dyn TagStorage,
+    root: &Path,
+) -> Result<BTreeMap<PathBuf, Vec<String>>, SyncError> {
+    let rows = storage
+        .sync_baseline_rows(&encode_path(root))
+        .map_err(|err| SyncError::Database(err.to_string()))?;
+
+    let mut baseline: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    for (path, tag) in rows {
+        let path = decode_path(&path);
+        if path.starts_with(root) {
+            baseline.entry(path).or_default().push(tag);
+        }
+    }
+    Ok(baseline)
+}
+
+// Database and baseline paths the walk did not reach, e.g. behind a symlink
+// or since deleted, have their attributes read individually. Returns the
+// paths whose attributes are known; the others are dropped from the sync.
+fn read_missing_xattrs(
+    paths: BTreeSet<PathBuf>,
+    xattr_tags: &mut BTreeMap<PathBuf, Vec<String>>,
+    skipped: &mut Vec<SkippedEntry>,
+    unsupported: &[PathBuf],
+) -> BTreeSet<PathBuf> {
+    let mut readable = BTreeSet::new();
+
+    for path in paths {
+        if unsupported.iter().any(|dir| path.starts_with(dir)) {
+            continue;
+        }
+        if !xattr_tags.contains_key(&path) {
+            match xdg_tags::read_tags(&path) {
+                Ok(found) if found.is_empty() => {}
+                Ok(found) => {
+                    xattr_tags.insert(path.clone(), found);
+                }
+                Err(err) if is_unsupported(&err) => {
+                    skipped.push(unsupported_entry(&path, &err));
+                    continue;
+                }
+                Err(err) => {
+                    skipped.push(SkippedEntry::from_io(&path, &err));
+                    continue;
+                }
+            }
+        }
+        readable.insert(path);
+    }
+
+    readable
+}
+
+// Tags as `user.xdg.tags` can hold them, so a database tag with a comma
+// equals its attribute copy
+fn xattr_forms(tags: &[String]) -> BTreeSet<String> {
+    tags.iter()
+        .map(|tag| xdg_tags::format_tag(tag))
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+pub(crate) fn plan_changes(
+    database_tags: &BTreeMap<PathBuf, Vec<String>>,
+    xattr_tags: &BTreeMap<PathBuf, Vec<String>>,
+    baseline: &BTreeMap<PathBuf, Vec<String>>,
+    policy: ConflictPolicy,
+) -> Vec<SyncChange> {
+    let paths: BTreeSet<&PathBuf> = database_tags
+        .keys()
+        .chain(xattr_tags.keys())
+        .chain(baseline.keys())
+        .collect();
+    let mut changes = Vec::new();
+
+    for path in paths {
+        let database = database_tags.get(path).cloned().unwrap_or_default();
+        let xattr = xattr_tags.get(path).cloned().unwrap_or_default();
+        let database_set = xattr_forms(&database);
+        let xattr_set = xattr_forms(&xattr);
+        if database_set == xattr_set {
+            continue;
+        }
+        let baseline_set = xattr_forms(baseline.get(path).map(Vec::as_slice).unwrap_or_default());
+
+        let (resolved_tags, direction) = if xattr_set == baseline_set {
+            (database.clone(), SyncDirection::ToXattr)
+        } else if database_set == baseline_set {
+            (xattr.clone(), SyncDirection::ToDatabase)
+        } else {
+            match policy {
+                ConflictPolicy::DatabaseWins => (database.clone(), SyncDirection::ToXattr),
+                ConflictPolicy::XattrWins => (xattr.clone(), SyncDirection::ToDatabase),
+                ConflictPolicy::Union => {
+                    let mut union = database.clone();
+                    union.extend(
+                        xattr
+                            .iter()
+                            .filter(|tag| !database_set.contains(*tag))
+                            .cloned(),
+                    );
+                    let direction = if database_set.is_superset(&xattr_set) {
+                        SyncDirection::ToXattr
+                    } else if xattr_set.is_superset(&database_set) {
+                        SyncDirection::ToDatabase
+                    } else {
+                        SyncDirection::Both
+                    };
+                    (union, direction)
+                }
+            }
+        };
+
+        changes.push(SyncChange {
+            path: path.clone(),
+            database_tags: database,
+            xattr_tags: xattr,
+            resolved_tags,
+            direction,
+        });
+    }
+
+    changes
+}
+
+// What both sides hold once `applied` is written. Paths whose planned change
+// could not be written keep their old baseline.
+fn settled_tags(
+    database_tags: &BTreeMap<PathBuf, Vec<String>>,
+    xattr_tags: &BTreeMap<PathBuf, Vec<String>>,
+    baseline: &BTreeMap<PathBuf, Vec<String>>,
+    planned: &BTreeSet<PathBuf>,
+    applied: &[SyncChange],
+) -> BTreeMap<PathBuf, Vec<String>> {
+    let mut settled: BTreeMap<PathBuf, Vec<String>> = database_tags
+        .keys()
+        .chain(xattr_tags.keys())
+        .chain(baseline.keys())
+        .filter(|path| !planned.contains(*path))
+        .map(|path| {
+            let tags = database_tags.get(path).cloned().unwrap_or_default();
+            (path.clone(), tags)
+        })
+        .collect();
+    for change in applied {
+        settled.insert(change.path.clone(), change.resolved_tags.clone());
+    }
+    settled
+}
+
+// Attributes are written first so a path whose write fails keeps its database tags
+fn write_xattrs(changes: Vec<SyncChange>, skipped: &mut Vec<SkippedEntry>) -> Vec<SyncChange> {
+    changes
+        .into_iter()
+        .filter(|change| {
+            if !change.direction.writes_xattr() {
+                return true;
+            }
+            match xdg_tags::write_tags(&change.path, &change.resolved_tags) {
+                Ok(()) => true,
+                Err(err) if is_unsupported(&err) => {
+                    skipped.push(unsupported_entry(&change.path, &err));
+                    false
+                }
+                Err(err) => {
+                    skipped.push(SkippedEntry::from_io(&change.path, &err));
+                    false
+                }
+            }
+        })
+        .collect()
+}
+
+fn write_database(
+    database: &mut Database,
+    changes: &[SyncChange],
+    baseline: &BTreeMap<PathBuf, Vec<String>>,
+    settled: &BTreeMap<PathBuf, Vec<String>>,
+) -> Result<(), SyncError> {
+    database
+        .in_transaction(|storage| {
+            for change in changes
+                .iter()
+                .filter(|change| change.direction.writes_database())
+            {
+                replace_tags_for_path(storage, &change.path, &change.resolved_tags)?;
+            }
+            for (path, tags) in settled {
+                if baseline.get(path).map(Vec::as_slice).unwrap_or_default() != tags.as_slice() {
+                    storage.set_sync_baseline(&encode_path(path), tags)?;
+                }
+            }
+            Ok(())
+        })
+        .map_err(|err| SyncError::Database(err.to_string()))?;
+
+    let written: Vec<PathBuf> = changes
+        .iter()
+        .filter(|change| change.direction.writes_database())
+        .map(|change| change.path.clone())
+        .collect();
+    mirror_to_manifests(database, &written);
+    Ok(())
+}
+
+// `ENOTSUP` is not mapped to `io::ErrorKind::Unsupported` on every platform
+fn is_unsupported(err: &io::Error) -> bool {
+    if err.kind() == io::ErrorKind::Unsupported {
+        return true;
+    }
+
+    #[cfg(unix)]
+    if matches!(err.raw_os_error(), Some(code) if code == libc::ENOTSUP || code == libc::EOPNOTSUPP)
+    {
+        return true;
+    }
+
+    false
+}
+
+fn unsupported_entry(path: &Path, err: &io::Error) -> SkippedEntry {
+    SkippedEntry {
+        kind: SkipKind::Unsupported,
+        ..SkippedEntry::from_io(path, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tagging::{ensure_schema, get_tags_for_paths};
+
+    fn tags(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn plans_one_sided_copies_and_resolves_conflicts_by_policy() {
+        let only_db = PathBuf::from("/data/only-db");
+        let only_xattr = PathBuf::from("/data/only-xattr");
+        let same = PathBuf::from("/data/same");
+        let conflict = PathBuf::from("/data/conflict");
+        let database = BTreeMap::from([
+            (only_db.clone(), tags(&["a"])),
+            (same.clone(), tags(&["x", "y"])),
+            (conflict.clone(), tags(&["a", "b"])),
+        ]);
+        let xattrs = BTreeMap::from([
+            (only_xattr.clone(), tags(&["c"])),
+            (same.clone(), tags(&["y", "x"])),
+            (conflict.clone(), tags(&["b", "c"])),
+        ]);
+
+        let plan = |policy| {
+            plan_changes(&database, &xattrs, &BTreeMap::new(), policy)
+                .into_iter()
+                .map(|change| (change.path, change.resolved_tags, change.direction))
+                .collect::<Vec<_>>()
+        };
+
+        let database_wins = plan(ConflictPolicy::DatabaseWins);
+        assert_eq!(
+            database_wins,
+            vec![
+                (conflict.clone(), tags(&["a", "b"]), SyncDirection::ToXattr),
+                (only_db.clone(), tags(&["a"]), SyncDirection::ToXattr),
+                (only_xattr.clone(), tags(&["c"]), SyncDirection::ToDatabase),
+            ]
+        );
+
+        let xattr_wins = plan(ConflictPolicy::XattrWins);
+        assert_eq!(
+            xattr_wins[0],
+            (
+                conflict.clone(),
+                tags(&["b", "c"]),
+                SyncDirection::ToDatabase
+            )
+        );
+
+        let union = plan(ConflictPolicy::Union);
+        assert_eq!(
+            union[0],
+            (conflict, tags(&["a", "b", "c"]), SyncDirection::Both)
+        );
+    }
+
+    #[test]
+    fn union_only_writes_the_side_that_is_missing_tags() {
+        let path = PathBuf::from("/data/file");
+        let database = BTreeMap::from([(path.clone(), tags(&["a", "b"]))]);
+        let xattrs = BTreeMap::from([(path, tags(&["b"]))]);
+
+        let changes = plan_changes(&database, &xattrs, &BTreeMap::new(), ConflictPolicy::Union);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].direction, SyncDirection::ToXattr);
+        assert_eq!(changes[0].resolved_tags, tags(&["a", "b"]));
+    }
+
+    #[test]
+    fn removals_since_the_last_sync_win_over_the_unchanged_side() {
+        let removed_in_db = PathBuf::from("/data/removed-in-db");
+        let removed_in_xattr = PathBuf::from("/data/removed-in-xattr");
+        let database = BTreeMap::from([(removed_in_xattr.clone(), tags(&["a"]))]);
+        let xattrs = BTreeMap::from([(removed_in_db.clone(), tags(&["b"]))]);
+        let baseline = BTreeMap::from([
+            (removed_in_db.clone(), tags(&["b"])),
+            (removed_in_xattr.clone(), tags(&["a"])),
+        ]);
+
+        for policy in [
+            ConflictPolicy::DatabaseWins,
+            ConflictPolicy::XattrWins,
+            ConflictPolicy::Union,
+        ] {
+            let plan = plan_changes(&database, &xattrs, &baseline, policy)
+                .into_iter()
+                .map(|change| (change.path, change.resolved_tags, change.direction))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                plan,
+                vec![
+                    (removed_in_db.clone(), Vec::new(), SyncDirection::ToXattr),
+                    (
+                        removed_in_xattr.clone(),
+                        Vec::new(),
+                        SyncDirection::ToDatabase
+                    ),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn compares_database_tags_in_their_attribute_form() {
+        let path = PathBuf::from("/data/file");
+        let database = BTreeMap::from([(path.clone(), tags(&["a,b"]))]);
+        let xattrs = BTreeMap::from([(path, tags(&["a b"]))]);
+
+        let changes = plan_changes(
+            &database,
+            &xattrs,
+            &BTreeMap::new(),
+            ConflictPolicy::DatabaseWins,
+        );
+        assert!(changes.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn syncs_tags_in_both_directions() {
+        let temp = tempfile::tempdir().expect("temp dir");
+        let root = fs::canonicalize(temp.path()).expect("canonical root");
+        let from_db = root.join("from-db.txt");
+        let from_xattr = root.join("nested").join("from-xattr.txt");
+        fs::create_dir_all(root.join("nested")).expect("create nested");
+        fs::write(&from_db, "data").expect("write");
+        fs::write(&from_xattr, "data").expect("write");
+        // tmpfs and some sandboxes reject user xattrs; nothing to sync there
+        if xdg_tags::write_tags(&from_xattr, &tags(&["blue"])).is_err() {
+            return;
+        }
+
+        let connection = duckdb::Connection::open_in_memory().expect("in memory db");
+        ensure_schema(&connection).expect("schema");
+        replace_tags_for_path(&connection, &from_db, &tags(&["red"])).expect("seed tags");
+        let mut database = Database::DuckDb(connection);
+
+        let dry_run = sync_xattr_tags(&mut database, &root, ConflictPolicy::DatabaseWins, true)
+            .expect("dry run");
+        assert_eq!(dry_run.changes.len(), 2);
+        assert!(xdg_tags::read_tags(&from_db).expect("read").is_empty());
+
+        let report = sync_xattr_tags(&mut database, &root, ConflictPolicy::DatabaseWins, false)
+            .expect("sync");
+        assert_eq!(report.changes.len(), 2);
+        assert!(report.skipped.is_empty());
+        assert_eq!(xdg_tags::read_tags(&from_db).expect("read"), tags(&["red"]));
+
+        let stored = get_tags_for_paths(database.storage(), std::slice::from_ref(&from_xattr))
+            .expect("fetch tags");
+        assert_eq!(stored.get(&from_xattr), Some(&tags(&["blue"])));
+
+        let again = sync_xattr_tags(&mut database, &root, ConflictPolicy::DatabaseWins, true)
+            .expect("dry run");
+        assert!(again.changes.is_empty());
+
+        // Removals on either side reach the other one on the next sync
+        xdg_tags::write_tags(&from_xattr, &[]).expect("clear xattr");
+        replace_tags_for_path(database.storage(), &from_db, &[]).expect("clear tags");
+        let report = sync_xattr_tags(&mut database, &root, ConflictPolicy::DatabaseWins, false)
+            .expect("sync");
+        assert_eq!(report.changes.len(), 2);
+        assert!(xdg_tags::read_tags(&from_db).expect("read").is_empty());
+
+        let stored = get_tags_for_paths(database.storage(), std::slice::from_ref(&from_xattr))
+            .expect("fetch tags");
+        assert!(stored
+            .get(&from_xattr)
+            .cloned()
+            .unwrap_or_default()
+            .is_empty());
+
+        let settled = sync_xattr_tags(&mut database, &root, ConflictPolicy::DatabaseWins, true)
+            .expect("dry run");
+        assert!(settled.changes.is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use log::debug;
+use std::io;
+use std::path::Path;
+
+// Attribute used by Dolphin/Baloo and other freedesktop tools: a UTF-8,
+// comma-separated list of tag names
+pub(crate) const XDG_TAGS_ATTRIBUTE: &str = "user.xdg.tags";
+
+#[cfg(unix)]
+pub(crate) const XATTRS_SUPPORTED: bool = xattr::SUPPORTED_PLATFORM;
+#[cfg(not(unix))]
+pub(crate) const XATTRS_SUPPORTED: bool = false;
+
+pub(crate) fn parse_tags(raw: &[u8]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in String::from_utf8_lossy(raw).split(',') {
+        let tag = tag.trim();
+        if !tag.is_empty() && !tags.iter().any(|existing| existing == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+// Commas would split a tag in two, so they are stored as spaces
+pub(crate) fn format_tag(tag: &str) -> String {
+    tag.replace(',', " ").trim().to_string()
+}
+
+pub(crate) fn format_tags(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| format_tag(tag))
+        .filter(|tag| !tag.is_empty())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Tags of the entry, or of the link target for symlinks. A missing attribute
+// reads as no tags.
+#[cfg(unix)]
+pub(crate) fn read_tags(path: &Path) -> io::Result<Vec<String>> {
+    Ok(xattr::get_deref(path, XDG_TAGS_ATTRIBUTE)?
+        .map(|raw| parse_tags(&raw))
+        .unwrap_or_default())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn read_tags(_path: &Path) -> io::Result<Vec<String>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+// Replaces the attribute; an empty list removes it altogether
+#[cfg(unix)]
+pub(crate) fn write_tags(path: &Path, tags: &[String]) -> io::Result<()> {
+    let value = format_tags(tags);
+    if !value.is_empty() {
+        return xattr::set_deref(path, XDG_TAGS_ATTRIBUTE, value.as_bytes());
+    }
+
+    if xattr::get_deref(path, XDG_TAGS_ATTRIBUTE)?.is_none() {
+        return Ok(());
+    }
+    xattr::remove_deref(path, XDG_TAGS_ATTRIBUTE)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn write_tags(_path: &Path, _tags: &[String]) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+// Like `read_tags`, but for scans, where filesystems without xattr support
+// and unreadable attributes simply yield no tags
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn read_tags_lenient(path: &Path) -> Vec<String> {
+    read_tags(path).unwrap_or_else(|err| {
+        debug!(
+            "Could not read {} on {:?}: {}",
+            XDG_TAGS_ATTRIBUTE, path, err
+        );
+        Vec::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_comma_separated_tags() {
+        assert_eq!(
+            parse_tags(b" work ,photos,,work, 2024 "),
+            vec!["work".to_string(), "photos".to_string(), "2024".to_string()]
+        );
+        assert!(parse_tags(b"").is_empty());
+    }
+
+    #[test]
+    fn formats_tags_without_embedded_commas() {
+        let tags = vec!["a,b".to_string(), " ".to_string(), "c".to_string()];
+        assert_eq!(format_tags(&tags), "a b,c");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trips_tags_through_xattrs_when_supported() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let file = dir.path().join("tagged.txt");
+        std::fs::write(&file, "data").expect("write");
+
+        assert!(read_tags_lenient(&file).is_empty());
+        // tmpfs and some sandboxes reject user xattrs; nothing more to check there
+        if xattr::set(&file, XDG_TAGS_ATTRIBUTE, b"red,blue").is_err() {
+            return;
+        }
+        assert_eq!(
+            read_tags(&file).expect("read"),
+            vec!["red".to_string(), "blue".to_string()]
+        );
+
+        write_tags(&file, &["green".to_string()]).expect("write");
+        assert_eq!(read_tags(&file).expect("read"), vec!["green".to_string()]);
+        write_tags(&file, &[]).expect("remove");
+        write_tags(&file, &[]).expect("removing twice is fine");
+        assert!(read_tags(&file).expect("read").is_empty());
+    }
+}
@@ -0,0 +1,552 @@
+use crate::atomic_file::write_atomically;
+use crate::path_codec::serialize_path;
+use crate::scan::SkippedEntry;
+use crate::storage::TagStorage;
+use crate::tagging::{get_tags_for_paths, normalize_path};
+use crate::AccessError;
+use log::{debug, error, info};
+use roxmltree::{Document, Node};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+const RDF_NAMESPACE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "type", content = "message")]
+pub enum SidecarError {
+    #[error("No paths were provided")]
+    EmptyPaths,
+
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+
+    #[error(transparent)]
+    Access(#[from] AccessError),
+
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SidecarWrite {
+    #[serde(serialize_with = "serialize_path")]
+    path: PathBuf,
+    #[serde(serialize_with = "serialize_path")]
+    sidecar: PathBuf,
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SidecarReport {
+    written: Vec<SidecarWrite>,
+    skipped: Vec<SkippedEntry>,
+}
+
+// Stems that several files in one directory have in common. Their Adobe-style
+// `stem.xmp` sidecar, such as the one next to a raw `photo.cr2` and its
+// `photo.jpg`, belongs to none of them.
+pub(crate) struct SharedStems {
+    stems: HashSet<OsString>,
+    unlisted: bool,
+}
+
+impl SharedStems {
+    // From a complete directory listing
+    pub(crate) fn from_listing<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut seen = HashSet::new();
+        let mut stems = HashSet::new();
+        for stem in paths
+            .into_iter()
+            .filter(|path| !is_sidecar(path))
+            .filter_map(Path::file_stem)
+        {
+            if !seen.insert(stem) {
+                stems.insert(stem.to_os_string());
+            }
+        }
+        Self {
+            stems,
+            unlisted: false,
+        }
+    }
+
+    // Lists the directory holding `path`. When that fails, every stem counts
+    // as shared.
+    pub(crate) fn next_to(path: &Path) -> Self {
+        let listing = path
+            .parent()
+            .and_then(|directory| fs::read_dir(directory).ok());
+        let Some(entries) = listing else {
+            return Self {
+                stems: HashSet::new(),
+                unlisted: true,
+            };
+        };
+        let paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        Self::from_listing(paths.iter().map(PathBuf::as_path))
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.unlisted
+            || path
+                .file_stem()
+                .is_none_or(|stem| self.stems.contains(stem))
+    }
+}
+
+// Sidecar already describing `path`: darktable's `photo.jpg.xmp` or
+// Adobe's `photo.xmp`. The latter is only taken while no other file in the
+// directory shares the stem.
+pub(crate) fn find_sidecar(path: &Path, shared: &SharedStems) -> Option<PathBuf> {
+    if is_sidecar(path) {
+        return None;
+    }
+    let appended = appended_sidecar(path);
+    if appended.is_file() {
+        return Some(appended);
+    }
+    let adobe = path.with_extension("xmp");
+    (adobe.is_file() && !shared.contains(path)).then_some(adobe)
+}
+
+// `photo.jpg.xmp`, which belongs to `photo.jpg` alone
+fn appended_sidecar(path: &Path) -> PathBuf {
+    let mut appended = path.as_os_str().to_os_string();
+    appended.push(".xmp");
+    PathBuf::from(appended)
+}
+
+fn is_sidecar(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("xmp"))
+}
+
+// Keywords in the `dc:subject` bag, in document order and without duplicates
+pub(crate) fn parse_subjects(xmp: &str) -> Result<Vec<String>, roxmltree::Error> {
+    let document = Document::parse(xmp)?;
+    let mut tags: Vec<String> = Vec::new();
+    for subject in document
+        .descendants()
+        .filter(|node| node.has_tag_name((DC_NAMESPACE, "subject")))
+    {
+        for item in subject
+            .descendants()
+            .filter(|node| node.has_tag_name((RDF_NAMESPACE, "li")))
+        {
+            let tag = item.text().unwrap_or_default().trim();
+            if !tag.is_empty() && !tags.iter().any(|existing| existing == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+    }
+    Ok(tags)
+}
+
+// Tags from the sidecar of a file, for scans. A missing or unreadable
+// sidecar yields no tags.
+pub(crate) fn read_tags_lenient(path: &Path, shared: &SharedStems) -> Vec<String> {
+    let Some(sidecar) = find_sidecar(path, shared) else {
+        return Vec::new();
+    };
+    let parsed = fs::read_to_string(&sidecar)
+        .map_err(|err| err.to_string())
+        .and_then(|xmp| parse_subjects(&xmp).map_err(|err| err.to_string()));
+    parsed.unwrap_or_else(|err| {
+        debug!("Could not read XMP sidecar {:?}: {}", sidecar, err);
+        Vec::new()
+    })
+}
+
+// Replaces the `dc:subject` bag of an XMP packet, leaving everything else as
+// it was. An empty list removes the bag.
+pub(crate) fn update_subjects(xmp: &str, tags: &[String]) -> Result<String, String> {
+    let document = Document::parse(xmp).map_err(|err| err.to_string())?;
+
+    if let Some(subject) = document
+        .descendants()
+        .find(|node| node.has_tag_name((DC_NAMESPACE, "subject")))
+    {
+        let range = subject.range();
+        let replacement = if tags.is_empty() {
+            String::new()
+        } else {
+            render_subject(
+                &qualified(subject.lookup_prefix(DC_NAMESPACE), "subject"),
+                subject.lookup_prefix(RDF_NAMESPACE).unwrap_or("rdf"),
+                tags,
+            )
+        };
+        return Ok(format!(
+            "{}{replacement}{}",
+            &xmp[..range.start],
+            &xmp[range.end..]
+        ));
+    }
+    if tags.is_empty() {
+        return Ok(xmp.to_string());
+    }
+
+    let description = document
+        .descendants()
+        .find(|node| node.has_tag_name((RDF_NAMESPACE, "Description")))
+        .ok_or("no rdf:Description element")?;
+    // Declares the Dublin Core prefix on the description when nothing has yet
+    let (dc, declaration) = match description.lookup_prefix(DC_NAMESPACE) {
+        Some(prefix) => (Some(prefix), String::new()),
+        None => (Some("dc"), format!(" xmlns:dc=\"{DC_NAMESPACE}\"")),
+    };
+    let rdf = description.lookup_prefix(RDF_NAMESPACE).unwrap_or("rdf");
+    let subject = render_subject(&qualified(dc, "subject"), rdf, tags);
+    insert_child(xmp, description, &declaration, &subject)
+}
+
+// Adds `child` as the last child of `element`, and `attributes` to its start tag
+fn insert_child(xmp: &str, element: Node, attributes: &str, child: &str) -> Result<String, String> {
+    let range = element.range();
+    let source = &xmp[range.clone()];
+    let name_end = source
+        .find(|ch: char| ch.is_whitespace() || ch == '>' || ch == '/')
+        .ok_or("malformed start tag")?;
+    let name = &source[1..name_end];
+    let start = range.start + name_end;
+
+    if source.ends_with("/>") {
+        let end = range.end - 2;
+        return Ok(format!(
+            "{}{attributes}{}>{child}</{name}>{}",
+            &xmp[..start],
+            xmp[start..end].trim_end(),
+            &xmp[range.end..]
+        ));
+    }
+    let close = range.start + source.rfind("</").ok_or("malformed end tag")?;
+    Ok(format!(
+        "{}{attributes}{}{child}{}",
+        &xmp[..start],
+        &xmp[start..close],
+        &xmp[close..]
+    ))
+}
+
+fn qualified(prefix: Option<&str>, name: &str) -> String {
+    match prefix {
+        Some(prefix) if !prefix.is_empty() => format!("{prefix}:{name}"),
+        _ => name.to_string(),
+    }
+}
+
+fn render_subject(subject: &str, rdf: &str, tags: &[String]) -> String {
+    let items: String = tags
+        .iter()
+        .map(|tag| format!("<{rdf}:li>{}</{rdf}:li>", escape_text(tag)))
+        .collect();
+    format!("<{subject}><{rdf}:Bag>{items}</{rdf}:Bag></{subject}>")
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn new_sidecar(tags: &[String]) -> String {
+    format!(
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"{RDF_NAMESPACE}\">\n\
+         <rdf:Description rdf:about=\"\" xmlns:dc=\"{DC_NAMESPACE}\">{}</rdf:Description>\n\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n",
+        render_subject("dc:subject", "rdf", tags)
+    )
+}
+
+// Writes `tags` into the sidecar of `path`, creating `photo.jpg.xmp` when
+// there is none. Returns the sidecar, or `None` when there was nothing to
+// write.
+pub(crate) fn write_tags(
+    path: &Path,
+    tags: &[String],
+    shared: &SharedStems,
+) -> io::Result<Option<PathBuf>> {
+    if is_sidecar(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "XMP files do not have sidecars",
+        ));
+    }
+
+    let Some(sidecar) = find_sidecar(path, shared) else {
+        if tags.is_empty() {
+            return Ok(None);
+        }
+        let sidecar = appended_sidecar(path);
+        write_atomically(&sidecar, new_sidecar(tags))?;
+        return Ok(Some(sidecar));
+    };
+
+    let current = fs::read_to_string(&sidecar)?;
+    let updated = update_subjects(&current, tags)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if updated != current {
+        write_atomically(&sidecar, updated)?;
+    }
+    Ok(Some(sidecar))
+}
+
+// Writes the direct tags of each path into its XMP sidecar's `dc:subject`
+// bag. Sidecars of paths without tags are left alone, unless
+// `clear_untagged` asks for their bag to be removed.
+pub fn write_xmp_sidecars(
+    storage: &dyn TagStorage,
+    paths: &[PathBuf],
+    clear_untagged: bool,
+) -> Result<SidecarReport, SidecarError> {
+    if paths.is_empty() {
+        return Err(SidecarError::EmptyPaths);
+    }
+    let paths = paths
+        .iter()
+        .map(|path| normalize_path(path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| SidecarError::InvalidPath(err.to_string()))?;
+
+    let mut tags_by_path = get_tags_for_paths(storage, &paths)
+        .map_err(|err| SidecarError::Database(err.to_string()))?;
+
+    let report = write_sidecars(paths, |path| match tags_by_path.remove(path) {
+        Some(tags) => Some(tags),
+        None => clear_untagged.then(Vec::new),
+    });
+    info!("Wrote {} XMP sidecars", report.written.len());
+    Ok(report)
+}
+
+// `tags_for` returns `None` for paths whose sidecar should not be touched
+fn write_sidecars(
+    paths: Vec<PathBuf>,
+    mut tags_for: impl FnMut(&Path) -> Option<Vec<String>>,
+) -> SidecarReport {
+    let mut written = Vec::new();
+    let mut skipped = Vec::new();
+    // Paths are often siblings, so each directory is listed once
+    let mut shared_by_directory: HashMap<PathBuf, SharedStems> = HashMap::new();
+    for path in paths {
+        let Some(tags) = tags_for(&path) else {
+            continue;
+        };
+        let result = if path.is_file() {
+            let directory = path.parent().unwrap_or(&path).to_path_buf();
+            let shared = shared_by_directory
+                .entry(directory)
+                .or_insert_with(|| SharedStems::next_to(&path));
+            write_tags(&path, &tags, shared)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "only files have XMP sidecars",
+            ))
+        };
+        match result {
+            Ok(Some(sidecar)) => written.push(SidecarWrite {
+                path,
+                sidecar,
+                tags,
+            }),
+            Ok(None) => {}
+            Err(err) => {
+                error!("Failed to write XMP sidecar for {:?}: {}", path, err);
+                skipped.push(SkippedEntry::from_io(&path, &err));
+            }
+        }
+    }
+    SidecarReport { written, skipped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Database, StorageBackend};
+    use crate::tagging::assign_tag_to_paths;
+
+    const LIGHTROOM_SIDECAR: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmp:Rating="4">
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>beach</rdf:li>
+     <rdf:li>Tom &amp; Jerry</rdf:li>
+     <rdf:li>beach</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+   <dc:creator><rdf:Seq><rdf:li>Ana</rdf:li></rdf:Seq></dc:creator>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn replaces_only_the_subject_bag() {
+        assert_eq!(
+            parse_subjects(LIGHTROOM_SIDECAR).unwrap(),
+            strings(&["beach", "Tom & Jerry"])
+        );
+
+        let updated = update_subjects(LIGHTROOM_SIDECAR, &strings(&["sea", "<b>"])).unwrap();
+        assert_eq!(parse_subjects(&updated).unwrap(), strings(&["sea", "<b>"]));
+        assert!(updated.contains("xmp:Rating=\"4\""));
+        assert!(
+            updated.contains("<dc:creator><rdf:Seq><rdf:li>Ana</rdf:li></rdf:Seq></dc:creator>")
+        );
+
+        let removed = update_subjects(&updated, &[]).unwrap();
+        assert!(parse_subjects(&removed).unwrap().is_empty());
+        assert!(removed.contains("<dc:creator>"));
+
+        // A description without Dublin Core gains the namespace and the bag
+        let bare = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:Rating="2"/></rdf:RDF></x:xmpmeta>"#;
+        let updated = update_subjects(bare, &strings(&["red"])).unwrap();
+        assert_eq!(parse_subjects(&updated).unwrap(), strings(&["red"]));
+        assert!(updated.contains("xmp:Rating=\"2\""));
+
+        assert!(update_subjects("<not-closed>", &strings(&["red"])).is_err());
+    }
+
+    #[test]
+    fn finds_creates_and_updates_sidecars() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let raw = dir.path().join("photo.cr2");
+        let jpeg = dir.path().join("photo.jpg");
+        for file in [&raw, &jpeg] {
+            fs::write(file, "image").expect("write");
+        }
+        fs::write(dir.path().join("photo.jpg.xmp"), LIGHTROOM_SIDECAR).expect("write");
+
+        let shared = SharedStems::next_to(&jpeg);
+        assert_eq!(
+            read_tags_lenient(&jpeg, &shared),
+            strings(&["beach", "Tom & Jerry"])
+        );
+        assert!(read_tags_lenient(&raw, &shared).is_empty());
+        assert!(read_tags_lenient(&dir.path().join("photo.jpg.xmp"), &shared).is_empty());
+
+        let report = write_sidecars(
+            vec![jpeg.clone(), raw.clone(), dir.path().join("gone.jpg")],
+            |path| {
+                if path == jpeg {
+                    Some(strings(&["sea"]))
+                } else {
+                    Some(strings(&["raw"]))
+                }
+            },
+        );
+        assert_eq!(
+            report
+                .written
+                .iter()
+                .map(|write| write.sidecar.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                dir.path().join("photo.jpg.xmp"),
+                dir.path().join("photo.cr2.xmp")
+            ]
+        );
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(read_tags_lenient(&jpeg, &shared), strings(&["sea"]));
+        assert_eq!(read_tags_lenient(&raw, &shared), strings(&["raw"]));
+
+        // Without tags, no sidecar is created
+        let plain = dir.path().join("plain.png");
+        fs::write(&plain, "image").expect("write");
+        assert_eq!(
+            write_tags(&plain, &[], &SharedStems::next_to(&plain)).expect("write"),
+            None
+        );
+        assert!(!dir.path().join("plain.png.xmp").exists());
+    }
+
+    #[test]
+    fn uses_adobe_sidecars_only_when_no_sibling_shares_them() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let scan = dir.path().join("scan.tif");
+        fs::write(&scan, "image").expect("write");
+        fs::write(dir.path().join("scan.xmp"), LIGHTROOM_SIDECAR).expect("write");
+
+        let shared = SharedStems::next_to(&scan);
+        assert_eq!(
+            read_tags_lenient(&scan, &shared),
+            strings(&["beach", "Tom & Jerry"])
+        );
+        assert_eq!(
+            write_tags(&scan, &strings(&["sea"]), &shared).expect("write"),
+            Some(dir.path().join("scan.xmp"))
+        );
+
+        // A raw and a JPEG of the same shot share `photo.xmp`, so neither
+        // reads it nor writes over it
+        let raw = dir.path().join("photo.cr2");
+        let jpeg = dir.path().join("photo.jpg");
+        for file in [&raw, &jpeg] {
+            fs::write(file, "image").expect("write");
+        }
+        fs::write(dir.path().join("photo.xmp"), LIGHTROOM_SIDECAR).expect("write");
+
+        let shared = SharedStems::next_to(&raw);
+        assert!(read_tags_lenient(&raw, &shared).is_empty());
+        assert_eq!(
+            write_tags(&jpeg, &strings(&["sea"]), &shared).expect("write"),
+            Some(dir.path().join("photo.jpg.xmp"))
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("photo.xmp")).expect("read"),
+            LIGHTROOM_SIDECAR
+        );
+    }
+
+    #[test]
+    fn leaves_sidecars_of_untagged_files_alone_unless_asked() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let tagged = dir.path().join("tagged.jpg");
+        let untagged = dir.path().join("untagged.jpg");
+        for file in [&tagged, &untagged] {
+            fs::write(file, "image").expect("write");
+        }
+        let sidecar = dir.path().join("untagged.jpg.xmp");
+        fs::write(&sidecar, LIGHTROOM_SIDECAR).expect("write");
+
+        let mut database = Database::open_in_memory(StorageBackend::DuckDb).expect("in memory db");
+        assign_tag_to_paths(&mut database, std::slice::from_ref(&tagged), "sea").expect("tag");
+
+        let report = write_xmp_sidecars(
+            database.storage(),
+            &[tagged.clone(), untagged.clone()],
+            false,
+        )
+        .expect("write");
+        assert_eq!(report.written.len(), 1);
+        assert_eq!(report.written[0].path, tagged);
+        assert_eq!(
+            fs::read_to_string(&sidecar).expect("read"),
+            LIGHTROOM_SIDECAR
+        );
+
+        let report = write_xmp_sidecars(database.storage(), std::slice::from_ref(&untagged), true)
+            .expect("write");
+        assert_eq!(report.written.len(), 1);
+        let cleared = fs::read_to_string(&sidecar).expect("read");
+        assert!(parse_subjects(&cleared).unwrap().is_empty());
+        assert!(cleared.contains("xmp:Rating=\"4\""));
+    }
+}
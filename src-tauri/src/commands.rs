@@ -0,0 +1,326 @@
+// Tauri commands. Each decodes its arguments, takes the shared connection and
+// hands over to `tag_crucible_core`, where the actual work happens.
+use log::error;
+use std::env;
+use std::path::PathBuf;
+use tag_crucible_core::path_codec::decode_path;
+use tag_crucible_core::path_matching::{self, PathMatching, PathMatchingRoot};
+use tag_crucible_core::saved_searches::{self, SavedSearch, SavedSearchError};
+use tag_crucible_core::scan::{
+    self, DirectoryNode, FacetFilter, FileInfo, FilterResult, ScanError, ScanOptions, ScanResult,
+    SearchOptions, SortOptions,
+};
+use tag_crucible_core::settings::{
+    self, Settings, SettingsError, SettingsFile, StorageBackendInfo,
+};
+use tag_crucible_core::sql_console::{self, QueryLimits, QueryResult};
+use tag_crucible_core::tag_manifest::{self, ManifestReport, ManifestRoot};
+use tag_crucible_core::tag_transfer::{
+    self, ExportFilter, ExportSummary, ImportStrategy, ImportSummary, PrefixRewrite, TagFileFormat,
+    TransferError,
+};
+use tag_crucible_core::tagging::{self, TaggingError};
+use tag_crucible_core::tmsu_import::{self, TmsuImportReport};
+use tag_crucible_core::xattr_sync::{self, ConflictPolicy, SyncError, SyncReport};
+use tag_crucible_core::xmp_sidecar::{self, SidecarError, SidecarReport};
+use tag_crucible_core::DbConnection;
+use tauri::State;
+
+// Generic directory scanning
+#[tauri::command]
+pub async fn scan_directory(
+    state: State<'_, DbConnection>,
+    path: String,
+    depth: usize,
+    options: Option<ScanOptions>,
+    sort: Option<SortOptions>,
+) -> Result<ScanResult, ScanError> {
+    let path = decode_path(&path);
+    let options = options.unwrap_or_default();
+    let sort = sort.unwrap_or_default();
+    scan::scan_directory(&*state, &path, depth, &options, &sort).map_err(|e| {
+        error!("Failed to scan directory at {:?}: {}", path, e);
+        e
+    })
+}
+
+// Scan current working directory
+#[tauri::command]
+pub async fn scan_current_directory(
+    state: State<'_, DbConnection>,
+    options: Option<ScanOptions>,
+    sort: Option<SortOptions>,
+) -> Result<ScanResult, ScanError> {
+    let options = options.unwrap_or_default();
+    let sort = sort.unwrap_or_default();
+    scan::scan_current_directory(&*state, &options, &sort).map_err(|e| {
+        error!(
+            "Failed to scan current directory at {:?}: {}",
+            env::current_dir().unwrap_or_default(),
+            e
+        );
+        e
+    })
+}
+
+#[tauri::command]
+pub async fn search_files(
+    state: State<'_, DbConnection>,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<Vec<FileInfo>, ScanError> {
+    let options = options.unwrap_or_default();
+    state
+        .with_duckdb(|connection| scan::search_files(connection, &query, &options))
+        .map_err(|e| {
+            error!("Failed to search for {:?}: {}", query, e);
+            e
+        })
+}
+
+#[tauri::command]
+pub async fn filter_entries(
+    state: State<'_, DbConnection>,
+    filter: Option<FacetFilter>,
+) -> Result<FilterResult, ScanError> {
+    let filter = filter.unwrap_or_default();
+    state
+        .with_duckdb(|connection| scan::filter_entries(connection, &filter))
+        .map_err(|e| {
+            error!("Failed to filter entries: {}", e);
+            e
+        })
+}
+
+#[tauri::command]
+pub fn assign_tag_to_paths(
+    state: State<'_, DbConnection>,
+    paths: Vec<String>,
+    tag: String,
+) -> Result<(), TaggingError> {
+    let paths: Vec<PathBuf> = paths.iter().map(|path| decode_path(path)).collect();
+    state.with_database(|database| tagging::assign_tag_to_paths(database, &paths, &tag))
+}
+
+#[tauri::command]
+pub fn set_path_matching(
+    state: State<'_, DbConnection>,
+    root: String,
+    matching: PathMatching,
+) -> Result<(), TaggingError> {
+    state.with_database(|database| {
+        path_matching::set_path_matching(database.storage(), &decode_path(&root), matching)
+    })
+}
+
+#[tauri::command]
+pub fn list_path_matching(
+    state: State<'_, DbConnection>,
+) -> Result<Vec<PathMatchingRoot>, TaggingError> {
+    state.with_database(|database| path_matching::list_path_matching(database.storage()))
+}
+
+#[tauri::command]
+pub async fn sync_xattr_tags(
+    state: State<'_, DbConnection>,
+    root: String,
+    policy: Option<ConflictPolicy>,
+    dry_run: bool,
+) -> Result<SyncReport, SyncError> {
+    let root = decode_path(&root);
+    let policy = policy.unwrap_or_default();
+    state
+        .with_database(|database| xattr_sync::sync_xattr_tags(database, &root, policy, dry_run))
+        .map_err(|e| {
+            error!("Failed to sync xattr tags under {:?}: {}", root, e);
+            e
+        })
+}
+
+#[tauri::command]
+pub fn create_saved_search(
+    state: State<'_, DbConnection>,
+    name: String,
+    query: String,
+) -> Result<SavedSearch, SavedSearchError> {
+    state.with_duckdb(|connection| saved_searches::create_saved_search(connection, &name, &query))
+}
+
+#[tauri::command]
+pub fn list_saved_searches(
+    state: State<'_, DbConnection>,
+) -> Result<Vec<SavedSearch>, SavedSearchError> {
+    state.with_duckdb(|connection| saved_searches::list_saved_searches(connection))
+}
+
+#[tauri::command]
+pub fn update_saved_search(
+    state: State<'_, DbConnection>,
+    name: String,
+    new_name: Option<String>,
+    query: Option<String>,
+) -> Result<SavedSearch, SavedSearchError> {
+    state.with_duckdb(|connection| {
+        saved_searches::update_saved_search(
+            connection,
+            &name,
+            new_name.as_deref(),
+            query.as_deref(),
+        )
+    })
+}
+
+#[tauri::command]
+pub fn delete_saved_search(
+    state: State<'_, DbConnection>,
+    name: String,
+) -> Result<(), SavedSearchError> {
+    state.with_duckdb(|connection| saved_searches::delete_saved_search(connection, &name))
+}
+
+#[tauri::command]
+pub fn evaluate_saved_search(
+    state: State<'_, DbConnection>,
+    name: String,
+    sort: Option<SortOptions>,
+) -> Result<DirectoryNode, SavedSearchError> {
+    let sort = sort.unwrap_or_default();
+    state.with_duckdb(|connection| saved_searches::evaluate_saved_search(connection, &name, &sort))
+}
+
+#[tauri::command]
+pub async fn run_readonly_query(
+    state: State<'_, DbConnection>,
+    sql: String,
+    limits: Option<QueryLimits>,
+) -> Result<QueryResult, TaggingError> {
+    state.with_duckdb(|connection| {
+        sql_console::run_readonly_query(connection, &sql, limits.unwrap_or_default())
+    })
+}
+
+#[tauri::command]
+pub fn export_tags(
+    state: State<'_, DbConnection>,
+    destination: String,
+    format: TagFileFormat,
+    filter: Option<ExportFilter>,
+) -> Result<ExportSummary, TransferError> {
+    state.with_duckdb(|connection| {
+        tag_transfer::export_tags(
+            connection,
+            &decode_path(&destination),
+            format,
+            &filter.unwrap_or_default(),
+        )
+    })
+}
+
+#[tauri::command]
+pub fn import_tags(
+    state: State<'_, DbConnection>,
+    source: String,
+    format: TagFileFormat,
+    strategy: Option<ImportStrategy>,
+    rewrite: Option<PrefixRewrite>,
+) -> Result<ImportSummary, TransferError> {
+    state.with_duckdb(|connection| {
+        tag_transfer::import_tags(
+            connection,
+            &decode_path(&source),
+            format,
+            strategy.unwrap_or_default(),
+            rewrite.as_ref(),
+        )
+    })
+}
+
+#[tauri::command]
+pub fn import_tmsu_database(
+    state: State<'_, DbConnection>,
+    database: String,
+    strategy: Option<ImportStrategy>,
+) -> Result<TmsuImportReport, TransferError> {
+    state.with_duckdb(|connection| {
+        tmsu_import::import_tmsu_database(
+            connection,
+            &decode_path(&database),
+            strategy.unwrap_or_default(),
+        )
+    })
+}
+
+#[tauri::command]
+pub async fn write_xmp_sidecars(
+    state: State<'_, DbConnection>,
+    paths: Vec<String>,
+    clear_untagged: bool,
+) -> Result<SidecarReport, SidecarError> {
+    let paths: Vec<PathBuf> = paths.iter().map(|path| decode_path(path)).collect();
+    state.with_database(|database| {
+        xmp_sidecar::write_xmp_sidecars(database.storage(), &paths, clear_untagged)
+    })
+}
+
+#[tauri::command]
+pub fn set_tag_manifests(
+    state: State<'_, DbConnection>,
+    root: String,
+    enabled: bool,
+) -> Result<(), TransferError> {
+    state.with_duckdb(|connection| {
+        tag_manifest::set_tag_manifests(connection, &decode_path(&root), enabled)
+    })
+}
+
+#[tauri::command]
+pub fn list_tag_manifest_roots(
+    state: State<'_, DbConnection>,
+) -> Result<Vec<ManifestRoot>, TransferError> {
+    state.with_duckdb(|connection| tag_manifest::list_tag_manifest_roots(connection))
+}
+
+#[tauri::command]
+pub fn export_tag_manifests(
+    state: State<'_, DbConnection>,
+    root: String,
+) -> Result<ManifestReport, TransferError> {
+    state.with_duckdb(|connection| {
+        tag_manifest::export_tag_manifests(connection, &decode_path(&root))
+    })
+}
+
+#[tauri::command]
+pub fn import_tag_manifests(
+    state: State<'_, DbConnection>,
+    root: String,
+    strategy: Option<ImportStrategy>,
+) -> Result<ImportSummary, TransferError> {
+    state.with_duckdb(|connection| {
+        tag_manifest::import_tag_manifests(
+            connection,
+            &decode_path(&root),
+            strategy.unwrap_or_default(),
+        )
+    })
+}
+
+#[tauri::command]
+pub fn get_settings(state: State<'_, SettingsFile>) -> Result<Settings, SettingsError> {
+    settings::get_settings(&state)
+}
+
+// A new storage backend takes over at once, holding a copy of the tags
+#[tauri::command]
+pub fn update_settings(
+    state: State<'_, SettingsFile>,
+    database: State<'_, DbConnection>,
+    settings: Settings,
+) -> Result<(), SettingsError> {
+    database.with_database(|database| settings::update_settings(&state, database, &settings))
+}
+
+#[tauri::command]
+pub fn list_storage_backends() -> Vec<StorageBackendInfo> {
+    settings::list_storage_backends()
+}
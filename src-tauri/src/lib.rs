@@ -1,27 +1,19 @@
-mod scan;
-mod tagging;
+mod commands;
 
-use log::info;
-use scan::{scan_current_directory, scan_directory};
+use log::{info, warn};
 use std::fs;
-use std::sync::Mutex;
+use tag_crucible_core::settings::{load_settings, Settings, SettingsFile};
+use tag_crucible_core::{Database, DbConnection};
 use tauri::Manager;
 
-use tagging::assign_tag_to_paths;
-
-pub(crate) struct DbConnection {
-    db: Mutex<Option<duckdb::Connection>>,
-}
-
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_log::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
-        .manage(DbConnection {
-            db: Default::default(),
-        })
+        .manage(DbConnection::default())
+        .manage(SettingsFile::default())
         .setup(|app| {
             let handle = app.handle();
 
@@ -35,27 +27,71 @@ pub fn run() {
                 format!("Failed to create database directory at {:?}: {}", db_dir, e)
             })?;
 
-            let db_path = db_dir.join("data.duckdb");
-            let conn = duckdb::Connection::open(&db_path)
-                .map_err(|e| format!("Failed to open DuckDB connection at {:?}: {}", db_path, e))?;
+            let settings_path = db_dir.join("settings.json");
+            // A broken settings file must not keep the app from starting
+            let settings = load_settings(&settings_path).unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load settings from {:?}, using the defaults: {}",
+                    settings_path, e
+                );
+                Settings::default()
+            });
+            *handle
+                .state::<SettingsFile>()
+                .path
+                .lock()
+                .map_err(|e| format!("Failed to acquire SettingsFile lock: {}", e))? =
+                Some(settings_path);
 
-            tagging::ensure_schema(&conn)
-                .map_err(|e| format!("Failed to initialize DuckDB schema: {}", e))?;
+            let backend = settings.storage_backend;
+            let db_path = db_dir.join(backend.file_name());
+            let database = Database::open(backend, &db_path).map_err(|e| {
+                format!(
+                    "Failed to open {:?} database at {:?}: {}",
+                    backend, db_path, e
+                )
+            })?;
 
             let db_state = handle.state::<DbConnection>();
             *db_state
                 .db
                 .lock()
-                .map_err(|e| format!("Failed to acquire DbConnection lock: {}", e))? = Some(conn);
+                .map_err(|e| format!("Failed to acquire DbConnection lock: {}", e))? =
+                Some(database);
 
-            info!("DuckDB initialized successfully at: {:?}", db_path);
+            info!(
+                "{:?} database initialized successfully at: {:?}",
+                backend, db_path
+            );
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
-            scan_directory,
-            scan_current_directory,
-            assign_tag_to_paths
+            commands::scan_directory,
+            commands::scan_current_directory,
+            commands::search_files,
+            commands::filter_entries,
+            commands::assign_tag_to_paths,
+            commands::set_path_matching,
+            commands::list_path_matching,
+            commands::sync_xattr_tags,
+            commands::create_saved_search,
+            commands::list_saved_searches,
+            commands::update_saved_search,
+            commands::delete_saved_search,
+            commands::evaluate_saved_search,
+            commands::run_readonly_query,
+            commands::export_tags,
+            commands::import_tags,
+            commands::import_tmsu_database,
+            commands::write_xmp_sidecars,
+            commands::set_tag_manifests,
+            commands::list_tag_manifest_roots,
+            commands::export_tag_manifests,
+            commands::import_tag_manifests,
+            commands::get_settings,
+            commands::update_settings,
+            commands::list_storage_backends
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
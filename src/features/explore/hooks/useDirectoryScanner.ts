@@ -1,6 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
 import { useCallback, useMemo, useState } from "react";
-import type { DirectoryNode } from "@/types";
+import type { DirectoryNode, ScanResult, SkippedEntry } from "@/types";
 
 type ScanRequest = {
   path?: string | null;
@@ -18,6 +18,7 @@ export interface UseDirectoryScannerOptions {
 
 export interface UseDirectoryScannerValue {
   directoryTree: DirectoryNode | null;
+  skippedEntries: SkippedEntry[];
   currentPathSegments: string[];
   loading: boolean;
   error: string | null;
@@ -60,6 +61,7 @@ export function useDirectoryScanner(
   const [directoryTree, setDirectoryTree] = useState<DirectoryNode | null>(
     null,
   );
+  const [skippedEntries, setSkippedEntries] = useState<SkippedEntry[]>([]);
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [lastTarget, setLastTarget] = useState<ResolvedScanTarget>({
@@ -76,15 +78,16 @@ export function useDirectoryScanner(
       setLastTarget(nextTarget);
 
       try {
-        const node = nextTarget.path
-          ? await invoke<DirectoryNode>("scan_directory", {
+        const result = nextTarget.path
+          ? await invoke<ScanResult>("scan_directory", {
               path: nextTarget.path,
               depth: nextTarget.depth,
             })
-          : await invoke<DirectoryNode>("scan_current_directory");
+          : await invoke<ScanResult>("scan_current_directory");
 
-        setDirectoryTree(node);
-        return node;
+        setDirectoryTree(result.tree);
+        setSkippedEntries(result.skipped);
+        return result.tree;
       } catch (unknownError) {
         const message =
           unknownError instanceof Error
@@ -92,6 +95,7 @@ export function useDirectoryScanner(
             : String(unknownError);
 
         setDirectoryTree(null);
+        setSkippedEntries([]);
         setError(message);
         return null;
       } finally {
@@ -125,6 +129,7 @@ export function useDirectoryScanner(
   const value = useMemo<UseDirectoryScannerValue>(
     () => ({
       directoryTree,
+      skippedEntries,
       currentPathSegments,
       loading,
       error,
@@ -135,6 +140,7 @@ export function useDirectoryScanner(
     }),
     [
       directoryTree,
+      skippedEntries,
       currentPathSegments,
       loading,
       error,
@@ -1,17 +1,150 @@
+export type Freshness = "live" | "cached";
+
+export interface DirectoryStats {
+  total_size: number;
+  file_count: number;
+  directory_count: number;
+  is_partial: boolean;
+}
+
+export type FileType =
+  | "regular"
+  | "directory"
+  | "symlink"
+  | "fifo"
+  | "socket"
+  | "block_device"
+  | "char_device"
+  | "unknown";
+
+export interface ExtendedMetadata {
+  file_type: FileType;
+  created: string | null;
+  accessed: string | null;
+  mode: number | null;
+  uid: number | null;
+  gid: number | null;
+  owner: string | null;
+  group: string | null;
+  inode: number | null;
+  device: number | null;
+  hard_links: number | null;
+}
+
 export interface FileInfo {
   path: string;
   is_directory: boolean;
   is_symlink: boolean;
+  symlink_target: string | null;
+  is_broken_symlink: boolean;
   size: number;
   hierarchy: string[];
   modified: string | null;
   own_tags: string[];
   inherited_tags: string[];
   windows_tags: string[];
+  platform_tags: string[];
+  sidecar_tags: string[];
+  manifest_tags: string[];
+  ignored_children: number;
+  freshness: Freshness;
+  stats: DirectoryStats | null;
+  extended: ExtendedMetadata;
 }
 
 export interface DirectoryNode {
   name: string;
   info: FileInfo;
   children: DirectoryNode[];
+  truncated: boolean;
+}
+
+export type SkipKind =
+  | "permission_denied"
+  | "not_found"
+  | "unsupported"
+  | "other";
+
+export interface SkippedEntry {
+  path: string;
+  kind: SkipKind;
+  message: string;
+}
+
+export interface ScanResult {
+  tree: DirectoryNode;
+  skipped: SkippedEntry[];
+}
+
+export type EntryKindFilter = "all" | "files_only" | "directories_only";
+
+export interface ScanOptions {
+  include?: string[];
+  exclude?: string[];
+  show_hidden?: boolean;
+  entry_kind?: EntryKindFilter;
+  min_size?: number | null;
+  max_size?: number | null;
+  modified_after?: string | null;
+  modified_before?: string | null;
+  respect_ignore_files?: boolean;
+  follow_symlinks?: boolean;
+  use_cache?: boolean;
+  full_subtree_stats?: boolean;
+  max_entries?: number | null;
+  max_entries_per_directory?: number | null;
+  max_duration_ms?: number | null;
+}
+
+export type SortKey = "name" | "size" | "modified" | "own_tag_count";
+
+export type SortDirection = "ascending" | "descending";
+
+export type NameComparison = "ordinal" | "case_insensitive" | "natural";
+
+export interface SortOptions {
+  key?: SortKey;
+  direction?: SortDirection;
+  name_comparison?: NameComparison;
+  directories_first?: boolean;
+}
+
+export type SearchMode = "substring" | "glob" | "fuzzy";
+
+export interface SearchOptions {
+  mode?: SearchMode;
+  full_path?: boolean;
+  limit?: number | null;
+}
+
+export interface FacetFilter {
+  root?: string | null;
+  tags?: string | null;
+  extensions?: string[];
+  min_size?: number | null;
+  max_size?: number | null;
+  modified_after?: string | null;
+  modified_before?: string | null;
+  entry_kind?: EntryKindFilter;
+  symlinks?: boolean | null;
+  limit?: number | null;
+}
+
+export interface FacetCount {
+  value: string;
+  count: number;
+}
+
+export interface Facets {
+  tags: FacetCount[];
+  extensions: FacetCount[];
+  files: number;
+  directories: number;
+  symlinks: number;
+}
+
+export interface FilterResult {
+  entries: FileInfo[];
+  total: number;
+  facets: Facets;
 }
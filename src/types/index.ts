@@ -1 +1,54 @@
-export type { DirectoryNode, FileInfo } from "./file";
+export type {
+  DirectoryNode,
+  DirectoryStats,
+  EntryKindFilter,
+  ExtendedMetadata,
+  FacetCount,
+  FacetFilter,
+  Facets,
+  FileInfo,
+  FileType,
+  FilterResult,
+  Freshness,
+  NameComparison,
+  ScanOptions,
+  ScanResult,
+  SearchMode,
+  SearchOptions,
+  SkipKind,
+  SkippedEntry,
+  SortDirection,
+  SortKey,
+  SortOptions,
+} from "./file";
+export type {
+  BackendFeature,
+  Settings,
+  StorageBackend,
+  StorageBackendInfo,
+} from "./settings";
+export type {
+  ConflictPolicy,
+  ExportFilter,
+  ExportSummary,
+  ImportStrategy,
+  ImportSummary,
+  InvalidRow,
+  ManifestReport,
+  ManifestRoot,
+  MissingFile,
+  PathMatching,
+  PathMatchingRoot,
+  PrefixRewrite,
+  QueryColumn,
+  QueryLimits,
+  QueryResult,
+  SavedSearch,
+  SidecarReport,
+  SidecarWrite,
+  SyncChange,
+  SyncDirection,
+  SyncReport,
+  TagFileFormat,
+  TmsuImportReport,
+} from "./tags";
@@ -0,0 +1,24 @@
+export type StorageBackend = "duckdb" | "sqlite";
+
+// Saved by `update_settings`. A new storage backend takes over at once,
+// holding a copy of the tags.
+export interface Settings {
+  storage_backend: StorageBackend;
+}
+
+// App features that need DuckDB
+export type BackendFeature =
+  | "scan_cache"
+  | "search"
+  | "facet_filters"
+  | "saved_searches"
+  | "sql_console"
+  | "tag_import_export"
+  | "tag_manifests";
+
+// From `list_storage_backends`, for showing what a switch gives up
+export interface StorageBackendInfo {
+  backend: StorageBackend;
+  file_name: string;
+  missing_features: BackendFeature[];
+}
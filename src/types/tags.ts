@@ -0,0 +1,118 @@
+import type { SkippedEntry } from "./file";
+
+export type ConflictPolicy = "database_wins" | "xattr_wins" | "union";
+
+export type SyncDirection = "to_xattr" | "to_database" | "both";
+
+export interface SyncChange {
+  path: string;
+  database_tags: string[];
+  xattr_tags: string[];
+  resolved_tags: string[];
+  direction: SyncDirection;
+}
+
+export interface SyncReport {
+  dry_run: boolean;
+  policy: ConflictPolicy;
+  changes: SyncChange[];
+  skipped: SkippedEntry[];
+}
+
+export interface PathMatching {
+  case_insensitive: boolean;
+  unicode_normalized: boolean;
+}
+
+export interface PathMatchingRoot {
+  root: string;
+  matching: PathMatching;
+}
+
+// A named tag query such as `finance AND status=open AND NOT archived`
+export interface SavedSearch {
+  name: string;
+  query: string;
+}
+
+export interface QueryLimits {
+  max_rows?: number | null;
+  timeout_ms?: number | null;
+}
+
+export interface QueryColumn {
+  name: string;
+  type: string;
+}
+
+// Result of `run_readonly_query`; non-scalar values arrive as text
+export interface QueryResult {
+  columns: QueryColumn[];
+  rows: (string | number | boolean | null)[][];
+  truncated: boolean;
+  elapsed_ms: number;
+}
+
+export type TagFileFormat = "csv" | "json_lines" | "parquet";
+
+export interface ExportFilter {
+  root?: string | null;
+  tag?: string | null;
+}
+
+export interface ExportSummary {
+  destination: string;
+  format: TagFileFormat;
+  rows: number;
+}
+
+export type ImportStrategy = "skip" | "overwrite" | "fail";
+
+export interface PrefixRewrite {
+  from: string;
+  to: string;
+}
+
+export interface InvalidRow {
+  row: number;
+  reason: string;
+}
+
+export interface ImportSummary {
+  inserted: number;
+  overwritten: number;
+  skipped: number;
+  invalid: number;
+  invalid_rows: InvalidRow[];
+}
+
+export interface MissingFile {
+  path: string;
+  tags: string[];
+}
+
+// `ImportSummary` counts plus the TMSU files that no longer exist
+export interface TmsuImportReport extends ImportSummary {
+  missing: MissingFile[];
+}
+
+export interface SidecarWrite {
+  path: string;
+  sidecar: string;
+  tags: string[];
+}
+
+export interface SidecarReport {
+  written: SidecarWrite[];
+  skipped: SkippedEntry[];
+}
+
+export interface ManifestRoot {
+  root: string;
+}
+
+export interface ManifestReport {
+  written: string[];
+  removed: string[];
+  skipped: SkippedEntry[];
+}